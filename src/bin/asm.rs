@@ -0,0 +1,69 @@
+extern crate nesru;
+
+use nesru::cpu::asm;
+use nesru::cpu::cpu::Variant;
+
+use std::fs;
+use std::process;
+
+/// Size of the PRG ROM of the NROM image, mapped to $8000-$FFFF.
+const PRG_SIZE: usize = 0x8000;
+const CHR_SIZE: usize = 0x2000;
+
+fn usage() -> ! {
+    eprintln!("usage: asm [--65c02 | --nmos] [--nes] <source.s> <output>");
+    eprintln!();
+    eprintln!("Assembles the source into a raw binary, which covers the lowest to the");
+    eprintln!("highest assembled address. With --nes an NROM iNES file is written");
+    eprintln!("instead, its 32 kB PRG ROM holds the code at $8000-$FFFF and the CHR");
+    eprintln!("ROM is empty. The vectors at $FFFA-$FFFF have to be set by the source.");
+    process::exit(2);
+}
+
+/// An iNES file with two PRG banks, one CHR bank, mapper 0 and horizontal
+/// mirroring.
+fn nrom(program: &asm::Program) -> Vec<u8> {
+    let mut image = vec![b'N', b'E', b'S', 0x1A, 2, 1, 0, 0];
+    image.resize(16, 0);
+    image.extend(program.image(0x8000, PRG_SIZE, 0xFF));
+    image.resize(image.len() + CHR_SIZE, 0);
+    image
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let variant = if args.iter().any(|a| a == "--65c02") {
+        Variant::Cmos65C02
+    } else if args.iter().any(|a| a == "--nmos") {
+        Variant::Nmos
+    } else {
+        Variant::Ricoh2A03
+    };
+    let nes = args.iter().any(|a| a == "--nes");
+    args.retain(|a| !a.starts_with("--"));
+    if args.len() != 2 {
+        usage();
+    }
+
+    let source = fs::read_to_string(&args[0]).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", args[0], e);
+        process::exit(1);
+    });
+
+    let program = asm::assemble(variant, &source).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[0], e);
+        process::exit(1);
+    });
+
+    let output = if nes {
+        nrom(&program)
+    } else {
+        program.flat(0x00).1
+    };
+
+    if let Err(e) = fs::write(&args[1], output) {
+        eprintln!("cannot write {}: {}", args[1], e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,80 @@
+extern crate nesru;
+
+use nesru::cpu::cpu::Variant;
+use nesru::cpu::disasm;
+use nesru::rom;
+
+use std::fs;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("usage: disasm [--65c02 | --nmos] <rom.nes> [bank]");
+    eprintln!("       disasm [--65c02 | --nmos] --raw <file> <origin>");
+    eprintln!();
+    eprintln!("Disassembles a 16 kB PRG bank of an iNES image, the first by default.");
+    eprintln!("The last bank is assumed at $C000, all others at $8000. With --raw the");
+    eprintln!("whole file is disassembled at the hexadecimal origin.");
+    process::exit(2);
+}
+
+fn parse_hex(s: &str) -> u16 {
+    u16::from_str_radix(s.trim_start_matches('$'), 16).unwrap_or_else(|_| usage())
+}
+
+fn read(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let variant = if args.iter().any(|a| a == "--65c02") {
+        Variant::Cmos65C02
+    } else if args.iter().any(|a| a == "--nmos") {
+        Variant::Nmos
+    } else {
+        Variant::Ricoh2A03
+    };
+    let raw = args.iter().any(|a| a == "--raw");
+    args.retain(|a| !a.starts_with("--"));
+
+    let (data, origin) = if raw {
+        if args.len() != 2 {
+            usage();
+        }
+        (read(&args[0]), parse_hex(&args[1]))
+    } else {
+        if args.is_empty() || args.len() > 2 {
+            usage();
+        }
+        let rom = rom::from_ines(&read(&args[0])).unwrap_or_else(|e| {
+            eprintln!("not an iNES image: {:?}", e);
+            process::exit(1);
+        });
+
+        let banks = rom.prg().len() / rom::PRG_BANK_SIZE;
+        let bank = args.get(1).map_or(0, |b| b.parse().unwrap_or_else(|_| usage()));
+        if bank >= banks {
+            eprintln!("bank {} out of range, the image has {} PRG banks", bank, banks);
+            process::exit(1);
+        }
+
+        let start = bank * rom::PRG_BANK_SIZE;
+        let origin = if bank + 1 == banks { 0xC000 } else { 0x8000 };
+        (rom.prg()[start..start + rom::PRG_BANK_SIZE].to_vec(), origin)
+    };
+
+    for instruction in disasm::disassemble(variant, &data, origin) {
+        let bytes: Vec<String> = instruction.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        println!(
+            "{:04X}  {:<8}  {}{}",
+            instruction.address,
+            bytes.join(" "),
+            if instruction.official { ' ' } else { '*' },
+            instruction
+        );
+    }
+}
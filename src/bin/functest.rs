@@ -0,0 +1,133 @@
+extern crate nesru;
+
+use nesru::cpu::bus::{Bus, Ram};
+use nesru::cpu::cpu::{Variant, CPU};
+
+use std::fs;
+use std::process;
+
+/// Address of the success trap of the functional test as assembled by
+/// default, see `report_success` in 6502_functional_test.a65.
+const FUNCTIONAL_SUCCESS: u16 = 0x3469;
+
+/// Enough for the functional test, which takes about 96 million cycles.
+const DEFAULT_CYCLES: usize = 200_000_000;
+
+fn usage() -> ! {
+    eprintln!("usage: functest [--nmos | --65c02 | --2a03] [--success <address>]");
+    eprintln!("                [--error <address>] [--cycles <limit>]");
+    eprintln!("                <image.bin> [load address] [start address]");
+    eprintln!();
+    eprintln!("Runs a raw 6502 test image like Klaus Dormann's functional and decimal");
+    eprintln!("mode tests on 64 kB of flat RAM, without any NES hardware. The image is");
+    eprintln!("loaded at $0000 and started at $0400 by default. The test ends when an");
+    eprintln!("instruction jumps to itself; it passed if that trap is at the success");
+    eprintln!("address, $3469 by default, and the byte at the error address, if given,");
+    eprintln!("is zero. The decimal mode test reports its result at $000B. The NMOS");
+    eprintln!("6502 is emulated by default.");
+    process::exit(2);
+}
+
+fn parse_hex(s: &str) -> u16 {
+    u16::from_str_radix(s.trim_start_matches('$'), 16).unwrap_or_else(|_| usage())
+}
+
+fn main() {
+    let mut variant = Variant::Nmos;
+    let mut success = Some(FUNCTIONAL_SUCCESS);
+    let mut error = None;
+    let mut limit = DEFAULT_CYCLES;
+    let mut positional = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--nmos" => variant = Variant::Nmos,
+            "--65c02" => variant = Variant::Cmos65C02,
+            "--2a03" => variant = Variant::Ricoh2A03,
+            "--success" => success = Some(parse_hex(&value())),
+            "--error" => {
+                error = Some(parse_hex(&value()));
+                // the decimal mode test has no success trap
+                success = None;
+            }
+            "--cycles" => limit = value().parse().unwrap_or_else(|_| usage()),
+            a if a.starts_with("--") => usage(),
+            _ => positional.push(arg),
+        }
+    }
+    if positional.is_empty() || positional.len() > 3 {
+        usage();
+    }
+
+    let image = fs::read(&positional[0]).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", positional[0], e);
+        process::exit(1);
+    });
+    let load = positional.get(1).map_or(0x0000, |a| parse_hex(a));
+    let start = positional.get(2).map_or(0x0400, |a| parse_hex(a));
+
+    let mut ram = Ram::new();
+    ram.load(load, &image);
+
+    let mut cpu = CPU::new();
+    cpu.set_variant(variant);
+    let mut state = cpu.state();
+    state.set_pc(start);
+    state.set_sp(0xFF);
+    cpu.set_state(&state);
+
+    let mut instructions: usize = 0;
+    let trap = loop {
+        let before = cpu.state();
+        let pc = before.pc();
+        match cpu.step(&mut ram) {
+            Ok(0) => {
+                println!("processor stopped at ${:04X}", pc);
+                break pc;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                // the registers before the failing instruction, the trace
+                // is only formatted here as it is slow
+                cpu.set_state(&before);
+                println!("{}", cpu.trace_line(&ram));
+                println!("{} after {} instructions", e, instructions);
+                process::exit(1);
+            }
+        }
+        instructions += 1;
+
+        if cpu.state().pc() == pc {
+            break pc;
+        }
+        if cpu.state().cycles() >= limit {
+            println!("{}", cpu.trace_line(&ram));
+            println!("no trap within {} cycles", limit);
+            process::exit(1);
+        }
+    };
+
+    let cycles = cpu.state().cycles();
+    let trapped = success.is_none_or(|s| s == trap);
+    let result = error.map(|addr| ram.peek(addr).unwrap_or(0xFF));
+    if trapped && result.is_none_or(|r| r == 0) {
+        println!(
+            "passed: trapped at ${:04X} after {} instructions and {} cycles",
+            trap, instructions, cycles
+        );
+        return;
+    }
+
+    println!("{}", cpu.trace_line(&ram));
+    print!(
+        "failed: trapped at ${:04X} after {} instructions and {} cycles",
+        trap, instructions, cycles
+    );
+    match (error, result) {
+        (Some(addr), Some(r)) if r != 0 => println!(", ${:02X} at ${:04X}", r, addr),
+        _ => println!(),
+    }
+    process::exit(1);
+}
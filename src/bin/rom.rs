@@ -1,8 +1,6 @@
 extern crate nesru;
 use nesru::rom;
 
-use std::env::args;
-
 
 fn main() {
     let mut args = std::env::args();
@@ -0,0 +1,135 @@
+extern crate nesru;
+
+use nesru::cpu::bus::Ram;
+use nesru::cpu::cpu::{CpuState, CPU};
+use nesru::rom;
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::process;
+
+/// Number of matching lines shown before the first difference.
+const CONTEXT: usize = 5;
+
+fn usage() -> ! {
+    eprintln!("usage: tracediff [--registers] <rom.nes> <reference.log> [start address]");
+    eprintln!();
+    eprintln!("Runs an NROM image and compares the trace of every instruction with a");
+    eprintln!("nestest style reference log, stopping at the first difference. The");
+    eprintln!("start address defaults to $C000, the automated mode of nestest.");
+    eprintln!("With --registers only the address, the instruction bytes, the");
+    eprintln!("registers and the cycles are compared, not the disassembly.");
+    process::exit(2);
+}
+
+/// Map the PRG ROM of an NROM image to $8000-$FFFF, a single 16 kB bank is
+/// mirrored at $C000.
+fn load_nrom(raw: &[u8]) -> Ram {
+    let header = rom::parse_ines_header(raw).unwrap_or_else(|e| {
+        eprintln!("not an iNES image: {:?}", e);
+        process::exit(1);
+    });
+
+    let banks = header.prg_banks();
+    let start = header.prg_offset();
+    let prg = raw.get(start..start + banks * rom::PRG_BANK_SIZE).unwrap_or_else(|| {
+        eprintln!("the image is shorter than its {} PRG banks", banks);
+        process::exit(1);
+    });
+
+    let mut ram = Ram::new();
+    ram.load(0x8000, prg);
+    if banks == 1 {
+        ram.load(0xC000, prg);
+    }
+    ram
+}
+
+/// Drop the disassembly, which differs most between emulators, and keep the
+/// address, the instruction bytes and everything from the registers on.
+fn registers_only(line: &str) -> String {
+    match (line.get(..15), line.find("A:")) {
+        (Some(head), Some(i)) => format!("{} {}", head.trim_end(), &line[i..]),
+        _ => line.to_string(),
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let registers = args.iter().any(|a| a == "--registers");
+    args.retain(|a| a != "--registers");
+    if args.len() < 2 || args.len() > 3 {
+        usage();
+    }
+
+    let start = match args.get(2) {
+        Some(s) => u16::from_str_radix(s.trim_start_matches('$'), 16).unwrap_or_else(|_| usage()),
+        None => 0xC000,
+    };
+
+    let image = fs::read(&args[0]).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", args[0], e);
+        process::exit(1);
+    });
+    let mut ram = load_nrom(&image);
+
+    let reference = File::open(&args[1]).unwrap_or_else(|e| {
+        eprintln!("cannot open {}: {}", args[1], e);
+        process::exit(1);
+    });
+
+    // the state after the reset sequence nestest logs start with
+    let mut state = CpuState::default();
+    state.set_pc(start);
+    state.set_sp(0xFD);
+    state.set_p(0x24);
+    state.set_cycles(7);
+
+    let mut cpu = CPU::new();
+    cpu.set_state(&state);
+
+    let compare = |line: &str| {
+        if registers {
+            registers_only(line)
+        } else {
+            line.trim_end().to_string()
+        }
+    };
+
+    let mut context = VecDeque::with_capacity(CONTEXT);
+    // a log with invalid UTF-8 still compares, the invalid bytes differ
+    for (n, expected) in BufReader::new(reference).split(b'\n').enumerate() {
+        let expected = expected.unwrap_or_else(|e| {
+            eprintln!("cannot read {}: {}", args[1], e);
+            process::exit(1);
+        });
+        let expected = String::from_utf8_lossy(&expected);
+        let expected = expected.trim_end_matches('\r');
+        let actual = cpu.trace_line(&ram);
+
+        if compare(expected) != compare(&actual) || cpu.is_halted() {
+            for line in &context {
+                println!("   {}", line);
+            }
+            println!("-  {}", expected);
+            println!("+  {}", actual);
+            println!("first difference in line {}", n + 1);
+            process::exit(1);
+        }
+
+        if context.len() == CONTEXT {
+            context.pop_front();
+        }
+        context.push_back(actual);
+        if let Err(e) = cpu.step(&mut ram) {
+            for line in &context {
+                println!("   {}", line);
+            }
+            println!("{} in line {}", e, n + 1);
+            process::exit(1);
+        }
+    }
+
+    println!("trace matches the reference");
+}
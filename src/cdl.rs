@@ -0,0 +1,348 @@
+//! Code/data logger in the format of the FCEUX `.cdl` files.
+//!
+//! The log has a byte of flags for every byte of the PRG ROM followed by one
+//! for every byte of the CHR ROM. For the PRG ROM:
+//!
+//! ```text
+//! xPdcAADC
+//! C  = executed as code, the opcode or an operand
+//! D  = read as data
+//! AA = the 8 kB window of the CPU it was last accessed through,
+//!      00 = $8000-$9FFF, 01 = $A000-$BFFF, 10 = $C000-$DFFF, 11 = $E000-$FFFF
+//! c  = target of an indirect jump, JMP ($nnnn)
+//! d  = read through a pointer, e.g. LDA ($nn),Y
+//! P  = fetched as PCM audio data by the DMC
+//! ```
+//!
+//! For the CHR ROM bit 0 marks bytes drawn by the PPU and bit 1 those read
+//! through $2007.
+//!
+//! Flags are only ever added, so logs of several sessions can be merged by
+//! loading the previous log before running and saving it afterwards.
+
+use cpu::cpu::{AddressingMode, Variant};
+use cpu::hook::{Access, Hook, InstructionEvent};
+use cpu::opinfo;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+/// the window bits, see `window_bits`
+pub const WINDOW: u8 = 0x0C;
+pub const INDIRECT_CODE: u8 = 0x10;
+pub const INDIRECT_DATA: u8 = 0x20;
+pub const PCM: u8 = 0x40;
+
+pub const CHR_RENDERED: u8 = 0x01;
+pub const CHR_READ: u8 = 0x02;
+
+/// The `AA` bits of a PRG byte accessed at the CPU address `addr`.
+pub fn window_bits(addr: u16) -> u8 {
+    ((addr & 0x6000) >> 11) as u8
+}
+
+/// Maps a CPU address to an offset in the PRG ROM, `None` for addresses
+/// outside of the cartridge ROM.
+pub type PrgMapping = Box<dyn Fn(u16) -> Option<usize>>;
+
+/// The flags of every PRG and CHR ROM byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeDataLog {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    /// An empty log for a cartridge with the given ROM sizes in bytes.
+    pub fn new(prg_size: usize, chr_size: usize) -> CodeDataLog {
+        CodeDataLog {
+            prg: vec![0; prg_size],
+            chr: vec![0; chr_size],
+        }
+    }
+
+    /// Read a log from the contents of a `.cdl` file. The file does not
+    /// store the size of the PRG ROM, so it has to be given.
+    pub fn from_bytes(data: &[u8], prg_size: usize) -> io::Result<CodeDataLog> {
+        if data.len() < prg_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "code/data log of {} bytes is smaller than the PRG ROM of {} bytes",
+                    data.len(),
+                    prg_size
+                ),
+            ));
+        }
+
+        Ok(CodeDataLog {
+            prg: data[..prg_size].to_vec(),
+            chr: data[prg_size..].to_vec(),
+        })
+    }
+
+    /// The contents of a `.cdl` file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.prg.clone();
+        data.extend_from_slice(&self.chr);
+        data
+    }
+
+    /// Load a log saved by `save` or by FCEUX. A missing file gives an empty
+    /// log, so a session can always start with `load` and end with `save`.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        prg_size: usize,
+        chr_size: usize,
+    ) -> io::Result<CodeDataLog> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(CodeDataLog::new(prg_size, chr_size))
+            }
+            Err(e) => return Err(e),
+        };
+
+        let log = CodeDataLog::from_bytes(&data, prg_size)?;
+        if log.chr.len() != chr_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "code/data log has {} CHR bytes, the cartridge {}",
+                    log.chr.len(),
+                    chr_size
+                ),
+            ));
+        }
+        Ok(log)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Add the flags of `other`, a log of the same cartridge.
+    pub fn merge(&mut self, other: &CodeDataLog) -> io::Result<()> {
+        if self.prg.len() != other.prg.len() || self.chr.len() != other.chr.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "code/data logs of different cartridges",
+            ));
+        }
+
+        for (flags, other) in self.prg.iter_mut().zip(&other.prg) {
+            *flags |= *other;
+        }
+        for (flags, other) in self.chr.iter_mut().zip(&other.chr) {
+            *flags |= *other;
+        }
+        Ok(())
+    }
+
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    /// Add `flags` to the PRG byte at `offset`, accessed at the CPU address
+    /// `addr`, which replaces the window bits.
+    pub fn log_prg(&mut self, offset: usize, addr: u16, flags: u8) {
+        if let Some(byte) = self.prg.get_mut(offset) {
+            *byte = (*byte & !WINDOW) | window_bits(addr) | flags;
+        }
+    }
+
+    /// Add `flags` to the CHR byte at `offset`. Called by the PPU.
+    pub fn log_chr(&mut self, offset: usize, flags: u8) {
+        if let Some(byte) = self.chr.get_mut(offset) {
+            *byte |= flags;
+        }
+    }
+}
+
+/// A `Hook` filling a `CodeDataLog` from the accesses of the CPU.
+///
+/// Register it through an `Rc<RefCell<_>>` to get at the log afterwards.
+/// The CHR ROM is logged by the PPU with `CodeDataLog::log_chr`.
+pub struct CodeDataLogger {
+    log: CodeDataLog,
+    mapping: PrgMapping,
+    variant: Variant,
+    /// addressing mode of the instruction being executed
+    mode: Option<AddressingMode>,
+}
+
+impl CodeDataLogger {
+    /// Log the PRG ROM of an NROM cartridge, mapped to $8000-$FFFF and
+    /// mirrored if it is smaller than 32 kB.
+    pub fn new(variant: Variant, log: CodeDataLog) -> CodeDataLogger {
+        let size = log.prg.len();
+        let mapping = move |addr: u16| {
+            if addr >= 0x8000 && size > 0 {
+                Some((addr as usize - 0x8000) % size)
+            } else {
+                None
+            }
+        };
+        CodeDataLogger::with_mapping(variant, log, Box::new(mapping))
+    }
+
+    /// Log through the bank switching of a mapper.
+    pub fn with_mapping(variant: Variant, log: CodeDataLog, mapping: PrgMapping) -> CodeDataLogger {
+        CodeDataLogger {
+            log,
+            mapping,
+            variant,
+            mode: None,
+        }
+    }
+
+    pub fn log(&self) -> &CodeDataLog {
+        &self.log
+    }
+
+    pub fn log_mut(&mut self) -> &mut CodeDataLog {
+        &mut self.log
+    }
+
+    pub fn into_log(self) -> CodeDataLog {
+        self.log
+    }
+
+    fn log_prg(&mut self, addr: u16, flags: u8) {
+        if let Some(offset) = (self.mapping)(addr) {
+            self.log.log_prg(offset, addr, flags);
+        }
+    }
+}
+
+impl Hook for CodeDataLogger {
+    fn before_instruction(&mut self, event: &InstructionEvent) {
+        self.mode = Some(opinfo::op_info(self.variant)[event.opcode as usize].mode);
+    }
+
+    fn after_instruction(&mut self, event: &InstructionEvent) {
+        match self.mode.take() {
+            Some(AddressingMode::Indirect) | Some(AddressingMode::AbsoluteIndexedIndirect) => {
+                self.log_prg(event.state.pc(), INDIRECT_CODE);
+            }
+            _ => {}
+        }
+    }
+
+    fn access(&mut self, addr: u16, _value: u8, kind: Access) {
+        let flags = match (kind, self.mode) {
+            (Access::Execute, _) | (Access::Operand, _) => CODE,
+            // the pointers of these modes are in the zero page, so a read of
+            // the ROM is the read through the pointer
+            (Access::Read, Some(AddressingMode::IndexedIndirect))
+            | (Access::Read, Some(AddressingMode::IndirectIndexed))
+            | (Access::Read, Some(AddressingMode::ZeroPageIndirect)) => DATA | INDIRECT_DATA,
+            (Access::Read, _) => DATA,
+            _ => return,
+        };
+        self.log_prg(addr, flags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::asm;
+    use cpu::bus::Ram;
+    use cpu::cpu::CPU;
+    use std::cell::RefCell;
+    use std::env;
+    use std::rc::Rc;
+
+    #[test]
+    fn log_program() {
+        let program = asm::assemble(
+            Variant::Ricoh2A03,
+            "
+                    .org $C000
+            start:  lda table
+                    ldy #1
+                    lda ($10),y
+                    jmp (vector)
+            table:  .byte 1, 2, 3
+            vector: .word target
+            unused: .byte $FF
+            target: jmp target
+            ",
+        )
+        .unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+        mem.load(0x0010, &[0x0A, 0xC0]);
+
+        let logger = Rc::new(RefCell::new(CodeDataLogger::new(
+            Variant::Ricoh2A03,
+            CodeDataLog::new(0x4000, 0x2000),
+        )));
+        let mut cpu = CPU::new();
+        let mut state = cpu.state();
+        state.set_pc(0xC000);
+        cpu.set_state(&state);
+        cpu.add_hook(Box::new(logger.clone()));
+        for _ in 0..5 {
+            cpu.step(&mut mem).unwrap();
+        }
+
+        let window = window_bits(0xC000);
+        assert_eq!(window, 0x08);
+        let log = logger.borrow();
+        let prg = log.log().prg();
+        // lda table, ldy #1, lda ($10),y, jmp (vector)
+        assert_eq!(&prg[..10], &[CODE | window; 10]);
+        assert_eq!(prg[0x0A], DATA | window);
+        assert_eq!(prg[0x0B], DATA | INDIRECT_DATA | window);
+        assert_eq!(prg[0x0C], 0);
+        assert_eq!(&prg[0x0D..0x0F], &[DATA | window; 2]);
+        assert_eq!(prg[0x0F], 0);
+        assert_eq!(prg[0x10], CODE | INDIRECT_CODE | window);
+        assert_eq!(&prg[0x11..0x13], &[CODE | window; 2]);
+        assert!(log.log().chr().iter().all(|&flags| flags == 0));
+    }
+
+    #[test]
+    fn bytes_and_merge() {
+        let mut log = CodeDataLog::new(4, 2);
+        log.log_prg(0, 0x8000, CODE);
+        log.log_prg(1, 0xE001, DATA);
+        log.log_chr(1, CHR_RENDERED);
+        assert_eq!(log.to_bytes(), vec![0x01, 0x0E, 0, 0, 0, 0x01]);
+
+        let mut other = CodeDataLog::from_bytes(&[0, 0, 0x21, 0, 0x02, 0], 4).unwrap();
+        other.merge(&log).unwrap();
+        assert_eq!(other.to_bytes(), vec![0x01, 0x0E, 0x21, 0, 0x02, 0x01]);
+
+        assert!(other.merge(&CodeDataLog::new(4, 0)).is_err());
+        assert!(CodeDataLog::from_bytes(&[0; 3], 4).is_err());
+    }
+
+    #[test]
+    fn load_and_save() {
+        let path = env::temp_dir().join(format!("nesru-cdl-{}.cdl", ::std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut log = CodeDataLog::load(&path, 4, 2).unwrap();
+        assert_eq!(log, CodeDataLog::new(4, 2));
+        log.log_prg(3, 0xA003, CODE);
+        log.save(&path).unwrap();
+
+        let mut log = CodeDataLog::load(&path, 4, 2).unwrap();
+        assert_eq!(log.prg()[3], CODE | 0x04);
+        log.log_prg(2, 0xA002, DATA);
+        log.save(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![0, 0, 0x06, 0x05, 0, 0]);
+
+        assert!(CodeDataLog::load(&path, 4, 4).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,941 @@
+//! A small assembler in the style of ca65, meant for tests and quick
+//! experiments.
+//!
+//! ```text
+//! PPUCTRL = $2000
+//!
+//!         .org $C000
+//! reset:  ldx #$00
+//! @loop:  lda message,x       ; @labels are local to the last label
+//!         beq done
+//!         sta $0200,x
+//!         inx
+//!         bne @loop
+//! done:   jmp done
+//!
+//! message:
+//!         .byte "HELLO", 0
+//!         .word reset, >PPUCTRL, * + 2
+//! ```
+//!
+//! Supported are labels, local `@labels`, constants (`NAME = expr`), the
+//! directives `.org`, `.byte`, `.word` and `.res`, and expressions with
+//! decimal, `$hex`, `%binary` and `'c'` literals, `*` for the current
+//! address, the unary operators `-`, `~`, `<` (low byte) and `>` (high byte)
+//! and the binary operators `* / + - << >> & ^ |` with C precedence.
+//!
+//! Zero page addressing is chosen whenever the operand is known to fit in a
+//! byte at the time it is first seen. `a:` and `z:` in front of an operand
+//! force absolute and zero page addressing.
+
+use cpu::bus::Ram;
+use cpu::cpu::{AddressingMode, Variant};
+use cpu::opinfo;
+use std::collections::HashMap;
+use std::fmt;
+
+use cpu::cpu::AddressingMode::*;
+
+/// Error with the line of the source it occurred in, counted from 1.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Consecutive bytes starting at `address`. Every `.org` starts a new
+/// segment.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// The output of the assembler.
+#[derive(Debug)]
+pub struct Program {
+    pub segments: Vec<Segment>,
+    symbols: HashMap<String, i64>,
+}
+
+impl Program {
+    /// Value of a label or constant. Local labels are written as
+    /// `label@local`.
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).map(|v| *v as u16)
+    }
+
+    /// Copy all segments into `ram`.
+    pub fn load_into(&self, ram: &mut Ram) {
+        for segment in &self.segments {
+            ram.load(segment.address, &segment.bytes);
+        }
+    }
+
+    /// The `len` bytes from `start` on, with `fill` where no code was
+    /// assembled. Bytes outside of the range are dropped.
+    pub fn image(&self, start: u16, len: usize, fill: u8) -> Vec<u8> {
+        let mut image = vec![fill; len];
+        for segment in &self.segments {
+            for (i, b) in segment.bytes.iter().enumerate() {
+                let offset = (segment.address as usize + i).wrapping_sub(start as usize);
+                if offset < len {
+                    image[offset] = *b;
+                }
+            }
+        }
+        image
+    }
+
+    /// The bytes from the lowest to the highest assembled address together
+    /// with the lowest address, gaps are filled with `fill`.
+    pub fn flat(&self, fill: u8) -> (u16, Vec<u8>) {
+        let start = self.segments.iter().map(|s| s.address as usize).min();
+        let end = self
+            .segments
+            .iter()
+            .map(|s| s.address as usize + s.bytes.len())
+            .max();
+        match (start, end) {
+            (Some(start), Some(end)) => (start as u16, self.image(start as u16, end - start, fill)),
+            _ => (0, Vec::new()),
+        }
+    }
+}
+
+/// Assemble `source` for the instruction set of `variant`.
+pub fn assemble(variant: Variant, source: &str) -> Result<Program, AsmError> {
+    let mut asm = Assembler::new(variant);
+    asm.pass(source, false)?;
+    asm.pass(source, true)?;
+
+    Ok(Program {
+        segments: asm.segments,
+        symbols: asm.symbols,
+    })
+}
+
+/// Reasons an expression cannot be evaluated.
+enum ExprError {
+    /// a symbol which is not defined yet, fine in the first pass
+    Undefined(String),
+    Invalid(String),
+}
+
+/// The operand syntax of an instruction, before the addressing mode is
+/// chosen.
+enum Operand<'a> {
+    None,
+    Accumulator,
+    Immediate(&'a str),
+    /// `(expr)`
+    Indirect(&'a str),
+    /// `(expr,X)`
+    IndirectX(&'a str),
+    /// `(expr),Y`
+    IndirectY(&'a str),
+    /// `expr`, `expr,X` or `expr,Y`
+    Direct(&'a str, Option<char>),
+    /// `zp,target` of BBR and BBS
+    Pair(&'a str, &'a str),
+}
+
+struct Assembler {
+    variant: Variant,
+    /// opcode for each mnemonic and addressing mode, official opcodes are
+    /// preferred over undocumented duplicates
+    opcodes: HashMap<(&'static str, AddressingMode), u8>,
+    symbols: HashMap<String, i64>,
+    /// addressing mode chosen for the instruction on each line in the first
+    /// pass, so both passes agree on the size
+    modes: HashMap<usize, AddressingMode>,
+    segments: Vec<Segment>,
+    pc: u16,
+    /// last non-local label, the scope of `@labels`
+    scope: String,
+    line: usize,
+    emit: bool,
+}
+
+impl Assembler {
+    fn new(variant: Variant) -> Assembler {
+        let mut opcodes = HashMap::new();
+        for official in &[true, false] {
+            for (opcode, opi) in opinfo::op_info(variant).iter().enumerate() {
+                if opi.official == *official {
+                    opcodes.entry((opi.mnemonic, opi.mode)).or_insert(opcode as u8);
+                }
+            }
+        }
+
+        Assembler {
+            variant,
+            opcodes,
+            symbols: HashMap::new(),
+            modes: HashMap::new(),
+            segments: Vec::new(),
+            pc: 0,
+            scope: String::new(),
+            line: 0,
+            emit: false,
+        }
+    }
+
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, AsmError> {
+        Err(AsmError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    /// Run over the whole source. The first pass defines the symbols and
+    /// chooses the addressing modes, the second emits the code.
+    fn pass(&mut self, source: &str, emit: bool) -> Result<(), AsmError> {
+        self.emit = emit;
+        self.pc = 0;
+        self.scope.clear();
+        self.segments.clear();
+
+        for (n, line) in source.lines().enumerate() {
+            self.line = n + 1;
+            self.statement(strip_comment(line))?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, line: &str) -> Result<(), AsmError> {
+        let mut rest = line.trim();
+
+        // label
+        let len = identifier_len(rest);
+        if len > 0 && rest[len..].starts_with(':') {
+            let label = &rest[..len];
+            let pc = self.pc as i64;
+            self.define(label, pc)?;
+            if !label.starts_with('@') {
+                self.scope = label.to_string();
+            }
+            rest = rest[len + 1..].trim();
+        }
+
+        if rest.is_empty() {
+            return Ok(());
+        }
+
+        // constant
+        let len = identifier_len(rest);
+        if len > 0 && rest[len..].trim_start().starts_with('=') {
+            let name = &rest[..len];
+            let expr = rest[len..].trim_start()[1..].trim();
+            return match self.eval(expr) {
+                Ok(v) => self.define(name, v),
+                Err(ExprError::Undefined(_)) if !self.emit => Ok(()),
+                Err(e) => self.expr_error(e),
+            };
+        }
+
+        let (word, args) = match rest.find(char::is_whitespace) {
+            Some(i) => (&rest[..i], rest[i..].trim()),
+            None => (rest, ""),
+        };
+
+        if word.starts_with('.') {
+            self.directive(&word.to_lowercase(), args)
+        } else {
+            self.instruction(&word.to_uppercase(), args)
+        }
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), AsmError> {
+        let name = self.qualify(name);
+        if !self.emit && self.symbols.contains_key(&name) {
+            return self.error(format!("{} is already defined", name));
+        }
+        self.symbols.insert(name, value);
+        Ok(())
+    }
+
+    /// Local labels are stored as `scope@label`.
+    fn qualify(&self, name: &str) -> String {
+        if name.starts_with('@') {
+            format!("{}{}", self.scope, name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn directive(&mut self, directive: &str, args: &str) -> Result<(), AsmError> {
+        match directive {
+            ".org" => {
+                let addr = self.value(args)?;
+                self.pc = self.check_range(addr, 0xFFFF)?;
+                Ok(())
+            }
+            ".byte" | ".db" => {
+                for arg in split_args(args) {
+                    if arg.starts_with('"') {
+                        if arg.len() < 2 || !arg.ends_with('"') {
+                            return self.error("unterminated string");
+                        }
+                        let bytes: Vec<u8> = arg[1..arg.len() - 1].bytes().collect();
+                        self.emit_bytes(&bytes);
+                    } else {
+                        let v = self.value(arg)?;
+                        let b = self.check_byte(v)?;
+                        self.emit_bytes(&[b]);
+                    }
+                }
+                Ok(())
+            }
+            ".word" | ".dw" => {
+                for arg in split_args(args) {
+                    let v = self.value(arg)?;
+                    let w = self.check_range(v, 0xFFFF)?;
+                    self.emit_bytes(&[w as u8, (w >> 8) as u8]);
+                }
+                Ok(())
+            }
+            ".res" => {
+                let args = split_args(args);
+                if args.is_empty() || args.len() > 2 {
+                    return self.error(".res needs a size and an optional fill value");
+                }
+                // the size must be known in the first pass
+                let size = match self.eval(args[0]) {
+                    Ok(v) => self.check_range(v, 0xFFFF)? as usize,
+                    Err(e) => return self.expr_error(e),
+                };
+                let fill = match args.get(1) {
+                    Some(arg) => {
+                        let v = self.value(arg)?;
+                        self.check_byte(v)?
+                    }
+                    None => 0,
+                };
+                self.emit_bytes(&vec![fill; size]);
+                Ok(())
+            }
+            _ => self.error(format!("unknown directive {}", directive)),
+        }
+    }
+
+    fn instruction(&mut self, mnemonic: &str, args: &str) -> Result<(), AsmError> {
+        if !self.opcodes.keys().any(|k| k.0 == mnemonic) {
+            return self.error(format!("unknown instruction {}", mnemonic));
+        }
+
+        let operand = self.parse_operand(mnemonic, args);
+        let mode = match self.modes.get(&self.line) {
+            Some(mode) => *mode,
+            None => {
+                let mode = self.choose_mode(mnemonic, &operand)?;
+                self.modes.insert(self.line, mode);
+                mode
+            }
+        };
+        let opcode = self.opcodes[&(mnemonic, mode)];
+
+        let mut bytes = vec![opcode];
+        match (mode, operand) {
+            (Implicit, _) | (Accumulator, _) => {}
+            (Relative, Operand::Direct(expr, None)) => {
+                let next = self.pc.wrapping_add(2);
+                let offset = self.branch_offset(expr, next)?;
+                bytes.push(offset);
+            }
+            (ZeroPageRelative, Operand::Pair(zp, target)) => {
+                let v = self.value(zp)?;
+                bytes.push(self.check_byte(v)?);
+                let next = self.pc.wrapping_add(3);
+                let offset = self.branch_offset(target, next)?;
+                bytes.push(offset);
+            }
+            (_, Operand::Immediate(expr))
+            | (_, Operand::Indirect(expr))
+            | (_, Operand::IndirectX(expr))
+            | (_, Operand::IndirectY(expr))
+            | (_, Operand::Direct(expr, _)) => {
+                let v = self.value(expr)?;
+                if opinfo::op_info(self.variant)[opcode as usize].bytes == 2 {
+                    bytes.push(self.check_byte(v)?);
+                } else {
+                    let w = self.check_range(v, 0xFFFF)?;
+                    bytes.push(w as u8);
+                    bytes.push((w >> 8) as u8);
+                }
+            }
+            _ => return self.error(format!("invalid operand for {}", mnemonic)),
+        }
+
+        self.emit_bytes(&bytes);
+        Ok(())
+    }
+
+    fn parse_operand<'a>(&self, mnemonic: &str, args: &'a str) -> Operand<'a> {
+        let upper = args.to_uppercase();
+
+        if args.is_empty() {
+            return Operand::None;
+        }
+        if upper == "A" {
+            return Operand::Accumulator;
+        }
+        if let Some(expr) = args.strip_prefix('#') {
+            return Operand::Immediate(expr.trim());
+        }
+
+        let indirect = [Indirect, ZeroPageIndirect, IndexedIndirect, IndirectIndexed, AbsoluteIndexedIndirect];
+        if args.starts_with('(') && indirect.iter().any(|m| self.has_mode(mnemonic, *m)) {
+            let close = closing_paren(args);
+            let compact: String = upper.chars().filter(|c| !c.is_whitespace()).collect();
+            if close == Some(args.len() - 1) {
+                if compact.ends_with(",X)") {
+                    let comma = args.rfind(',').unwrap_or(0);
+                    return Operand::IndirectX(args[1..comma].trim());
+                }
+                return Operand::Indirect(args[1..args.len() - 1].trim());
+            }
+            if let Some(close) = close {
+                if compact.ends_with("),Y") && args[close + 1..].trim_start().starts_with(',') {
+                    return Operand::IndirectY(args[1..close].trim());
+                }
+            }
+        }
+
+        let parts = split_args(args);
+        if parts.len() == 2 {
+            match parts[1].to_uppercase().as_str() {
+                "X" => return Operand::Direct(parts[0], Some('X')),
+                "Y" => return Operand::Direct(parts[0], Some('Y')),
+                _ => return Operand::Pair(parts[0], parts[1]),
+            }
+        }
+        Operand::Direct(args, None)
+    }
+
+    fn has_mode(&self, mnemonic: &str, mode: AddressingMode) -> bool {
+        self.opcodes.keys().any(|k| k.0 == mnemonic && k.1 == mode)
+    }
+
+    /// Pick the addressing mode in the first pass. Zero page modes are used
+    /// if the operand is already known to fit in a byte.
+    fn choose_mode(&self, mnemonic: &str, operand: &Operand) -> Result<AddressingMode, AsmError> {
+        let has = |mode| self.has_mode(mnemonic, mode);
+        let pick = |modes: &[AddressingMode]| modes.iter().cloned().find(|m| has(*m));
+
+        let mode = match *operand {
+            Operand::None => pick(&[Implicit, Accumulator]),
+            Operand::Accumulator => pick(&[Accumulator]),
+            Operand::Immediate(_) => pick(&[Immediate]),
+            Operand::Indirect(_) => pick(&[Indirect, ZeroPageIndirect]),
+            Operand::IndirectX(_) => pick(&[IndexedIndirect, AbsoluteIndexedIndirect]),
+            Operand::IndirectY(_) => pick(&[IndirectIndexed]),
+            Operand::Pair(_, _) => pick(&[ZeroPageRelative]),
+            Operand::Direct(expr, index) => {
+                let (zp, abs) = match index {
+                    None => (ZeroPage, Absolute),
+                    Some('X') => (ZeroPageX, AbsoluteX),
+                    _ => (ZeroPageY, AbsoluteY),
+                };
+                if index.is_none() && has(Relative) {
+                    Some(Relative)
+                } else if expr.starts_with("a:") {
+                    pick(&[abs])
+                } else if expr.starts_with("z:") {
+                    pick(&[zp])
+                } else {
+                    let small = match self.eval(expr) {
+                        Ok(v) => (0..=0xFF).contains(&v),
+                        Err(_) => false,
+                    };
+                    if small {
+                        pick(&[zp, abs])
+                    } else {
+                        pick(&[abs, zp])
+                    }
+                }
+            }
+        };
+
+        match mode {
+            Some(mode) => Ok(mode),
+            None => self.error(format!("addressing mode not available for {}", mnemonic)),
+        }
+    }
+
+    fn branch_offset(&self, expr: &str, next: u16) -> Result<u8, AsmError> {
+        let target = self.value(expr)?;
+        let offset = target - next as i64;
+        if self.emit && !(-128..=127).contains(&offset) {
+            return self.error(format!("branch target out of range by {} bytes", offset));
+        }
+        Ok(offset as u8)
+    }
+
+    /// Evaluate an operand. Undefined symbols count as zero in the first
+    /// pass, the second pass reports them.
+    fn value(&self, expr: &str) -> Result<i64, AsmError> {
+        let expr = expr
+            .trim()
+            .trim_start_matches("a:")
+            .trim_start_matches("z:");
+        match self.eval(expr) {
+            Ok(v) => Ok(v),
+            Err(ExprError::Undefined(_)) if !self.emit => Ok(0),
+            Err(e) => self.expr_error(e),
+        }
+    }
+
+    fn expr_error<T>(&self, e: ExprError) -> Result<T, AsmError> {
+        match e {
+            ExprError::Undefined(name) => self.error(format!("undefined symbol {}", name)),
+            ExprError::Invalid(message) => self.error(message),
+        }
+    }
+
+    fn check_byte(&self, v: i64) -> Result<u8, AsmError> {
+        // negative bytes are common, e.g. .byte -1
+        if (-128..=0xFF).contains(&v) || !self.emit {
+            Ok(v as u8)
+        } else {
+            self.error(format!("{} does not fit in a byte", v))
+        }
+    }
+
+    fn check_range(&self, v: i64, max: i64) -> Result<u16, AsmError> {
+        if (0..=max).contains(&v) || !self.emit {
+            Ok(v as u16)
+        } else {
+            self.error(format!("{} is out of range", v))
+        }
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8]) {
+        if self.emit {
+            let pc = self.pc;
+            let contiguous = match self.segments.last() {
+                Some(s) => s.address.wrapping_add(s.bytes.len() as u16) == pc,
+                None => false,
+            };
+            if !contiguous {
+                self.segments.push(Segment {
+                    address: pc,
+                    bytes: Vec::new(),
+                });
+            }
+            if let Some(segment) = self.segments.last_mut() {
+                segment.bytes.extend_from_slice(bytes);
+            }
+        }
+        self.pc = self.pc.wrapping_add(bytes.len() as u16);
+    }
+
+    fn eval(&self, expr: &str) -> Result<i64, ExprError> {
+        let mut parser = ExprParser {
+            asm: self,
+            s: expr.as_bytes(),
+            pos: 0,
+        };
+        let v = parser.binary(0)?;
+        parser.skip_space();
+        if parser.pos != parser.s.len() {
+            return Err(ExprError::Invalid(format!("invalid expression {}", expr)));
+        }
+        Ok(v)
+    }
+}
+
+/// Binary operators from the lowest to the highest precedence.
+const PRECEDENCE: &[&[&str]] = &[&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/"]];
+
+/// Recursive descent parser which evaluates the expression while parsing.
+struct ExprParser<'a> {
+    asm: &'a Assembler,
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn skip_space(&mut self) {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        if self.s[self.pos..].starts_with(token.as_bytes()) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn invalid<T>(&self) -> Result<T, ExprError> {
+        Err(ExprError::Invalid(format!(
+            "invalid expression {}",
+            String::from_utf8_lossy(self.s)
+        )))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, ExprError> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+
+        let mut v = self.binary(level + 1)?;
+        'operators: loop {
+            for op in PRECEDENCE[level] {
+                if self.eat(op) {
+                    let rhs = self.binary(level + 1)?;
+                    v = match *op {
+                        "|" => v | rhs,
+                        "^" => v ^ rhs,
+                        "&" => v & rhs,
+                        "<<" => v.checked_shl(rhs as u32).unwrap_or(0),
+                        ">>" => v.checked_shr(rhs as u32).unwrap_or(0),
+                        "+" => v.wrapping_add(rhs),
+                        "-" => v.wrapping_sub(rhs),
+                        "*" => v.wrapping_mul(rhs),
+                        _ => match v.checked_div(rhs) {
+                            Some(v) => v,
+                            None => return Err(ExprError::Invalid(String::from("division by zero"))),
+                        },
+                    };
+                    continue 'operators;
+                }
+            }
+            return Ok(v);
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, ExprError> {
+        if self.eat("-") {
+            Ok(-self.unary()?)
+        } else if self.eat("~") {
+            Ok(!self.unary()?)
+        } else if self.eat("<") {
+            Ok(self.unary()? & 0xFF)
+        } else if self.eat(">") {
+            Ok((self.unary()? >> 8) & 0xFF)
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, ExprError> {
+        self.skip_space();
+        let rest = &self.s[self.pos..];
+
+        if self.eat("(") {
+            let v = self.binary(0)?;
+            if !self.eat(")") {
+                return self.invalid();
+            }
+            return Ok(v);
+        }
+        if self.eat("*") {
+            return Ok(self.asm.pc as i64);
+        }
+        if rest.len() >= 3 && rest[0] == b'\'' && rest[2] == b'\'' {
+            self.pos += 3;
+            return Ok(rest[1] as i64);
+        }
+
+        let (radix, skip) = match rest.first() {
+            Some(b'$') => (16, 1),
+            Some(b'%') => (2, 1),
+            Some(c) if c.is_ascii_digit() => (10, 0),
+            _ => (0, 0),
+        };
+        if radix != 0 {
+            let digits = rest[skip..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .count();
+            let text = String::from_utf8_lossy(&rest[skip..skip + digits]);
+            self.pos += skip + digits;
+            return match i64::from_str_radix(&text, radix) {
+                Ok(v) => Ok(v),
+                Err(_) => self.invalid(),
+            };
+        }
+
+        let len = identifier_len(&String::from_utf8_lossy(rest));
+        if len == 0 {
+            return self.invalid();
+        }
+        let name = String::from_utf8_lossy(&rest[..len]).to_string();
+        self.pos += len;
+        let name = self.asm.qualify(&name);
+        match self.asm.symbols.get(&name) {
+            Some(v) => Ok(*v),
+            None => Err(ExprError::Undefined(name)),
+        }
+    }
+}
+
+/// Length of the label or symbol name at the start of `s`.
+fn identifier_len(s: &str) -> usize {
+    let mut len = 0;
+    for (i, c) in s.char_indices() {
+        let valid = c == '_' || c.is_ascii_alphabetic() || (i == 0 && c == '@') || (i > 0 && c.is_ascii_digit());
+        if !valid {
+            break;
+        }
+        len = i + c.len_utf8();
+    }
+    len
+}
+
+/// Remove a `;` comment which is not inside a string or character literal.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &line[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Split at the commas which are not inside parentheses or quotes.
+fn split_args(s: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), _) => {}
+            (None, '"') => quote = Some(c),
+            // a character literal like ','
+            (None, '\'') if s[i..].len() >= 3 && s.as_bytes()[i + 2] == b'\'' => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                args.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !s.trim().is_empty() {
+        args.push(s[start..].trim());
+    }
+    args
+}
+
+/// Index of the parenthesis closing the one at the start of `s`.
+fn closing_paren(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::disasm;
+
+    fn bytes(variant: Variant, source: &str) -> Vec<u8> {
+        let program = assemble(variant, source).unwrap();
+        assert_eq!(program.segments.len(), 1);
+        program.flat(0).1
+    }
+
+    fn error(source: &str) -> AsmError {
+        assemble(Variant::Ricoh2A03, source).unwrap_err()
+    }
+
+    #[test]
+    fn all_addressing_modes() {
+        let source = "
+            .org $8000
+            nop
+            asl a
+            lda #$12
+            lda $12
+            lda $12,x
+            ldx $12,y
+            lda $1234
+            lda $1234,X
+            lda $1234,y
+            lda ($12,x)
+            lda ($12),y
+            jmp ($1234)
+        here:
+            bne here
+        ";
+        assert_eq!(
+            bytes(Variant::Ricoh2A03, source),
+            vec![
+                0xEA, 0x0A, 0xA9, 0x12, 0xA5, 0x12, 0xB5, 0x12, 0xB6, 0x12, 0xAD, 0x34, 0x12,
+                0xBD, 0x34, 0x12, 0xB9, 0x34, 0x12, 0xA1, 0x12, 0xB1, 0x12, 0x6C, 0x34, 0x12,
+                0xD0, 0xFE,
+            ]
+        );
+
+        let source = "
+            .org $8000
+            lda ($12)
+            jmp ($1234,x)
+            bbr0 $12, *
+        ";
+        assert_eq!(
+            bytes(Variant::Cmos65C02, source),
+            vec![0xB2, 0x12, 0x7C, 0x34, 0x12, 0x0F, 0x12, 0xFD]
+        );
+    }
+
+    #[test]
+    fn round_trip_through_disassembler() {
+        for variant in &[Variant::Nmos, Variant::Cmos65C02] {
+            for opcode in 0..=255u8 {
+                let opi = &opinfo::op_info(*variant)[opcode as usize];
+                let data = [opcode, 0x34, 0x12];
+                let instruction = disasm::decode(*variant, &data, 0x8000).unwrap();
+                let source = format!(".org $8000\n{}", instruction);
+
+                let program = assemble(*variant, &source).unwrap();
+                let assembled = program.flat(0).1;
+                let again = disasm::decode(*variant, &assembled, 0x8000).unwrap();
+
+                // duplicates of other opcodes assemble to the preferred one
+                assert_eq!(again.to_string(), instruction.to_string(), "{:02X}", opcode);
+                assert_eq!(again.mode, opi.mode, "{:02X}", opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn labels_and_forward_references() {
+        let source = "
+                .org $C000
+        start:  ldx #0
+        @loop:  lda data,x
+                beq @done
+                inx
+                bne @loop
+        @done:  jmp start
+        other:
+        @loop:  jmp @loop
+        data:   .byte 1, 2, 0
+        ";
+        let program = assemble(Variant::Ricoh2A03, source).unwrap();
+        assert_eq!(program.symbol("start"), Some(0xC000));
+        assert_eq!(program.symbol("start@loop"), Some(0xC002));
+        assert_eq!(program.symbol("other@loop"), Some(0xC00D));
+        assert_eq!(
+            program.flat(0).1,
+            vec![
+                0xA2, 0x00, 0xBD, 0x10, 0xC0, 0xF0, 0x03, 0xE8, 0xD0, 0xF8, 0x4C, 0x00, 0xC0,
+                0x4C, 0x0D, 0xC0, 0x01, 0x02, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn constants_and_expressions() {
+        let source = "
+        PPU = $2000
+        ZP = $10
+        COUNT = (3 + 4) * 2 - 1
+                .org $0600
+                sta PPU + 1
+                sta ZP
+                sta a:ZP
+                lda #<table
+                ldx #>table
+                ldy #COUNT
+                .byte %1010, 'A', -1, \"hi\", 1 << 4 | 1, ~0 & $0F
+                .word table, * + 2
+                .res 2, $EA
+        table:
+        ";
+        let code = bytes(Variant::Ricoh2A03, source);
+        assert_eq!(
+            code,
+            vec![
+                0x8D, 0x01, 0x20, 0x85, 0x10, 0x8D, 0x10, 0x00, 0xA9, 0x1B, 0xA2, 0x06, 0xA0,
+                0x0D, 0x0A, 0x41, 0xFF, 0x68, 0x69, 0x11, 0x0F, 0x1B, 0x06, 0x19, 0x06, 0xEA,
+                0xEA,
+            ]
+        );
+    }
+
+    #[test]
+    fn forward_references_use_absolute_addressing() {
+        let source = "
+                .org $0600
+                lda later
+        later = $10
+        ";
+        assert_eq!(bytes(Variant::Ricoh2A03, source), vec![0xAD, 0x10, 0x00]);
+    }
+
+    #[test]
+    fn segments() {
+        let source = "
+                .org $8000
+                nop
+                .org $FFFC
+                .word $8000
+        ";
+        let program = assemble(Variant::Ricoh2A03, source).unwrap();
+        assert_eq!(
+            program.segments,
+            vec![
+                Segment { address: 0x8000, bytes: vec![0xEA] },
+                Segment { address: 0xFFFC, bytes: vec![0x00, 0x80] },
+            ]
+        );
+
+        let image = program.image(0x8000, 0x8000, 0xFF);
+        assert_eq!(image[0], 0xEA);
+        assert_eq!(image[1], 0xFF);
+        assert_eq!(&image[0x7FFC..], &[0x00, 0x80, 0xFF, 0xFF]);
+
+        let mut ram = Ram::new();
+        program.load_into(&mut ram);
+        use cpu::bus::Bus;
+        assert_eq!(ram.read(0xFFFD), 0x80);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("  foo").message, "unknown instruction FOO");
+        assert_eq!(error("x:\nx:").line, 2);
+        assert_eq!(error("  lda missing").message, "undefined symbol missing");
+        assert_eq!(error("  lda #$100").message, "256 does not fit in a byte");
+        assert_eq!(error("  inx #1").message, "addressing mode not available for INX");
+        assert_eq!(error("  .foo").message, "unknown directive .foo");
+        assert_eq!(
+            error("l: .res 200\n  bne l").message,
+            "branch target out of range by -202 bytes"
+        );
+    }
+}
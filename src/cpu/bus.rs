@@ -0,0 +1,117 @@
+//! The interface between the CPU and the rest of the system.
+//!
+//! The CPU does not own any memory. Everything it accesses, RAM, cartridge
+//! ROM and the memory mapped registers of the other chips, is reached through
+//! a `Bus` which is passed to every call which executes code.
+
+/// Address space as seen by the CPU.
+///
+/// Every `read` and `write` is one bus cycle of the CPU. Depending on the
+/// `Timing` of the CPU this includes the dummy accesses of the real
+/// processor, which matter for registers with side effects on read or write.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, val: u8);
+
+    /// Read `addr` without any side effects, e.g. for a debugger or a trace
+    /// log. Returns `None` where this is not possible, which is the default.
+    fn peek(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Write `addr` without any side effects, e.g. for a debugger restoring
+    /// memory. Returns `false` where this is not possible, which is the
+    /// default.
+    fn poke(&mut self, _addr: u16, _val: u8) -> bool {
+        false
+    }
+
+    /// The bits of the value read from `addr` which the device drives. The
+    /// others float and keep the last value on the data bus, the open bus,
+    /// e.g. the upper bits of the controller ports. All bits by default.
+    fn driven_bits(&self, _addr: u16) -> u8 {
+        0xFF
+    }
+
+    /// Advance the devices on the bus by one CPU cycle. The CPU calls this
+    /// once for every cycle it spends.
+    fn tick(&mut self) {}
+}
+
+const RAM_SIZE: usize = 0x10000;
+
+/// 64 kB of RAM covering the whole address space, without any devices.
+/// Useful for tests and for running plain 6502 programs.
+pub struct Ram {
+    mem: Vec<u8>,
+}
+
+impl Default for Ram {
+    fn default() -> Ram {
+        Ram::new()
+    }
+}
+
+impl Ram {
+    pub fn new() -> Ram {
+        Ram {
+            mem: vec![0; RAM_SIZE],
+        }
+    }
+
+    /// Copy `data` into the RAM starting at `addr`, wrapping around at the
+    /// end of the address space.
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        for (i, b) in data.iter().enumerate() {
+            let a = addr.wrapping_add(i as u16);
+            self.mem[a as usize] = *b;
+        }
+    }
+}
+
+impl Bus for Ram {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.mem[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.mem[addr as usize] = val;
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.mem[addr as usize])
+    }
+
+    fn poke(&mut self, addr: u16, val: u8) -> bool {
+        self.mem[addr as usize] = val;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ram_read_write_peek() {
+        let mut ram = Ram::new();
+
+        ram.write(0xFFFF, 0xAB);
+        assert_eq!(ram.read(0xFFFF), 0xAB);
+        assert_eq!(ram.peek(0xFFFF), Some(0xAB));
+
+        assert!(ram.poke(0xFFFF, 0xCD));
+        assert_eq!(ram.read(0xFFFF), 0xCD);
+    }
+
+    #[test]
+    fn ram_load_wraps() {
+        let mut ram = Ram::new();
+
+        ram.load(0xFFFE, &[1, 2, 3]);
+        assert_eq!(ram.peek(0xFFFE), Some(1));
+        assert_eq!(ram.peek(0xFFFF), Some(2));
+        assert_eq!(ram.peek(0x0000), Some(3));
+    }
+}
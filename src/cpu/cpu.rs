@@ -1,22 +1,95 @@
-use cpu::opinfo::{OpInfo, OP_INFO};
+use cpu::interrupt::{
+    Interrupt, InterruptLines, IrqSource, IRQ_VECTOR, NMI_VECTOR, RESET_VECTOR,
+};
+use cpu::opinfo::{self, OpInfo, Penalty};
 use cpu::status_register::StatusRegister;
 use cpu::utils;
+use std::error;
 use std::fmt;
+use std::io::Write;
 
-use memory::{self, Memory};
-
-type MemoryAddress = u16;
-type PageCrossed = bool;
+use cpu::bus::Bus;
+use cpu::debug::{Breakpoint, BreakpointId, Frame, FrameKind, Return, StackAnomaly, Stop};
+use cpu::hook::{Access, Hook, HookId, InstructionEvent};
+use memory;
 
 // TODO: set correct address
 static STACK_BASE_ADDRESS: u16 = 0x0100;
 
-pub struct OpResponse {
-    bytes_consumed: usize,
-    cycles_spent: usize,
+/// Chip dependent constant of the unstable XAA and LXA opcodes. Values of
+/// $00, $EE and $FF are observed on real hardware.
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+/// Reason of a `CpuError`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CpuErrorKind {
+    /// an undocumented opcode rejected in strict mode, see `CPU::set_strict`
+    IllegalOpcode,
+    /// a JAM opcode locked up the processor
+    Jam,
+    /// an inconsistency in the emulator itself
+    Internal(&'static str),
+}
+
+/// Failure of an instruction, returned by `CPU::step`. The CPU stays halted
+/// and returns the same error until it is reset, so its state can still be
+/// inspected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuError {
+    /// address of the failing instruction
+    pub pc: u16,
+    pub opcode: u8,
+    pub kind: CpuErrorKind,
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            CpuErrorKind::IllegalOpcode => write!(f, "illegal opcode")?,
+            CpuErrorKind::Jam => write!(f, "processor jammed")?,
+            CpuErrorKind::Internal(reason) => write!(f, "internal error: {}", reason)?,
+        }
+        write!(f, " at ${:04X} (opcode ${:02X})", self.pc, self.opcode)
+    }
+}
+
+impl error::Error for CpuError {}
+
+/// Granularity at which the CPU drives the bus, see `CPU::set_timing`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// Only the bus accesses an instruction needs for its result are made,
+    /// dummy reads and writes are skipped but still take their cycle. The bus
+    /// is ticked for all cycles once the instruction completed.
+    Instruction,
+    /// Every cycle makes its bus access, including the dummy reads and
+    /// writes of the real processor, and the bus is ticked right after it.
+    /// Devices observe the exact mid instruction timing.
+    Cycle,
+}
+
+/// Members of the 6502 family the CPU can emulate, see `CPU::set_variant`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// The original NMOS 6502 including decimal mode and the unofficial
+    /// opcodes.
+    Nmos,
+    /// The Ricoh 2A03 of the NES, an NMOS 6502 whose decimal mode is
+    /// disconnected. The decimal flag can be set, but ADC and SBC ignore it.
+    Ricoh2A03,
+    /// The WDC 65C02 with its additional instructions and addressing modes.
+    /// Decimal mode sets valid flags at the cost of an extra cycle, the
+    /// undefined opcodes are NOPs and the JMP indirect bug is fixed.
+    Cmos65C02,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl Variant {
+    pub fn has_decimal_mode(self) -> bool {
+        self != Variant::Ricoh2A03
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum AddressingMode {
     Implicit,
     Accumulator,
@@ -31,6 +104,12 @@ pub enum AddressingMode {
     IndexedIndirect,
     IndirectIndexed,
     Indirect,
+    /// (zp), 65C02 only
+    ZeroPageIndirect,
+    /// (abs,X) of JMP, 65C02 only
+    AbsoluteIndexedIndirect,
+    /// zp,rel of BBR and BBS, 65C02 only
+    ZeroPageRelative,
 }
 
 pub struct CPU {
@@ -43,8 +122,195 @@ pub struct CPU {
 
     /// count the total amount of cycles spent
     cycles: usize,
+
+    /// set by the last address calculation if indexing crossed a page
+    page_crossed: bool,
+
+    /// set by a JAM or STP opcode or an error, only a reset recovers the
+    /// processor
+    halted: bool,
+
+    /// failure of the instruction being executed, see `fault`
+    fault: Option<CpuErrorKind>,
+
+    /// the error which halted the processor, returned by every step until
+    /// the next reset
+    error: Option<CpuError>,
+
+    /// set by the WAI opcode of the 65C02 until an interrupt is requested
+    waiting: bool,
+
+    /// levels of the NMI and IRQ inputs
+    interrupt_lines: InterruptLines,
+
+    /// result of the interrupt poll of the last instruction, serviced before
+    /// the next instruction is fetched
+    pending_interrupt: Option<Interrupt>,
+
+    /// interrupt inputs sampled at the end of the current and the previous
+    /// cycle, an instruction acts on the samples of its second to last cycle
+    irq_sampled: bool,
+    nmi_sampled: bool,
+    irq_sampled_prev: bool,
+    nmi_sampled_prev: bool,
+
+    timing: Timing,
+    variant: Variant,
+    /// reject undocumented opcodes, see `set_strict`
+    strict: bool,
+
+    /// receives a nestest style line for every instruction, see `set_trace`
+    trace: Option<Box<dyn Write>>,
+
+    /// registered hooks, see `add_hook`
+    hooks: Vec<(HookId, Box<dyn Hook>)>,
+    next_hook_id: usize,
+
+    /// the instruction being executed while hooks are registered
+    current_instruction: Option<InstructionEvent>,
+
+    /// address and number of the operand bytes of the instruction being
+    /// executed, to tell operand fetches from data reads
+    operands: Option<(u16, usize)>,
+
+    /// registered breakpoints, see `add_breakpoint`
+    breakpoints: Vec<(BreakpointId, Breakpoint)>,
+    next_breakpoint_id: usize,
+
+    /// address of the instruction the last execution breakpoint stopped at
+    resume_pc: Option<u16>,
+
+    /// first watchpoint hit by the instruction being executed
+    watch_hit: Option<Stop>,
+
+    /// the `call` in progress
+    calling: Option<self::call::Calling>,
+
+    /// undo log of the last `history_capacity` steps, see `set_history`
+    history: self::history::History,
+    history_capacity: usize,
+
+    /// undo information of the step being executed
+    recording: Option<self::history::UndoEntry>,
+
+    /// shadow call stack if turned on, see `set_call_stack`
+    call_stack: Option<Vec<Frame>>,
+    stack_anomalies: Vec<StackAnomaly>,
+
+    /// checks of the lint mode if turned on, see `set_lint`
+    lint: Option<Box<self::lints::Linter>>,
 }
 
+/// Signature shared by all instruction handlers, see `JUMP_TABLE`.
+type Operation = fn(&mut CPU, &mut dyn Bus, &OpInfo);
+
+/// Instruction handlers indexed by opcode. The metadata for each entry is
+/// stored at the same index in `OP_INFO`.
+#[rustfmt::skip]
+static JUMP_TABLE: [Operation; 256] = [
+    // 0x00
+    CPU::brk, CPU::ora, CPU::jam, CPU::slo, CPU::nop, CPU::ora, CPU::asl, CPU::slo,
+    CPU::php, CPU::ora, CPU::asl, CPU::anc, CPU::nop, CPU::ora, CPU::asl, CPU::slo,
+    // 0x10
+    CPU::bpl, CPU::ora, CPU::jam, CPU::slo, CPU::nop, CPU::ora, CPU::asl, CPU::slo,
+    CPU::clc, CPU::ora, CPU::nop, CPU::slo, CPU::nop, CPU::ora, CPU::asl, CPU::slo,
+    // 0x20
+    CPU::jsr, CPU::and, CPU::jam, CPU::rla, CPU::bit, CPU::and, CPU::rol, CPU::rla,
+    CPU::plp, CPU::and, CPU::rol, CPU::anc, CPU::bit, CPU::and, CPU::rol, CPU::rla,
+    // 0x30
+    CPU::bmi, CPU::and, CPU::jam, CPU::rla, CPU::nop, CPU::and, CPU::rol, CPU::rla,
+    CPU::sec, CPU::and, CPU::nop, CPU::rla, CPU::nop, CPU::and, CPU::rol, CPU::rla,
+    // 0x40
+    CPU::rti, CPU::eor, CPU::jam, CPU::sre, CPU::nop, CPU::eor, CPU::lsr, CPU::sre,
+    CPU::pha, CPU::eor, CPU::lsr, CPU::alr, CPU::jmp, CPU::eor, CPU::lsr, CPU::sre,
+    // 0x50
+    CPU::bvc, CPU::eor, CPU::jam, CPU::sre, CPU::nop, CPU::eor, CPU::lsr, CPU::sre,
+    CPU::cli, CPU::eor, CPU::nop, CPU::sre, CPU::nop, CPU::eor, CPU::lsr, CPU::sre,
+    // 0x60
+    CPU::rts, CPU::adc, CPU::jam, CPU::rra, CPU::nop, CPU::adc, CPU::ror, CPU::rra,
+    CPU::pla, CPU::adc, CPU::ror, CPU::arr, CPU::jmp, CPU::adc, CPU::ror, CPU::rra,
+    // 0x70
+    CPU::bvs, CPU::adc, CPU::jam, CPU::rra, CPU::nop, CPU::adc, CPU::ror, CPU::rra,
+    CPU::sei, CPU::adc, CPU::nop, CPU::rra, CPU::nop, CPU::adc, CPU::ror, CPU::rra,
+    // 0x80
+    CPU::nop, CPU::sta, CPU::nop, CPU::sax, CPU::sty, CPU::sta, CPU::stx, CPU::sax,
+    CPU::dey, CPU::nop, CPU::txa, CPU::xaa, CPU::sty, CPU::sta, CPU::stx, CPU::sax,
+    // 0x90
+    CPU::bcc, CPU::sta, CPU::jam, CPU::sha, CPU::sty, CPU::sta, CPU::stx, CPU::sax,
+    CPU::tya, CPU::sta, CPU::txs, CPU::tas, CPU::shy, CPU::sta, CPU::shx, CPU::sha,
+    // 0xA0
+    CPU::ldy, CPU::lda, CPU::ldx, CPU::lax, CPU::ldy, CPU::lda, CPU::ldx, CPU::lax,
+    CPU::tay, CPU::lda, CPU::tax, CPU::lxa, CPU::ldy, CPU::lda, CPU::ldx, CPU::lax,
+    // 0xB0
+    CPU::bcs, CPU::lda, CPU::jam, CPU::lax, CPU::ldy, CPU::lda, CPU::ldx, CPU::lax,
+    CPU::clv, CPU::lda, CPU::tsx, CPU::las, CPU::ldy, CPU::lda, CPU::ldx, CPU::lax,
+    // 0xC0
+    CPU::cpy, CPU::cmp, CPU::nop, CPU::dcp, CPU::cpy, CPU::cmp, CPU::dec, CPU::dcp,
+    CPU::iny, CPU::cmp, CPU::dex, CPU::axs, CPU::cpy, CPU::cmp, CPU::dec, CPU::dcp,
+    // 0xD0
+    CPU::bne, CPU::cmp, CPU::jam, CPU::dcp, CPU::nop, CPU::cmp, CPU::dec, CPU::dcp,
+    CPU::cld, CPU::cmp, CPU::nop, CPU::dcp, CPU::nop, CPU::cmp, CPU::dec, CPU::dcp,
+    // 0xE0
+    CPU::cpx, CPU::sbc, CPU::nop, CPU::isc, CPU::cpx, CPU::sbc, CPU::inc, CPU::isc,
+    CPU::inx, CPU::sbc, CPU::nop, CPU::sbc, CPU::cpx, CPU::sbc, CPU::inc, CPU::isc,
+    // 0xF0
+    CPU::beq, CPU::sbc, CPU::jam, CPU::isc, CPU::nop, CPU::sbc, CPU::inc, CPU::isc,
+    CPU::sed, CPU::sbc, CPU::nop, CPU::isc, CPU::nop, CPU::sbc, CPU::inc, CPU::isc,
+];
+
+/// Instruction handlers of the 65C02, the counterpart of `OP_INFO_65C02`.
+#[rustfmt::skip]
+static JUMP_TABLE_65C02: [Operation; 256] = [
+    // 0x00
+    CPU::brk, CPU::ora, CPU::nop, CPU::nop, CPU::tsb, CPU::ora, CPU::asl, CPU::rmb,
+    CPU::php, CPU::ora, CPU::asl, CPU::nop, CPU::tsb, CPU::ora, CPU::asl, CPU::bbr,
+    // 0x10
+    CPU::bpl, CPU::ora, CPU::ora, CPU::nop, CPU::trb, CPU::ora, CPU::asl, CPU::rmb,
+    CPU::clc, CPU::ora, CPU::inc, CPU::nop, CPU::trb, CPU::ora, CPU::asl, CPU::bbr,
+    // 0x20
+    CPU::jsr, CPU::and, CPU::nop, CPU::nop, CPU::bit, CPU::and, CPU::rol, CPU::rmb,
+    CPU::plp, CPU::and, CPU::rol, CPU::nop, CPU::bit, CPU::and, CPU::rol, CPU::bbr,
+    // 0x30
+    CPU::bmi, CPU::and, CPU::and, CPU::nop, CPU::bit, CPU::and, CPU::rol, CPU::rmb,
+    CPU::sec, CPU::and, CPU::dec, CPU::nop, CPU::bit, CPU::and, CPU::rol, CPU::bbr,
+    // 0x40
+    CPU::rti, CPU::eor, CPU::nop, CPU::nop, CPU::nop, CPU::eor, CPU::lsr, CPU::rmb,
+    CPU::pha, CPU::eor, CPU::lsr, CPU::nop, CPU::jmp, CPU::eor, CPU::lsr, CPU::bbr,
+    // 0x50
+    CPU::bvc, CPU::eor, CPU::eor, CPU::nop, CPU::nop, CPU::eor, CPU::lsr, CPU::rmb,
+    CPU::cli, CPU::eor, CPU::phy, CPU::nop, CPU::nop_long, CPU::eor, CPU::lsr, CPU::bbr,
+    // 0x60
+    CPU::rts, CPU::adc, CPU::nop, CPU::nop, CPU::stz, CPU::adc, CPU::ror, CPU::rmb,
+    CPU::pla, CPU::adc, CPU::ror, CPU::nop, CPU::jmp, CPU::adc, CPU::ror, CPU::bbr,
+    // 0x70
+    CPU::bvs, CPU::adc, CPU::adc, CPU::nop, CPU::stz, CPU::adc, CPU::ror, CPU::rmb,
+    CPU::sei, CPU::adc, CPU::ply, CPU::nop, CPU::jmp, CPU::adc, CPU::ror, CPU::bbr,
+    // 0x80
+    CPU::bra, CPU::sta, CPU::nop, CPU::nop, CPU::sty, CPU::sta, CPU::stx, CPU::smb,
+    CPU::dey, CPU::bit, CPU::txa, CPU::nop, CPU::sty, CPU::sta, CPU::stx, CPU::bbs,
+    // 0x90
+    CPU::bcc, CPU::sta, CPU::sta, CPU::nop, CPU::sty, CPU::sta, CPU::stx, CPU::smb,
+    CPU::tya, CPU::sta, CPU::txs, CPU::nop, CPU::stz, CPU::sta, CPU::stz, CPU::bbs,
+    // 0xA0
+    CPU::ldy, CPU::lda, CPU::ldx, CPU::nop, CPU::ldy, CPU::lda, CPU::ldx, CPU::smb,
+    CPU::tay, CPU::lda, CPU::tax, CPU::nop, CPU::ldy, CPU::lda, CPU::ldx, CPU::bbs,
+    // 0xB0
+    CPU::bcs, CPU::lda, CPU::lda, CPU::nop, CPU::ldy, CPU::lda, CPU::ldx, CPU::smb,
+    CPU::clv, CPU::lda, CPU::tsx, CPU::nop, CPU::ldy, CPU::lda, CPU::ldx, CPU::bbs,
+    // 0xC0
+    CPU::cpy, CPU::cmp, CPU::nop, CPU::nop, CPU::cpy, CPU::cmp, CPU::dec, CPU::smb,
+    CPU::iny, CPU::cmp, CPU::dex, CPU::wai, CPU::cpy, CPU::cmp, CPU::dec, CPU::bbs,
+    // 0xD0
+    CPU::bne, CPU::cmp, CPU::cmp, CPU::nop, CPU::nop, CPU::cmp, CPU::dec, CPU::smb,
+    CPU::cld, CPU::cmp, CPU::phx, CPU::stp, CPU::nop, CPU::cmp, CPU::dec, CPU::bbs,
+    // 0xE0
+    CPU::cpx, CPU::sbc, CPU::nop, CPU::nop, CPU::cpx, CPU::sbc, CPU::inc, CPU::smb,
+    CPU::inx, CPU::sbc, CPU::nop, CPU::nop, CPU::cpx, CPU::sbc, CPU::inc, CPU::bbs,
+    // 0xF0
+    CPU::beq, CPU::sbc, CPU::sbc, CPU::nop, CPU::nop, CPU::sbc, CPU::inc, CPU::smb,
+    CPU::sed, CPU::sbc, CPU::plx, CPU::nop, CPU::nop, CPU::sbc, CPU::inc, CPU::bbs,
+];
+
 impl fmt::Display for CPU {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -55,6 +321,12 @@ impl fmt::Display for CPU {
     }
 }
 
+impl Default for CPU {
+    fn default() -> CPU {
+        CPU::new()
+    }
+}
+
 impl CPU {
     pub fn new() -> CPU {
         CPU {
@@ -66,10 +338,42 @@ impl CPU {
             status_register: StatusRegister::new(),
 
             cycles: 0,
+            page_crossed: false,
+            halted: false,
+            fault: None,
+            error: None,
+            waiting: false,
+            interrupt_lines: InterruptLines::new(),
+            pending_interrupt: None,
+            irq_sampled: false,
+            nmi_sampled: false,
+            irq_sampled_prev: false,
+            nmi_sampled_prev: false,
+
+            timing: Timing::Instruction,
+            variant: Variant::Ricoh2A03,
+            strict: false,
+            trace: None,
+            hooks: Vec::new(),
+            next_hook_id: 0,
+            current_instruction: None,
+            operands: None,
+            breakpoints: Vec::new(),
+            next_breakpoint_id: 0,
+            resume_pc: None,
+            watch_hit: None,
+            calling: None,
+            history: Default::default(),
+            history_capacity: 0,
+            recording: None,
+            call_stack: None,
+            stack_anomalies: Vec::new(),
+            lint: None,
         }
     }
 
-    pub fn powerup(&mut self, mem: &mut Memory) {
+    pub fn powerup<B: Bus>(&mut self, bus: &mut B) {
+        let bus: &mut dyn Bus = bus;
         self.status_register.set_all(0x34);
         self.accumulator = 0;
         self.index_x = 0;
@@ -78,16 +382,274 @@ impl CPU {
 
         // Remaining tasks: set memory
         // LSFR = 0x00
-        mem.write(0x4017, 0x00);
-        mem.write_range(0x4000, 0x400F, 0x00);
+        bus.write(0x4017, 0x00);
+        for addr in 0x4000..0x4010 {
+            bus.write(addr, 0x00);
+        }
     }
 
-    pub fn reset(&mut self, mem: &mut Memory) {
-        self.stack_pointer -= 3;
+    /// Runs the reset sequence. The stack pointer is decremented by three
+    /// without writing to the stack and execution continues at the address
+    /// stored in the reset vector at $FFFC/D.
+    ///
+    /// Internally this is the interrupt sequence with the stack writes turned
+    /// into reads, so it takes 7 cycles.
+    pub fn reset<B: Bus>(&mut self, bus: &mut B) {
+        let bus: &mut dyn Bus = bus;
+        let start = self.cycles;
+
         self.status_register.interrupt_disable = true;
+        // the unused bit is not stored and always reads as one
+        self.status_register.unused_bit = true;
+        self.halted = false;
+        self.error = None;
+        // the reset sequence is not recorded, so it cannot be undone
+        self.history.clear();
+        if let Some(ref mut frames) = self.call_stack {
+            frames.clear();
+        }
+        self.waiting = false;
+        self.pending_interrupt = None;
+        self.interrupt_lines.clear_nmi();
 
         // Remaining tasks: set memory
-        mem.write(0x4015, 0x00);
+        bus.write(0x4015, 0x00);
+
+        let pc = self.program_counter;
+        self.dummy_read(bus, pc);
+        self.dummy_read(bus, pc);
+        for _ in 0..3 {
+            self.stack_dummy_read(bus);
+            self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+        }
+        self.program_counter = self.read16(bus, RESET_VECTOR);
+
+        self.catch_up(bus, start);
+    }
+
+    /// Select how instructions drive the bus. The default is
+    /// `Timing::Instruction`.
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Write a line in the format of the nestest log to `out` before every
+    /// instruction is executed, see `trace_line`. `None` turns tracing off.
+    pub fn set_trace(&mut self, out: Option<Box<dyn Write>>) {
+        self.trace = out;
+    }
+
+    /// Select the emulated processor. The default is `Variant::Ricoh2A03`.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Reject undocumented opcodes, the unofficial opcodes of the NMOS 6502
+    /// and the 2A03 and the undefined NOPs of the 65C02, with
+    /// `CpuErrorKind::IllegalOpcode` instead of executing them, e.g. to check
+    /// that a program sticks to the official instruction set. Off by
+    /// default.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Drive the edge triggered NMI input, e.g. from the PPU at the start of
+    /// vblank.
+    pub fn set_nmi(&mut self, active: bool) {
+        self.interrupt_lines.set_nmi(active);
+    }
+
+    /// Assert or release the level triggered IRQ line for one of its sources.
+    pub fn set_irq(&mut self, source: IrqSource, active: bool) {
+        self.interrupt_lines.set_irq(source, active);
+    }
+
+    /// Read a byte from the bus, this takes one cycle.
+    fn read(&mut self, bus: &mut dyn Bus, addr: u16) -> u8 {
+        self.read_as(bus, addr, Access::Read)
+    }
+
+    /// A read reported to the hooks as `kind`.
+    fn read_as(&mut self, bus: &mut dyn Bus, addr: u16, kind: Access) -> u8 {
+        let val = bus.read(addr);
+        let kind = match self.operands {
+            Some((first, len)) if kind == Access::Read && (addr.wrapping_sub(first) as usize) < len => {
+                Access::Operand
+            }
+            _ => kind,
+        };
+        if self.has_hooks() || self.has_breakpoints() {
+            self.observe(bus, addr, val, kind);
+        }
+        if self.lint.is_some() && kind == Access::Read {
+            self.lint_read(addr);
+        }
+        self.end_cycle(bus);
+        val
+    }
+
+    /// Report an access to the hooks and the watchpoints.
+    fn observe(&mut self, bus: &dyn Bus, addr: u16, val: u8, kind: Access) {
+        if self.has_hooks() {
+            self.notify_access(addr, val, kind);
+        }
+        if self.has_breakpoints() {
+            self.check_access(bus, addr, val, kind);
+        }
+    }
+
+    /// Write a byte to the bus, this takes one cycle.
+    fn write(&mut self, bus: &mut dyn Bus, addr: u16, val: u8) {
+        if self.recording.is_some() {
+            self.record_write(bus, addr);
+        }
+        bus.write(addr, val);
+        if self.has_hooks() || self.has_breakpoints() {
+            self.observe(bus, addr, val, Access::Write);
+        }
+        if self.lint.is_some() {
+            self.lint_write(addr, val);
+        }
+        self.end_cycle(bus);
+    }
+
+    /// A read whose value the processor discards. It still has side effects
+    /// on registers like $2007, so it only reaches the bus in
+    /// `Timing::Cycle`.
+    fn dummy_read(&mut self, bus: &mut dyn Bus, addr: u16) {
+        if self.timing == Timing::Cycle {
+            let val = bus.read(addr);
+            if self.has_hooks() || self.has_breakpoints() {
+                self.observe(bus, addr, val, Access::DummyRead);
+            }
+        }
+        self.end_cycle(bus);
+    }
+
+    /// The first of the two writes of a read-modify-write instruction, which
+    /// writes back the unmodified value.
+    fn dummy_write(&mut self, bus: &mut dyn Bus, addr: u16, val: u8) {
+        if self.timing == Timing::Cycle {
+            bus.write(addr, val);
+            if self.has_hooks() || self.has_breakpoints() {
+                self.observe(bus, addr, val, Access::DummyWrite);
+            }
+        }
+        self.end_cycle(bus);
+    }
+
+    /// Read the stack at the current stack pointer without changing it. Done
+    /// by instructions which pull from the stack while incrementing S.
+    fn stack_dummy_read(&mut self, bus: &mut dyn Bus) {
+        let addr = STACK_BASE_ADDRESS + self.stack_pointer as u16;
+        self.dummy_read(bus, addr);
+    }
+
+    /// Finish the current cycle: tick the bus and sample the interrupt
+    /// inputs.
+    fn end_cycle(&mut self, bus: &mut dyn Bus) {
+        self.cycles += 1;
+        if self.timing == Timing::Cycle {
+            bus.tick();
+        }
+
+        self.irq_sampled_prev = self.irq_sampled;
+        self.nmi_sampled_prev = self.nmi_sampled;
+        self.irq_sampled = self.interrupt_lines.irq() && !self.status_register.interrupt_disable;
+        self.nmi_sampled = self.interrupt_lines.nmi_pending();
+    }
+
+    /// In `Timing::Instruction` tick the bus for all cycles spent since
+    /// `start`.
+    fn catch_up(&mut self, bus: &mut dyn Bus, start: usize) {
+        if self.timing == Timing::Instruction {
+            for _ in start..self.cycles {
+                bus.tick();
+            }
+        }
+    }
+
+    /// Decide whether to service an interrupt before the next instruction.
+    /// The 6502 polls during the second to last cycle of every instruction,
+    /// NMI takes priority over IRQ and the IRQ is masked by the interrupt
+    /// disable flag as it was in that cycle. Hence CLI, SEI and PLP change
+    /// the masking only after the next instruction.
+    fn poll_interrupts(&mut self) {
+        self.pending_interrupt = if self.nmi_sampled_prev {
+            Some(Interrupt::Nmi)
+        } else if self.irq_sampled_prev {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        };
+    }
+
+    /// Push the return address and the status register and continue at the
+    /// address stored in `vector`. Shared by BRK and the hardware interrupts,
+    /// only BRK pushes the status with the break bit set.
+    ///
+    /// An NMI which is detected before the status is pushed hijacks a BRK or
+    /// IRQ: the pushed state is left as is, but the NMI vector is used. The
+    /// 65C02 also clears the decimal flag.
+    fn enter_interrupt(
+        &mut self,
+        bus: &mut dyn Bus,
+        return_address: u16,
+        brk: bool,
+        vector: u16,
+    ) {
+        let caller = self.program_counter;
+        let sp = self.stack_pointer;
+        self.push16(bus, return_address);
+
+        let vector = if vector == IRQ_VECTOR && self.interrupt_lines.take_nmi() {
+            NMI_VECTOR
+        } else {
+            vector
+        };
+
+        let p = self.status_register.to_u8() | 0x20;
+        let p = if brk { p | 0x10 } else { p & !0x10 };
+        self.push(bus, p);
+
+        self.status_register.interrupt_disable = true;
+        if self.variant == Variant::Cmos65C02 {
+            self.status_register.decimal_mode = false;
+        }
+        self.program_counter = self.read16(bus, vector);
+
+        let kind = match (vector, brk) {
+            (NMI_VECTOR, _) => FrameKind::Nmi,
+            (_, true) => FrameKind::Brk,
+            _ => FrameKind::Irq,
+        };
+        self.enter_frame(kind, caller, return_address, sp);
+
+        // the first instruction of the handler always executes, interrupts
+        // detected meanwhile are serviced after it
+        self.irq_sampled_prev = false;
+        self.nmi_sampled_prev = false;
+    }
+
+    /// Service a hardware interrupt, this takes 7 cycles like BRK. The first
+    /// two cycles fetch the next opcode and discard it.
+    fn interrupt(&mut self, bus: &mut dyn Bus, interrupt: Interrupt) {
+        if interrupt == Interrupt::Nmi {
+            self.interrupt_lines.take_nmi();
+        }
+
+        let pc = self.program_counter;
+        self.dummy_read(bus, pc);
+        self.dummy_read(bus, pc);
+        self.enter_interrupt(bus, pc, false, interrupt.vector());
     }
 
     fn carry_flag(&self) -> u8 {
@@ -97,435 +659,419 @@ impl CPU {
     /// Update the zero flag of the cpus status register. This is a common call
     /// in many instructions.
     fn update_zero_flag(&mut self, r: u8) {
-        if r == 0x00 {
-            self.status_register.zero_flag = true;
-        }
+        self.status_register.zero_flag = r == 0x00;
     }
 
     fn update_negative_flag(&mut self, r: u8) {
-        if r >> 7 & 0x01 == 0x01 {
-            self.status_register.negative_flag = true;
-        } else {
-            self.status_register.negative_flag = false;
-        }
+        self.status_register.negative_flag = r >> 7 & 0x01 == 0x01;
     }
 
     /// Get the memory address stored at (actually behind) the current program
     /// counter.
     ///
-    /// This method will increment the cpu cycles spent if it detects a page
-    /// crossing.
+    /// If indexing crosses a page boundary `page_crossed` is set, whether this
+    /// costs an extra cycle depends on the instruction, see
+    /// `operand_address`.
     ///
     /// 1. All instructions have only one argument, so there is only one memory
     ///    address to read per instruction.
     /// 2. Depending on the addressing mode, either one or two bytes have to be
-    ///    read from memory.
-    ///
-    /// # Bugs/TODOs
-    ///
-    /// 1. detect page crossings. Where?
-    ///     * Which modes?
-    ///
-    fn get_address(&mut self, mem: &Memory, mode: AddressingMode) -> Option<u16> {
+    ///    read from memory. Every read and the dummy read of the zero page
+    ///    indexed modes takes a cycle.
+    /// 3. For `Relative` and `Indirect` the returned address is the final
+    ///    target of the branch or jump.
+    fn get_address(&mut self, bus: &mut dyn Bus, mode: AddressingMode) -> Option<u16> {
         use cpu::cpu::AddressingMode::*;
 
-        match mode {
-            Absolute => {
-                let a = mem.read(self.program_counter + 1) as u16;
-                let b = mem.read(self.program_counter + 2) as u16;
+        let operand = self.program_counter.wrapping_add(1);
 
-                Some((b << 8) + a)
-            }
+        match mode {
+            Absolute => Some(self.read16(bus, operand)),
             AbsoluteX => {
-                let a = self.read16(mem, self.program_counter + 1);
-                let addr = a + self.index_x as u16;
+                let a = self.read16(bus, operand);
+                let addr = a.wrapping_add(self.index_x as u16);
 
-                if memory::page_crossed(a,  addr) {
-                    self.cycles += 1;
-                }
+                self.page_crossed = memory::page_crossed(a, addr);
 
                 Some(addr)
             }
             AbsoluteY => {
-                let a = self.read16(mem, self.program_counter + 1);
-                let addr = a + self.index_y as u16;
+                let a = self.read16(bus, operand);
+                let addr = a.wrapping_add(self.index_y as u16);
 
-                if memory::page_crossed(a,  addr) {
-                    self.cycles += 1;
-                }
+                self.page_crossed = memory::page_crossed(a, addr);
 
                 Some(addr)
             }
             Accumulator => None,
             Implicit => None,
-            Immediate => Some(self.program_counter + 1),
+            Immediate => Some(operand),
             IndexedIndirect => {
-                let a = mem.read(self.program_counter + 1) as u16;
-                let b = a.wrapping_add(self.index_x as u16);
-                let c = mem.read(b) as u16;
-                let d = mem.read(b + 1) as u16;
-                let e = (d << 8) + c;
+                let a = self.read(bus, operand);
+                // the pointer is read before X is added
+                self.dummy_read(bus, a as u16);
+                let b = a.wrapping_add(self.index_x);
 
-                Some(e)
+                Some(self.read16_zero_page(bus, b))
             }
             IndirectIndexed => {
-                let a = mem.read(self.program_counter + 1) as u16;
-                let b = self.read16(mem, a);
+                let a = self.read(bus, operand);
+                let b = self.read16_zero_page(bus, a);
 
-                let addr = b + self.index_y as u16;
+                let addr = b.wrapping_add(self.index_y as u16);
 
-                if memory::page_crossed(b,  addr) {
-                    self.cycles += 1;
-                }
+                self.page_crossed = memory::page_crossed(b, addr);
 
                 Some(addr)
             }
             Relative => {
-                let relative_value = mem.read(self.program_counter + 1);
-                Some(self.program_counter + relative_value as u16)
+                // the displacement is a signed byte relative to the address
+                // of the next instruction
+                let displacement = self.read(bus, operand) as i8;
+                let next = self.program_counter.wrapping_add(2);
+
+                Some(next.wrapping_add(displacement as u16))
+            }
+            ZeroPageRelative => {
+                // the zero page operand is handled by BBR and BBS, only the
+                // displacement in the last byte is read here
+                let displacement = self.read(bus, operand.wrapping_add(1)) as i8;
+                let next = self.program_counter.wrapping_add(3);
+
+                Some(next.wrapping_add(displacement as u16))
             }
-            ZeroPage => Some(mem.read(self.program_counter + 1) as u16),
+            ZeroPage => Some(self.read(bus, operand) as u16),
             ZeroPageX => {
-                let a = mem.read(self.program_counter + 1);
+                let a = self.read(bus, operand);
+                self.dummy_read(bus, a as u16);
                 let b = a.wrapping_add(self.index_x);
 
                 Some(b as u16)
             }
             ZeroPageY => {
-                let a = mem.read(self.program_counter + 1);
+                let a = self.read(bus, operand);
+                self.dummy_read(bus, a as u16);
                 let b = a.wrapping_add(self.index_y);
 
                 Some(b as u16)
             }
+            Indirect if self.variant == Variant::Cmos65C02 => {
+                // the 65C02 fixed the page bug at the cost of one cycle
+                let pointer = self.read16(bus, operand);
+                self.dummy_read(bus, operand.wrapping_add(1));
+
+                Some(self.read16(bus, pointer))
+            }
             Indirect => {
-                // TODO: test
-                let addr_lo = mem.read(self.program_counter + 1) as u16;
-                let addr_hi = match addr_lo {
-                    0xFF => {
-                        // bug in 6502
-                        0x0000
-                    }
-                    _ => mem.read(self.program_counter + 2) as u16,
-                };
-
-                Some(addr_hi << 8 + addr_lo)
+                let pointer = self.read16(bus, operand);
+
+                // bug in 6502: the high byte of the target is fetched without
+                // carrying into the page, so JMP ($xxFF) reads it from $xx00.
+                let lo = self.read(bus, pointer) as u16;
+                let hi = self.read(bus, (pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF)) as u16;
+
+                Some((hi << 8) | lo)
+            }
+            ZeroPageIndirect => {
+                let a = self.read(bus, operand);
+
+                Some(self.read16_zero_page(bus, a))
+            }
+            AbsoluteIndexedIndirect => {
+                let a = self.read16(bus, operand);
+                self.dummy_read(bus, operand.wrapping_add(1));
+                let pointer = a.wrapping_add(self.index_x as u16);
+
+                Some(self.read16(bus, pointer))
+            }
+        }
+    }
+
+    /// Like `get_address`, for modes which always have an address. A mode
+    /// without one is a bug in the instruction table and fails the
+    /// instruction.
+    fn address(&mut self, bus: &mut dyn Bus, mode: AddressingMode) -> u16 {
+        match self.get_address(bus, mode) {
+            Some(addr) => addr,
+            None => {
+                self.fault(CpuErrorKind::Internal("addressing mode without an address"));
+                0
             }
         }
     }
 
-    fn read16(&self, mem: &Memory, addr: u16) -> u16 {
-        let lo = mem.read(addr) as u16;
-        let hi = mem.read(addr + 1) as u16;
+    /// Address of the memory operand of `opi`.
+    ///
+    /// The absolute indexed and the indirect indexed modes add the index to
+    /// the low byte first and read from that address while the high byte is
+    /// fixed up. Reading instructions skip this dummy read unless the page
+    /// was crossed, stores and read-modify-write instructions always do it.
+    /// The 65C02 reads the last byte of the instruction again instead.
+    fn operand_address(&mut self, bus: &mut dyn Bus, opi: &OpInfo) -> u16 {
+        let addr = self.address(bus, opi.mode);
+
+        let indexed = matches!(
+            opi.mode,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectIndexed
+        );
+        if !indexed || !(self.page_crossed || opi.penalty == Penalty::Never) {
+            return addr;
+        }
+
+        let dummy = if self.variant == Variant::Cmos65C02 {
+            self.program_counter.wrapping_add(opi.bytes as u16 - 1)
+        } else if self.page_crossed {
+            addr.wrapping_sub(0x0100)
+        } else {
+            addr
+        };
+        self.dummy_read(bus, dummy);
+
+        addr
+    }
+
+    /// Read the operand of an instruction, either the immediate value or the
+    /// value stored at the operand address.
+    fn read_operand(&mut self, bus: &mut dyn Bus, opi: &OpInfo) -> u8 {
+        let addr = self.operand_address(bus, opi);
+        self.read(bus, addr)
+    }
+
+    fn read16(&mut self, bus: &mut dyn Bus, addr: u16) -> u16 {
+        let lo = self.read(bus, addr) as u16;
+        let hi = self.read(bus, addr.wrapping_add(1)) as u16;
 
         (hi << 8) + lo
     }
 
-    fn push(&mut self, mem: &mut Memory, val: u8) {
+    /// Read a 16 bit pointer from the zero page. The high byte wraps around
+    /// within the zero page, i.e. a pointer at $FF is read from $FF and $00.
+    fn read16_zero_page(&mut self, bus: &mut dyn Bus, addr: u8) -> u16 {
+        let lo = self.read(bus, addr as u16) as u16;
+        let hi = self.read(bus, addr.wrapping_add(1) as u16) as u16;
+
+        (hi << 8) + lo
+    }
+
+    fn push(&mut self, bus: &mut dyn Bus, val: u8) {
+        if self.lint.is_some() {
+            self.lint_stack(true);
+        }
         let addr = STACK_BASE_ADDRESS + self.stack_pointer as u16;
-        mem.write(addr, val);
+        self.write(bus, addr, val);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
-    fn push16(&mut self, mem: &mut Memory, val: u16) {
-        let hi = ((val >> 8) as u8) & 0xFF;
-        let lo = (val as u8) & 0xFF;
+    fn push16(&mut self, bus: &mut dyn Bus, val: u16) {
+        let hi = (val >> 8) as u8;
+        let lo = val as u8;
 
-        self.push(mem, hi);
-        self.push(mem, lo);
+        self.push(bus, hi);
+        self.push(bus, lo);
     }
 
-    fn pop(&mut self, mem: &mut Memory) -> u8 {
-        let addr = STACK_BASE_ADDRESS + self.stack_pointer as u16 + 1;
-        let val = mem.read(addr);
-
+    fn pop(&mut self, bus: &mut dyn Bus) -> u8 {
+        if self.lint.is_some() {
+            self.lint_stack(false);
+        }
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
 
-        val
+        let addr = STACK_BASE_ADDRESS + self.stack_pointer as u16;
+        self.read(bus, addr)
     }
 
-    fn pop16(&mut self, mem: &mut Memory) -> u16 {
-        let lo = self.pop(mem) as u16;
-        let hi = self.pop(mem) as u16;
+    fn pop16(&mut self, bus: &mut dyn Bus) -> u16 {
+        let lo = self.pop(bus) as u16;
+        let hi = self.pop(bus) as u16;
 
         (hi << 8) + lo
     }
 
-    pub fn step(&mut self, mem: &mut Memory) {
-        // TODO: check for interrupts
-        //
+    /// Returns true if the processor executed a JAM or STP opcode or failed
+    /// with an error. A halted CPU does not execute any further instructions
+    /// until it is reset.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Execute the next instruction and return the number of cycles it took.
+    /// If the previous instruction detected an interrupt, the interrupt
+    /// sequence is run instead and the first instruction of the handler is
+    /// executed by the next step.
+    ///
+    /// A CPU halted by STP does nothing and returns 0, a waiting 65C02 idles
+    /// for one cycle. A failing instruction halts the CPU and its error is
+    /// returned by this and every following step until a reset.
+    ///
+    /// Breakpoints stop the CPU before the instruction, watchpoints after it,
+    /// see `add_breakpoint`.
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> Result<usize, Stop> {
+        let bus: &mut dyn Bus = bus;
+        if self.halted {
+            return match self.error {
+                Some(error) => Err(Stop::Error(error)),
+                None => Ok(0),
+            };
+        }
+
+        let start = self.cycles;
+        if self.history_capacity > 0 {
+            self.begin_undo();
+        }
+
+        let woke = self.waiting;
+        if self.waiting {
+            let nmi = self.interrupt_lines.nmi_pending();
+            if !nmi && !self.interrupt_lines.irq() {
+                // idle until an interrupt is requested
+                self.end_cycle(bus);
+                self.catch_up(bus, start);
+                self.end_undo();
+                return Ok(1);
+            }
+
+            // a masked IRQ only ends the wait, the program continues after
+            // the WAI
+            self.waiting = false;
+            self.pending_interrupt = if nmi {
+                Some(Interrupt::Nmi)
+            } else if !self.status_register.interrupt_disable {
+                Some(Interrupt::Irq)
+            } else {
+                None
+            };
+        }
+
+        if let Some(ref mut lint) = self.lint {
+            lint.pc = self.program_counter;
+        }
+
+        if self.pending_interrupt.is_none() && self.has_breakpoints() {
+            if let Some(stop) = self.check_execute(bus) {
+                // nothing was executed, there is nothing to undo unless the
+                // CPU woke up from WAI
+                if woke {
+                    self.end_undo();
+                } else {
+                    self.recording = None;
+                }
+                return Err(stop);
+            }
+        }
+
+        let result = if let Some(interrupt) = self.pending_interrupt.take() {
+            self.interrupt(bus, interrupt);
+            Ok(())
+        } else {
+            if self.trace.is_some() {
+                let line = self.trace_line(bus);
+                if let Some(ref mut out) = self.trace {
+                    // a failing trace must not stop the emulation
+                    let _ = writeln!(out, "{}", line);
+                }
+            }
+            let result = self.execute_next(bus);
+            self.poll_interrupts();
+            result
+        };
+
+        self.catch_up(bus, start);
+        self.end_undo();
+        result?;
+        match self.watch_hit.take() {
+            Some(stop) => Err(stop),
+            None => Ok(self.cycles - start),
+        }
+    }
 
-        self.execute_next(mem);
+    /// Execute instructions until at least `cycles` cycles are spent and
+    /// return the cycles actually spent. Instructions are never interrupted,
+    /// so the last one may overshoot the budget by a few cycles, which the
+    /// caller can subtract from the next budget. Stops early if the CPU is
+    /// halted and at the first error or breakpoint.
+    pub fn run_for<B: Bus>(&mut self, bus: &mut B, cycles: usize) -> Result<usize, Stop> {
+        let start = self.cycles;
+        while self.cycles - start < cycles {
+            // only a CPU halted without an error spends no cycles
+            if self.step(bus)? == 0 {
+                break;
+            }
+        }
+        Ok(self.cycles - start)
     }
 
     /// Executes the next instruction stored at the program_counters address.
-    #[cfg_attr(rustfmt, rustfmt_skip)]
-    fn execute_next(&mut self, mem: &mut Memory) {
-        use cpu::cpu::AddressingMode::*;
-
-        match mem.read(self.program_counter) {
-            // ADC
-            0x69 => self.adc(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0x65 => self.adc(mem, &OpInfo{mode: ZeroPage,  bytes: 2, cycles: 3}),
-            0x75 => self.adc(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0x6D => self.adc(mem, &OpInfo{mode: Absolute,  bytes: 3, cycles: 4}),
-            0x7D => self.adc(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 4}),
-            0x79 => self.adc(mem, &OpInfo{mode: AbsoluteY, bytes: 3, cycles: 4}),
-            0x61 => self.adc(mem, &OpInfo{mode: IndexedIndirect, bytes: 2, cycles: 6}),
-            0x71 => self.adc(mem, &OpInfo{mode: IndirectIndexed, bytes: 2, cycles: 5}),
-
-            // AND
-            0x29 => self.and(mem, AddressingMode::Immediate),
-            0x25 => self.and(mem, AddressingMode::ZeroPage),
-            0x35 => self.and(mem, AddressingMode::ZeroPageX),
-            0x2D => self.and(mem, AddressingMode::ZeroPageY),
-            0x3D => self.and(mem, AddressingMode::AbsoluteX),
-            0x39 => self.and(mem, AddressingMode::AbsoluteY),
-            0x21 => self.and(mem, AddressingMode::IndexedIndirect),
-            0x31 => self.and(mem, AddressingMode::IndirectIndexed),
-
-            // ASL
-            0x0A => self.asl(mem, AddressingMode::Accumulator),
-            0x06 => self.asl(mem, AddressingMode::ZeroPage),
-            0x16 => self.asl(mem, AddressingMode::ZeroPageX),
-            0x0E => self.asl(mem, AddressingMode::Absolute),
-            0x1E => self.asl(mem, AddressingMode::AbsoluteX),
-
-            // BCC
-            0x90 => self.bcc(mem, &OpInfo{mode: Relative, bytes: 2, cycles: 2}),
-
-            // BCS
-            0xB0 => self.bcs(mem, &OpInfo{mode: Relative, bytes: 2, cycles: 2}),
-
-            // BEQ (branch if equal)
-            0xF0 => self.beq(mem, &OpInfo{mode: Relative, bytes: 2, cycles: 2}),
-
-            // BIT (bit test)
-            0x24 => self.bit(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0x2C => self.bit(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-
-            // BMI (branch if minus)
-            0x30 => self.bmi(mem, &OpInfo{mode: Relative, bytes: 2, cycles: 2}),
-
-            // BNE (branch if not equal)
-            0xD0 => self.bne(mem, &OpInfo{mode: Relative, bytes: 2, cycles: 2}),
-
-            // BPL (branch if positive)
-            0x10 => self.bpl(mem, &OpInfo{mode: Relative, bytes: 2, cycles: 2}),
-
-            // BRK (force interrupt)
-            0x00 => self.brk(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 7}),
-
-            // BVC (branch if overflow clear)
-            0x50 => self.bvc(mem, &OpInfo{mode: Relative, bytes: 2, cycles: 2}),
-
-            // BVS (branch if overflow set)
-            0x70 => self.bvs(mem, &OpInfo{mode: Relative, bytes: 2, cycles: 2}),
-
-            // CLC (clear carry flag)
-            0x18 => self.clc(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // CLD (clear decimal mode)
-            0xD8 => self.cld(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // CLI (clear interrupt disable)
-            0x58 => self.cli(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // CLV (clear overflow flag)
-            0xB8 => self.clv(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // CMP (compare)
-            0xC9 => self.cmp(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0xC5 => self.cmp(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0xD5 => self.cmp(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0xCD => self.cmp(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-            0xDD => self.cmp(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 4}),
-            0xD9 => self.cmp(mem, &OpInfo{mode: AbsoluteY, bytes: 3, cycles: 4}),
-            0xC1 => self.cmp(mem, &OpInfo{mode: IndexedIndirect, bytes: 2, cycles: 6}),
-            0xD1 => self.cmp(mem, &OpInfo{mode: IndirectIndexed, bytes: 2, cycles: 5}),
-
-            // CPX
-            0xE0 => self.cpx(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0xE4 => self.cpx(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0xEC => self.cpx(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-
-            // CPY
-            0xC0 => self.cpx(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0xC4 => self.cpx(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0xCC => self.cpx(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-
-            // DEC (decrement memory)
-            0xC6 => self.dec(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 5}),
-            0xD6 => self.dec(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 6}),
-            0xCE => self.dec(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 6}),
-            0xDE => self.dec(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 7}),
-
-            // DEX (decrement x register)
-            0xCA => self.dex(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // DEY (decrement x register)
-            0x88 => self.dey(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // EOR (exclusive or)
-            0x49 => self.eor(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0x45 => self.eor(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0x55 => self.eor(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0x4D => self.eor(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-            0x5D => self.eor(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 4}),
-            0x59 => self.eor(mem, &OpInfo{mode: AbsoluteY, bytes: 3, cycles: 4}),
-            0x41 => self.eor(mem, &OpInfo{mode: IndexedIndirect, bytes: 2, cycles: 6}),
-            0x51 => self.eor(mem, &OpInfo{mode: IndirectIndexed, bytes: 2, cycles: 5}),
-
-            // INC (increment memory)
-            0xE6 => self.inc(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 5}),
-            0xF6 => self.inc(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 6}),
-            0xEE => self.inc(mem, &OpInfo{mode: ZeroPage, bytes: 3, cycles: 6}),
-            0xFE => self.inc(mem, &OpInfo{mode: ZeroPage, bytes: 3, cycles: 7}),
-
-            // INX (increment x register)
-            0xE8 => self.dey(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // INY (increment y register)
-            0xC8 => self.dey(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // JMP (jump)
-            0x4C => self.jmp(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 3}),
-            0x6C => self.jmp(mem, &OpInfo{mode: Indirect, bytes: 3, cycles: 5}),
-
-            // JSR (jump to subrouting)
-            0x20 => self.jsr(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 6}),
-
-            // LDA (load accumulator)
-            0xA9 => self.lda(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0xA5 => self.lda(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0xB5 => self.lda(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0xAD => self.lda(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-            0xBD => self.lda(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 4}),
-            0xB9 => self.lda(mem, &OpInfo{mode: AbsoluteY, bytes: 3, cycles: 4}),
-            0xA1 => self.lda(mem, &OpInfo{mode: IndirectIndexed, bytes: 2, cycles: 6}),
-            0xB1 => self.lda(mem, &OpInfo{mode: IndexedIndirect, bytes: 2, cycles: 5}),
-
-            // LDX (load x register)
-            0xA2 => self.ldx(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0xA6 => self.ldx(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0xB6 => self.ldx(mem, &OpInfo{mode: ZeroPageY, bytes: 2, cycles: 4}),
-            0xAE => self.ldx(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-            0xBE => self.ldx(mem, &OpInfo{mode: AbsoluteY, bytes: 3, cycles: 4}),
-
-            // LDY (load y register)
-            0xA0 => self.ldy(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0xA4 => self.ldy(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0xB4 => self.ldy(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0xAC => self.ldy(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-            0xBC => self.ldy(mem, &OpInfo{mode: AbsoluteX , bytes: 3, cycles: 4}),
-
-            // LSR (logical shift right)
-            0x4A => self.lsr(mem, &OpInfo{mode: Accumulator, bytes: 1, cycles: 2}),
-            0x46 => self.lsr(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 5}),
-            0x56 => self.lsr(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 6}),
-            0x4E => self.lsr(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 6}),
-            0x5E => self.lsr(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 7}),
-
-            // NOP (no operation)
-            0xEA => self.nop(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // OAR (logical inclusive or)
-            0x09 => self.ora(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0x05 => self.ora(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 3}),
-            0x15 => self.ora(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0x0D => self.ora(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 4}),
-            0x1D => self.ora(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 4}),
-            0x19 => self.ora(mem, &OpInfo{mode: AbsoluteY, bytes: 3, cycles: 4}),
-            0x01 => self.ora(mem, &OpInfo{mode: IndexedIndirect, bytes: 2, cycles: 6}),
-            0x11 => self.ora(mem, &OpInfo{mode: IndirectIndexed, bytes: 2, cycles: 5}),
-
-            // PHA (push accumulator)
-            0x48 => self.pha(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 3}),
-
-            // PHP (push status register)
-            0x08 => self.php(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 3}),
-
-            // PLA (pull accumulator)
-            0x68 => self.pla(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 3}),
-
-            // PLP (pull status register)
-            0x28 => self.pla(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 3}),
-
-            // ROL (rotate left)
-            0x2A => self.rol(mem, &OpInfo{mode: Accumulator, bytes: 1, cycles: 2}),
-            0x26 => self.rol(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 5}),
-            0x36 => self.rol(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 6}),
-            0x2E => self.rol(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 6}),
-            0x3E => self.rol(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 7}),
-
-            // ROR (rotate right)
-            0x6A => self.ror(mem, &OpInfo{mode: Accumulator, bytes: 1, cycles: 2}),
-            0x66 => self.ror(mem, &OpInfo{mode: ZeroPage, bytes: 2, cycles: 5}),
-            0x76 => self.ror(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 6}),
-            0x6E => self.ror(mem, &OpInfo{mode: Absolute, bytes: 3, cycles: 6}),
-            0x7E => self.ror(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 7}),
-
-            // RTI (return from interrupt)
-            0x40 => self.rti(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 6}),
-
-            // SBC (subtract with carry)
-            0xE9 => self.sbc(mem, &OpInfo{mode: Immediate, bytes: 2, cycles: 2}),
-            0xE5 => self.sbc(mem, &OpInfo{mode: ZeroPage,  bytes: 2, cycles: 3}),
-            0xF5 => self.sbc(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0xED => self.sbc(mem, &OpInfo{mode: Absolute,  bytes: 3, cycles: 4}),
-            0xFD => self.sbc(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 4}),
-            0xF9 => self.sbc(mem, &OpInfo{mode: AbsoluteY, bytes: 3, cycles: 4}),
-            0xE1 => self.sbc(mem, &OpInfo{mode: IndexedIndirect, bytes: 2, cycles: 6}),
-            0xF1 => self.sbc(mem, &OpInfo{mode: IndirectIndexed, bytes: 2, cycles: 5}),
-
-            // SEC (set carry flag)
-            0x38 => self.sec(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // SED (set decimal flag)
-            0xF8 => self.sed(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // SEI (set interrupt disable flag)
-            0x78 => self.sei(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // STA (store accumulator)
-            0x85 => self.sta(mem, &OpInfo{mode: ZeroPage,  bytes: 2, cycles: 3}),
-            0x95 => self.sta(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0x8D => self.sta(mem, &OpInfo{mode: Absolute,  bytes: 3, cycles: 4}),
-            0x9D => self.sta(mem, &OpInfo{mode: AbsoluteX, bytes: 3, cycles: 5}),
-            0x99 => self.sta(mem, &OpInfo{mode: AbsoluteY, bytes: 3, cycles: 5}),
-            0x81 => self.sta(mem, &OpInfo{mode: IndexedIndirect, bytes: 2, cycles: 6}),
-            0x91 => self.sta(mem, &OpInfo{mode: IndirectIndexed, bytes: 2, cycles: 6}),
-
-            // STX (store x register)
-            0x86 => self.stx(mem, &OpInfo{mode: ZeroPage,  bytes: 2, cycles: 3}),
-            0x96 => self.stx(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0x8E => self.stx(mem, &OpInfo{mode: Absolute,  bytes: 3, cycles: 4}),
-
-            // STY (store y register)
-            0x84 => self.sty(mem, &OpInfo{mode: ZeroPage,  bytes: 2, cycles: 3}),
-            0x94 => self.sty(mem, &OpInfo{mode: ZeroPageX, bytes: 2, cycles: 4}),
-            0x8C => self.sty(mem, &OpInfo{mode: Absolute,  bytes: 3, cycles: 4}),
-
-            // TAX (transfer accumulator to x register)
-            0xAA => self.tax(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // TAY (transfer accumulator to y register)
-            0xA8 => self.tay(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // TSX (transfer stack pointer to x register)
-            0xBA => self.tsx(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // TXA (transfer x register to accumulator)
-            0x8A => self.txa(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // TXS (transfer x register to stack pointer)
-            0x9A => self.txs(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // TYA (transfer y register to accumulator)
-            0x98 => self.tya(mem, &OpInfo{mode: Implicit, bytes: 1, cycles: 2}),
-
-            // TODO: more remaining optcodes
-            _ => panic!("not implemented"),
+    ///
+    /// The opcode selects both the handler from `JUMP_TABLE` and its metadata
+    /// from `OP_INFO`, or from their 65C02 counterparts. Every bus access of
+    /// the handler takes one cycle, so the cycles add up to the counts in
+    /// `OP_INFO` including all penalties.
+    ///
+    /// A failure recorded by the handler halts the CPU and is returned.
+    fn execute_next(&mut self, bus: &mut dyn Bus) -> Result<(), CpuError> {
+        let start = self.cycles;
+        let pc = self.program_counter;
+        let opcode = self.read_as(bus, pc, Access::Execute) as usize;
+        let opi = &opinfo::op_info(self.variant)[opcode];
+        if self.strict && !opi.official {
+            // only the opcode was fetched
+            self.fault(CpuErrorKind::IllegalOpcode);
+            return self.take_fault(pc, opcode as u8);
+        }
+        if self.lint.is_some() {
+            self.lint_instruction(bus, pc, opcode as u8);
+        }
+        let hooked = self.has_hooks();
+        if hooked {
+            self.notify_before(bus, pc, opcode as u8, opi);
+        }
+        let table = match self.variant {
+            Variant::Cmos65C02 => &JUMP_TABLE_65C02,
+            _ => &JUMP_TABLE,
         };
+
+        // one byte instructions read the following byte and discard it,
+        // except for the single cycle NOPs of the 65C02
+        if opi.bytes == 1 && opi.cycles > 1 {
+            self.dummy_read(bus, pc.wrapping_add(1));
+        }
+
+        self.page_crossed = false;
+        self.operands = Some((pc.wrapping_add(1), opi.bytes - 1));
+        table[opcode](self, bus, opi);
+        self.operands = None;
+
+        if hooked {
+            let cycles = self.cycles - start;
+            self.notify_after(cycles);
+        }
+        self.take_fault(pc, opcode as u8)
+    }
+
+    /// Halt with the fault of the instruction at `pc`, if any.
+    fn take_fault(&mut self, pc: u16, opcode: u8) -> Result<(), CpuError> {
+        match self.fault.take() {
+            Some(kind) => {
+                let error = CpuError { pc, opcode, kind };
+                self.halted = true;
+                self.error = Some(error);
+                Err(error)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Fail the instruction being executed. The handler finishes, then
+    /// `step` halts the CPU and returns the error.
+    fn fault(&mut self, kind: CpuErrorKind) {
+        if self.fault.is_none() {
+            self.fault = Some(kind);
+        }
+    }
+
+    /// Move the program counter past the instruction `opi`.
+    fn advance(&mut self, opi: &OpInfo) {
+        self.program_counter = self.program_counter.wrapping_add(opi.bytes as u16);
     }
 
     /// CPU instruction: ADC (add with carry)
@@ -538,37 +1084,142 @@ impl CPU {
     /// - zero flag: set if accumulator is zero
     /// - negative flag: set if bit 7 (highest bit) is set
     /// - overflow flag: set if sign bit is incorrect
-    fn adc(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
+    fn adc(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.operand_address(bus, opi);
+        let m = self.read(bus, addr);
+
+        self.add(m);
+        self.decimal_cycle(bus, addr);
+
+        self.advance(opi);
+    }
+
+    /// Add `m` and the carry flag to the accumulator, in decimal mode if the
+    /// decimal flag is set and the variant supports it. Shared by ADC and RRA.
+    fn add(&mut self, m: u8) {
+        if self.status_register.decimal_mode && self.variant.has_decimal_mode() {
+            self.add_decimal(m);
+        } else {
+            self.add_with_carry(m);
+        }
+    }
+
+    /// Subtract `m` and the inverted carry flag from the accumulator, in
+    /// decimal mode if the decimal flag is set and the variant supports it.
+    /// Shared by SBC and ISC.
+    fn subtract(&mut self, m: u8) {
+        if self.status_register.decimal_mode && self.variant.has_decimal_mode() {
+            self.subtract_decimal(m);
+        } else {
+            // A - M - (1 - C) == A + !M + C in two's complement
+            self.add_with_carry(!m);
+        }
+    }
+
+    /// The 65C02 spends an extra cycle on ADC and SBC in decimal mode, in
+    /// which it reads the operand address again.
+    fn decimal_cycle(&mut self, bus: &mut dyn Bus, addr: u16) {
+        if self.status_register.decimal_mode && self.variant == Variant::Cmos65C02 {
+            self.dummy_read(bus, addr);
+        }
+    }
 
+    /// BCD addition of `m` and the carry flag to the accumulator.
+    ///
+    /// The NMOS 6502 sets the zero flag from the binary sum and the negative
+    /// and overflow flags from the intermediate result before the high digit
+    /// is adjusted. The 65C02 sets negative and zero from the final result.
+    /// Invalid BCD operands give the same results as on the real chips.
+    fn add_decimal(&mut self, m: u8) {
         let a = self.accumulator;
         let c = self.carry_flag();
 
-        // this might overflow
-        self.accumulator = a + m + c;
+        let mut lo = (a & 0x0F) + (m & 0x0F) + c;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let mut r = (a & 0xF0) as u16 + (m & 0xF0) as u16 + lo as u16;
+        let signed = (a & 0xF0) as i8 as i16 + (m & 0xF0) as i8 as i16 + lo as i16;
+        let intermediate = r as u8;
 
-        // set carry flag
-        if a as usize + m as usize + c as usize > 0xFF {
-            self.status_register.carry_flag = true;
+        if r >= 0xA0 {
+            r += 0x60;
+        }
+        self.accumulator = r as u8;
+        self.status_register.carry_flag = r >= 0x100;
+        self.status_register.overflow_flag = !(-128..=127).contains(&signed);
+
+        if self.variant == Variant::Cmos65C02 {
+            let a = self.accumulator;
+            self.update_zero_flag(a);
+            self.update_negative_flag(a);
         } else {
-            self.status_register.carry_flag = false;
+            self.update_zero_flag(a.wrapping_add(m).wrapping_add(c));
+            self.update_negative_flag(intermediate);
         }
+    }
+
+    /// BCD subtraction of `m` and the inverted carry flag from the
+    /// accumulator.
+    ///
+    /// Carry and overflow are those of the binary subtraction on all
+    /// variants. The NMOS 6502 also keeps the binary negative and zero flags,
+    /// the 65C02 sets them from the result.
+    fn subtract_decimal(&mut self, m: u8) {
+        let a = self.accumulator;
+        let c = self.carry_flag() as i16;
+
+        // sets the flags of the binary subtraction
+        self.add_with_carry(!m);
 
-        self.status_register.overflow_flag = utils::calculate_overflow_bit(a, m, self.accumulator);
+        let lo = (a & 0x0F) as i16 - (m & 0x0F) as i16 + c - 1;
+        let r = if self.variant == Variant::Cmos65C02 {
+            let mut r = a as i16 - m as i16 + c - 1;
+            if r < 0 {
+                r -= 0x60;
+            }
+            if lo < 0 {
+                r -= 0x06;
+            }
+            r
+        } else {
+            let lo = if lo < 0 { ((lo - 0x06) & 0x0F) - 0x10 } else { lo };
+            let r = (a & 0xF0) as i16 - (m & 0xF0) as i16 + lo;
+            if r < 0 {
+                r - 0x60
+            } else {
+                r
+            }
+        };
+        self.accumulator = r as u8;
+
+        if self.variant == Variant::Cmos65C02 {
+            let a = self.accumulator;
+            self.update_zero_flag(a);
+            self.update_negative_flag(a);
+        }
+    }
 
+    /// Binary addition of `m` and the carry flag to the accumulator. Shared by
+    /// ADC and SBC, the latter adds the one's complement of its operand.
+    fn add_with_carry(&mut self, m: u8) {
         let a = self.accumulator;
-        self.update_zero_flag(a);
-        self.update_negative_flag(a);
+        let sum = a as u16 + m as u16 + self.carry_flag() as u16;
+        let r = sum as u8;
+
+        self.accumulator = r;
+        self.status_register.carry_flag = sum > 0xFF;
+        self.status_register.overflow_flag = utils::calculate_overflow_bit(a, m, r);
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
     }
 
     /// CPU instruction: AND (logical AND)
     ///
     /// A logical AND is performed, bit by bit, on the accumulator contents
     /// using the contents of a byte of memory.
-    fn and(&mut self, mem: &mut Memory, mode: AddressingMode) {
-        let addr = self.get_address(mem, mode).unwrap();
-        let m = mem.read(addr);
+    fn and(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
         let a = self.accumulator;
 
         self.accumulator = a & m;
@@ -576,6 +1227,8 @@ impl CPU {
         let a = self.accumulator;
         self.update_zero_flag(a);
         self.update_negative_flag(a);
+
+        self.advance(opi);
     }
 
     /// CPU instruction: ASL (arithmetic shift left)
@@ -585,24 +1238,99 @@ impl CPU {
     /// carry flag. The effect of this operation is to multiply the memory
     /// contents by 2 (ignoring 2's complement considerations), setting the
     /// carry if the result will not fit in 8 bits.
-    fn asl(&mut self, mem: &mut Memory, mode: AddressingMode) {
-        match mode {
+    fn asl(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.read_modify_write(bus, opi, CPU::shift_left);
+
+        self.advance(opi);
+    }
+
+    /// Apply `op` to the accumulator or to the memory operand, depending on
+    /// the addressing mode. Shared by the shift, rotate, increment and
+    /// decrement instructions.
+    fn read_modify_write<F>(&mut self, bus: &mut dyn Bus, opi: &OpInfo, op: F) -> u8
+    where
+        F: FnOnce(&mut CPU, u8) -> u8,
+    {
+        match opi.mode {
             AddressingMode::Accumulator => {
-                let (v, c) = self.accumulator.overflowing_shl(1);
-                self.accumulator = v;
-                self.status_register.carry_flag = c;
+                let a = self.accumulator;
+                self.accumulator = op(self, a);
+                self.accumulator
             }
             _ => {
-                let addr = self.get_address(mem, mode).unwrap();
-                let m = mem.read(addr);
-                let (v, c) = m.overflowing_shl(1);
-                self.status_register.carry_flag = c;
+                // the NMOS 6502 writes the unmodified value back while the
+                // operation is carried out, the 65C02 reads it again
+                let addr = self.operand_address(bus, opi);
+                let m = self.read(bus, addr);
+                if self.variant == Variant::Cmos65C02 {
+                    self.dummy_read(bus, addr);
+                } else {
+                    self.dummy_write(bus, addr, m);
+                }
+                let r = op(self, m);
+
+                self.write(bus, addr, r);
+                r
             }
         }
+    }
 
-        let a = self.accumulator;
-        self.update_zero_flag(a);
-        self.update_negative_flag(a);
+    /// Add one to `v`.
+    fn increment(&mut self, v: u8) -> u8 {
+        let r = v.wrapping_add(1);
+
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+        r
+    }
+
+    /// Subtract one from `v`.
+    fn decrement(&mut self, v: u8) -> u8 {
+        let r = v.wrapping_sub(1);
+
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+        r
+    }
+
+    /// Shift `v` one bit left, bit 7 goes into the carry flag.
+    fn shift_left(&mut self, v: u8) -> u8 {
+        let r = v << 1;
+
+        self.status_register.carry_flag = v & 0x80 == 0x80;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+        r
+    }
+
+    /// Shift `v` one bit right, bit 0 goes into the carry flag.
+    fn shift_right(&mut self, v: u8) -> u8 {
+        let r = v >> 1;
+
+        self.status_register.carry_flag = v & 0x01 == 0x01;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+        r
+    }
+
+    /// Rotate `v` one bit left through the carry flag.
+    fn rotate_left(&mut self, v: u8) -> u8 {
+        let r = (v << 1) | self.carry_flag();
+
+        self.status_register.carry_flag = v & 0x80 == 0x80;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+        r
+    }
+
+    /// Rotate `v` one bit right through the carry flag.
+    fn rotate_right(&mut self, v: u8) -> u8 {
+        let r = (v >> 1) | (self.carry_flag() << 7);
+
+        self.status_register.carry_flag = v & 0x01 == 0x01;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+        r
     }
 
     /// CPU instruction: BCC (branch if carry clear)
@@ -611,27 +1339,27 @@ impl CPU {
     /// program counter to cause a branch to a new location.
     ///
     /// TODO: test
-    fn bcc(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let condition = self.status_register.carry_flag == false;
-        self.conditional_branch(mem, opi, condition);
+    fn bcc(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let condition = !self.status_register.carry_flag;
+        self.conditional_branch(bus, opi, condition);
     }
 
     /// CPU instruction: BCS (branch if carry set)
     ///
     /// If the carry flag is set then add the relative displacement to the
     /// program counter to cause a branch to a new location.
-    fn bcs(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn bcs(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
         let condition = self.status_register.carry_flag;
-        self.conditional_branch(mem, opi, condition);
+        self.conditional_branch(bus, opi, condition);
     }
 
     /// CPU instruction: BEQ (branch if equal)
     ///
     /// If the zero flag is set then add the relative displacement to the
     /// program counter to cause a branch to a new location.
-    fn beq(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn beq(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
         let condition = self.status_register.zero_flag;
-        self.conditional_branch(mem, opi, condition);
+        self.conditional_branch(bus, opi, condition);
     }
 
     /// CPU instruction: BIT (bit test)
@@ -640,71 +1368,75 @@ impl CPU {
     /// target memory location. The mask pattern in A is ANDed with the value
     /// in memory to set or clear the zero flag, but the result is not kept.
     /// Bits 7 and 6 of the value from memory are copied into the N and V flags.
-    fn bit(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
+    ///
+    /// The immediate mode of the 65C02 only affects the zero flag.
+    fn bit(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
         let res = m & self.accumulator;
 
-        if res == 0x00 {
-            self.status_register.zero_flag = true;
+        self.status_register.zero_flag = res == 0x00;
+        if opi.mode != AddressingMode::Immediate {
+            self.status_register.negative_flag = m >> 7 & 0x01 == 0x01;
+            self.status_register.overflow_flag = m >> 6 & 0x01 == 0x01;
         }
 
-        if m >> 7 & 0x01 == 0x01 {
-            self.status_register.negative_flag = true;
-        }
-
-        if m >> 6 & 0x01 == 0x01 {
-            self.status_register.overflow_flag = true;
-        }
-
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
-    fn conditional_branch(&mut self, mem: &mut Memory, opi: &OpInfo, condition: bool) {
+    /// Common part of all branch instructions. The displacement is always
+    /// fetched, a taken branch spends another cycle to add it to the low byte
+    /// of the program counter and one more if the high byte has to be fixed.
+    fn conditional_branch(&mut self, bus: &mut dyn Bus, opi: &OpInfo, condition: bool) {
+        let addr = self.address(bus, opi.mode);
+        let next = self.program_counter.wrapping_add(opi.bytes as u16);
+
         if condition {
-            let addr = self.get_address(mem, opi.mode).unwrap();
-            let page_crossed = memory::page_crossed(self.program_counter, addr);
-            self.cycles += 1;
+            // a taken branch which stays on the page does not poll during
+            // its last cycle, an interrupt first seen in that cycle is
+            // serviced one instruction later
+            if self.irq_sampled && !self.irq_sampled_prev {
+                self.irq_sampled = false;
+            }
+            if self.nmi_sampled && !self.nmi_sampled_prev {
+                self.nmi_sampled = false;
+            }
+            self.dummy_read(bus, next);
 
-            // add 2 cycles if the target address is on a new page
-            if page_crossed {
-                self.cycles += 2;
+            if memory::page_crossed(next, addr) {
+                self.dummy_read(bus, (next & 0xFF00) | (addr & 0x00FF));
             }
 
             self.program_counter = addr;
         } else {
-            self.program_counter += opi.bytes as u16;
+            self.program_counter = next;
         }
-
-        self.cycles += opi.cycles;
     }
 
     /// CPU instruction: BMI (branch if minus)
     ///
     /// If the negative flag is set then add the relative displacement to the
     /// program counter to cause a branch to a new location.
-    fn bmi(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn bmi(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
         let condition = self.status_register.negative_flag;
-        self.conditional_branch(mem, opi, condition);
+        self.conditional_branch(bus, opi, condition);
     }
 
     /// CPU instruction: BNE (branch if not equal)
     ///
     /// If the zero flag is clear then add the relative displacement to the
     /// program counter to cause a branch to a new location.
-    fn bne(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let condition = self.status_register.zero_flag == false;
-        self.conditional_branch(mem, opi, condition);
+    fn bne(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let condition = !self.status_register.zero_flag;
+        self.conditional_branch(bus, opi, condition);
     }
 
     /// CPU instruction: BPL (branch if positive)
     ///
     /// If the negative flag is clear then add the relative displacement to
     /// the program counter to cause a branch to a new location.
-    fn bpl(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let condition = self.status_register.negative_flag == false;
-        self.conditional_branch(mem, opi, condition);
+    fn bpl(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let condition = !self.status_register.negative_flag;
+        self.conditional_branch(bus, opi, condition);
     }
 
     /// CPU instruction: BRK (force interrupt)
@@ -712,201 +1444,164 @@ impl CPU {
     /// The BRK instruction forces the generation of an interrupt request. The
     /// program counter and processor status are pushed on the stack then the
     /// IRQ interrupt vector at $FFFE/F is loaded into the PC and the break
-    /// flag in the status set to one.
-    fn brk(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let sr = self.status_register.to_u8();
-        self.push(mem, sr);
-        let pc = self.program_counter;
-        self.push16(mem, pc);
-
-        // TODO: load interrupt vector
-        self.status_register.break_command = true;
+    /// flag in the pushed status set to one.
+    ///
+    /// BRK skips the byte following the opcode, the pushed return address is
+    /// the address of the BRK plus two. Like the hardware interrupts it does
+    /// not poll, the first instruction of the handler is always executed.
+    fn brk(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let return_address = self.program_counter.wrapping_add(opi.bytes as u16 + 1);
+        self.enter_interrupt(bus, return_address, true, IRQ_VECTOR);
     }
 
     /// CPU instruction: BVC (branch if overflow clear)
     ///
     /// If the overflow flag is clear then add the relative displacement to the
     /// program counter to cause a branch to a new location.
-    fn bvc(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let condition = self.status_register.overflow_flag == false;
-        self.conditional_branch(mem, opi, condition);
+    fn bvc(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let condition = !self.status_register.overflow_flag;
+        self.conditional_branch(bus, opi, condition);
     }
 
     /// CPU instruction: BVS (branch if overflow set)
     ///
     /// If the overflow flag is set then add the relative displacement to the
     /// program counter to cause a branch to a new location.
-    fn bvs(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn bvs(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
         let condition = self.status_register.overflow_flag;
-        self.conditional_branch(mem, opi, condition);
+        self.conditional_branch(bus, opi, condition);
     }
 
     /// CPU instruction: CLC (clear carry flag)
     ///
     /// Set the carry flag to zero.
-    fn clc(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn clc(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         self.status_register.carry_flag = false;
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: CLD (clear decimal mode)
     ///
     /// Sets the decimal mode flag to zero.
-    fn cld(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn cld(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         self.status_register.decimal_mode = false;
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: CLI (clear interrupt disable)
     ///
     /// Clears the interrupt disable flag allowing normal interrupt requests to
     /// be serviced.
-    fn cli(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    ///
+    /// The poll happens before the flag changes, so a pending IRQ is only
+    /// serviced after the next instruction.
+    fn cli(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         self.status_register.interrupt_disable = false;
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: CLV (clear overflow flag)
     ///
     /// Clears the overflow flag.
-    fn clv(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn clv(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         self.status_register.overflow_flag = false;
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: CMP (compare)
     ///
     /// This instruction compares the contents of the accumulator with another
     /// memory held value and sets the zero and carry flags as appropriate.
-    ///
-    /// TODO: only one opcode tested
-    fn cmp(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
-
-        let r = self.accumulator.wrapping_sub(m);
-        self.update_negative_flag(r);
+    fn cmp(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
+        let a = self.accumulator;
 
-        if self.accumulator > m {
-            self.status_register.carry_flag = true;
-        } else if self.accumulator == m {
-            self.status_register.carry_flag = true;
-            self.status_register.zero_flag = true;
-        }
+        self.compare(a, m);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction CPX (compare x register)
     ///
     /// This instruction compares the contents of the X register with another
     /// memory held value and sets the zero and carry flags as appropriate.
-    ///
-    /// TODO: tests
-    /// TODO: refactor: pull duplicate code out
-    fn cpx(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
-
-        let r = self.accumulator.wrapping_sub(m);
-        self.update_negative_flag(r);
+    fn cpx(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
+        let x = self.index_x;
 
-        if self.index_x > m {
-            self.status_register.carry_flag = true;
-        } else if self.index_x == m {
-            self.status_register.carry_flag = true;
-            self.status_register.zero_flag = true;
-        }
+        self.compare(x, m);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: CPY (compare y register)
     ///
     /// This instruction compares the contents of the Y register with another
     /// memory held value and sets the zero and carry flags as appropriate.
-    ///
-    /// TODO: tests
-    /// TODO: refactor: pull duplicate code out
-    fn cpy(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
+    fn cpy(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
+        let y = self.index_y;
 
-        let r = self.accumulator.wrapping_sub(m);
-        self.update_negative_flag(r);
+        self.compare(y, m);
 
-        if self.index_y > m {
-            self.status_register.carry_flag = true;
-        } else if self.index_y == m {
-            self.status_register.carry_flag = true;
-            self.status_register.zero_flag = true;
-        }
+        self.advance(opi);
+    }
+
+    /// Common flag logic of CMP, CPX and CPY. The carry is set if the register
+    /// is greater than or equal to the memory value, the negative flag is
+    /// taken from the difference of both.
+    fn compare(&mut self, reg: u8, m: u8) {
+        let r = reg.wrapping_sub(m);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.status_register.carry_flag = reg >= m;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
     }
 
     /// CPU instruction: DEC (decrememt memory)
     ///
     /// Subtracts one from the value held at a specified memory location
     /// setting the zero and negative flags as appropriate.
-    fn dec(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
-        let r = m.wrapping_sub(1);
-
-        mem.write(addr, r);
-
-        self.update_negative_flag(r);
-        self.update_zero_flag(r);
+    fn dec(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.read_modify_write(bus, opi, CPU::decrement);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: DEX (decrement x register)
     ///
     /// Subtracts one from the X register setting the zero and negative flags
     /// as appropriate.
-    fn dex(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn dex(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.index_x.wrapping_sub(1);
 
         self.index_x = r;
         self.update_negative_flag(r);
         self.update_zero_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: DEY (decrement y register)
     ///
     /// Subtracts one from the Y register setting the zero and negative flags
     /// as appropriate.
-    fn dey(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn dey(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.index_y.wrapping_sub(1);
 
         self.index_y = r;
         self.update_negative_flag(r);
         self.update_zero_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: EOR (exclusive or)
     ///
     /// An exclusive OR is performed, bit by bit, on the accumulator contents
     /// using the contents of a byte of memory.
-    fn eor(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
+    fn eor(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
         let r = self.accumulator ^ m;
         self.accumulator = r;
@@ -914,173 +1609,151 @@ impl CPU {
         self.update_zero_flag(r);
         self.update_negative_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: INC (increment memory)
     ///
     /// Adds one to the value held at a specified memory location setting the
     /// zero and negative flags as appropriate.
-    fn inc(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
-        let r = m.wrapping_add(1);
+    fn inc(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.read_modify_write(bus, opi, CPU::increment);
 
-        mem.write(addr, r);
-
-        self.update_negative_flag(r);
-        self.update_zero_flag(r);
-
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: INX (increment x register)
     ///
     /// Adds one to the X register setting the zero and negative flags as
     /// appropriate.
-    fn inx(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn inx(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.index_x.wrapping_add(1);
 
         self.index_x = r;
         self.update_negative_flag(r);
         self.update_zero_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: INY (increment x register)
     ///
     /// Adds one to the Y register setting the zero and negative flags as
     /// appropriate.
-    fn iny(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn iny(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.index_y.wrapping_add(1);
 
         self.index_y = r;
         self.update_negative_flag(r);
         self.update_zero_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: JMP (jump)
     ///
     /// Sets the program counter to the address specified by the operand.
-    fn jmp(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
+    fn jmp(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.address(bus, opi.mode);
 
-        self.program_counter = self.read16(mem, addr);
-
-        self.cycles += opi.cycles;
+        self.program_counter = addr;
     }
 
     /// CPU instruction: JSR (jump to subroutine)
     ///
     /// The JSR instruction pushes the address (minus one) of the return point
     /// on to the stack and then sets the program counter to the target memory address.
-    fn jsr(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let return_address = self.program_counter - 1;
-        self.push16(mem, return_address);
+    ///
+    /// The high byte of the target is only fetched after the return address
+    /// was pushed, so JSR does not use `get_address`.
+    fn jsr(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let caller = self.program_counter;
+        let operand = caller.wrapping_add(1);
+        let lo = self.read(bus, operand) as u16;
+        self.stack_dummy_read(bus);
 
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        self.program_counter = self.read16(mem, addr);
+        // the return address points to the last byte of the JSR instruction
+        let return_address = caller.wrapping_add(opi.bytes as u16 - 1);
+        let sp = self.stack_pointer;
+        self.push16(bus, return_address);
 
-        self.cycles += opi.cycles;
+        let hi = self.read(bus, return_address) as u16;
+        self.program_counter = (hi << 8) | lo;
+        self.enter_frame(FrameKind::Subroutine, caller, return_address.wrapping_add(1), sp);
     }
 
     /// CPU instruction: LDA (load accumulator)
     ///
     /// Loads a byte of memory into the accumulator setting the zero and
     /// negative flags as appropriate.
-    fn lda(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
+    fn lda(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
         self.accumulator = m;
         self.update_zero_flag(m);
         self.update_negative_flag(m);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: LDX (load x register)
     ///
     /// Loads a byte of memory into the X register setting the zero and
     /// negative flags as appropriate.
-    fn ldx(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
+    fn ldx(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
         self.index_x = m;
         self.update_zero_flag(m);
         self.update_negative_flag(m);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: LDY (load y register)
     ///
     /// Loads a byte of memory into the Y register setting the zero and
     /// negative flags as appropriate.
-    fn ldy(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
+    fn ldy(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
         self.index_y = m;
         self.update_zero_flag(m);
         self.update_negative_flag(m);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: LSR (logical shift left)
     ///
     /// Each of the bits in A or M is shift one place to the right. The bit
     /// that was in bit 0 is shifted into the carry flag. Bit 7 is set to zero.
-    fn lsr(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        match opi.mode {
-            AddressingMode::Accumulator => {
-                self.status_register.carry_flag = (self.accumulator & 0x01) == 0x01;
-                let r = self.accumulator >> 1;
-                self.update_negative_flag(r);
-                self.accumulator = r;
-            }
-            _ => {
-                let addr = self.get_address(mem, opi.mode).unwrap();
-                let m = mem.read(addr);
-
-                let r = m >> 1;
-                self.update_negative_flag(r);
-                mem.write(addr, r);
-            }
-        }
+    fn lsr(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.read_modify_write(bus, opi, CPU::shift_right);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: NOP (no operation)
     ///
     /// The NOP instruction causes no changes to the processor other than the
     /// normal incrementing of the program counter to the next instruction.
-    fn nop(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+    ///
+    /// The unofficial NOPs with an operand still read it from memory.
+    fn nop(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        if opi.mode != AddressingMode::Implicit {
+            self.read_operand(bus, opi);
+        }
+
+        self.advance(opi);
     }
 
     /// CPU instruction: ORA (logical inclusive or)
     ///
     /// An inclusive OR is performed, bit by bit, on the accumulator contents
     /// using the contents of a byte of memory.
-    fn ora(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
+    fn ora(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
         let r = self.accumulator | m;
         self.accumulator = r;
@@ -1088,55 +1761,56 @@ impl CPU {
         self.update_zero_flag(r);
         self.update_negative_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: PHA (push accumulator)
     ///
     /// Pushes a copy of the accumulator on to the stack.
-    fn pha(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn pha(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
         let a = self.accumulator;
-        self.push(mem, a);
+        self.push(bus, a);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: PHP (push processor status)
     ///
-    /// Pushes a copy of the status flags on to the stack.
-    fn php(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let p = self.status_register.to_u8();
-        self.push(mem, p);
+    /// Pushes a copy of the status flags on to the stack. The break and the
+    /// unused bit are always set in the pushed copy.
+    fn php(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let p = self.status_register.to_u8() | 0x30;
+        self.push(bus, p);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: PLA (pull accumulator)
     ///
     /// Pulls an 8 bit value from the stack and into the accumulator. The zero
     /// and negative flags are set as appropriate.
-    fn pla(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let a = self.pop(mem);
+    fn pla(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.stack_dummy_read(bus);
+        let a = self.pop(bus);
         self.accumulator = a;
         self.update_zero_flag(a);
+        self.update_negative_flag(a);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: PLP (pull status register)
     ///
     /// Pulls an 8 bit value from the stack and into the processor flags. The
     /// flags will take on new states as determined by the value pulled.
-    fn plp(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let p = StatusRegister::from_u8(self.pop(mem));
-        self.status_register = p;
+    ///
+    /// Like CLI and SEI the interrupt disable flag changes after the poll.
+    fn plp(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.stack_dummy_read(bus);
+        let p = self.pop(bus);
+        self.pull_status_register(p);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: ROL (rotate left)
@@ -1144,29 +1818,10 @@ impl CPU {
     /// Move each of the bits in either A or M one place to the left. Bit 0 is
     /// filled with the current value of the carry flag whilst the old bit 7
     /// becomes the new carry flag value.
-    fn rol(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let (r, c) = match opi.mode {
-            AddressingMode::Accumulator => {
-                let (r, c) = self.accumulator.overflowing_shl(1);
-
-                self.accumulator = r;
-                (r, c)
-            }
-            _ => {
-                let addr = self.get_address(mem, opi.mode).unwrap();
-                let (r, c) = mem.read(addr).overflowing_shl(1);
-
-                mem.write(addr, r);
-                (r, c)
-            }
-        };
-
-        self.status_register.carry_flag = c;
-        self.update_negative_flag(r);
-        self.update_zero_flag(r);
+    fn rol(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.read_modify_write(bus, opi, CPU::rotate_left);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: ROR (rotate right)
@@ -1174,29 +1829,10 @@ impl CPU {
     /// Move each of the bits in either A or M one place to the right. Bit 7 is
     /// filled with the current value of the carry flag whilst the old bit 0
     /// becomes the new carry flag value.
-    fn ror(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let (r, c) = match opi.mode {
-            AddressingMode::Accumulator => {
-                let (r, c) = self.accumulator.overflowing_shr(1);
-
-                self.accumulator = r;
-                (r, c)
-            }
-            _ => {
-                let addr = self.get_address(mem, opi.mode).unwrap();
-                let (r, c) = mem.read(addr).overflowing_shr(1);
+    fn ror(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.read_modify_write(bus, opi, CPU::rotate_right);
 
-                mem.write(addr, r);
-                (r, c)
-            }
-        };
-
-        self.status_register.carry_flag = c;
-        self.update_negative_flag(r);
-        self.update_zero_flag(r);
-
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: RTI (return from interrupt)
@@ -1204,21 +1840,40 @@ impl CPU {
     /// The RTI instruction is used at the end of an interrupt processing
     /// routine. It pulls the processor flags from the stack followed by the
     /// program counter.
-    fn rti(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        self.status_register = StatusRegister::from_u8(self.pop(mem));
-        self.program_counter = self.pop16(mem);
+    fn rti(&mut self, bus: &mut dyn Bus, _opi: &OpInfo) {
+        self.stack_dummy_read(bus);
+        let p = self.pop(bus);
+        self.pull_status_register(p);
+        let pc = self.program_counter;
+        self.program_counter = self.pop16(bus);
+        let target = self.program_counter;
+        self.leave_frame(Return::Rti, pc, target);
+    }
 
-        self.cycles += opi.cycles;
+    /// Load the status register from a value pulled off the stack. The break
+    /// and the unused bit do not exist as flip-flops in the processor, so the
+    /// pulled values of these two bits are discarded.
+    fn pull_status_register(&mut self, p: u8) {
+        self.status_register = StatusRegister::from_u8((p & 0xCF) | 0x20);
     }
 
     /// CPU instruction: RTS (return from subroutine)
     ///
     /// The RTS instruction is used at the end of a subroutine to return to the
     /// calling routine. It pulls the program counter (minus one) from the stack.
-    fn rts(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        self.program_counter = self.pop16(mem) + 1;
+    ///
+    /// The pulled address is read once more while it is incremented.
+    fn rts(&mut self, bus: &mut dyn Bus, _opi: &OpInfo) {
+        self.stack_dummy_read(bus);
+        let addr = self.pop16(bus);
+        self.dummy_read(bus, addr);
 
-        self.cycles += opi.cycles;
+        let pc = self.program_counter;
+        self.program_counter = addr.wrapping_add(1);
+        self.leave_frame(Return::Rts, pc, addr.wrapping_add(1));
+        if self.calling.is_some() {
+            self.return_from_call(addr.wrapping_add(1));
+        }
     }
 
     /// CPU instruction: SBC (subtract with carry)
@@ -1227,321 +1882,754 @@ impl CPU {
     /// accumulator together with the not of the carry bit. If overflow occurs
     /// the carry bit is clear, this enables multiple byte subtraction to be
     /// performed.
-    fn sbc(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
-        let m = mem.read(addr);
-        let a = self.accumulator;
+    fn sbc(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.operand_address(bus, opi);
+        let m = self.read(bus, addr);
 
-        let r = self.accumulator - m - (1 - self.status_register.carry_flag as u8);
-        self.accumulator = r;
-
-        self.status_register.overflow_flag = utils::calculate_overflow_bit(a, m, r);
-        self.update_zero_flag(r);
-        self.update_negative_flag(r);
+        self.subtract(m);
+        self.decimal_cycle(bus, addr);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: SEC (set carry flag)
     ///
     /// Set the carry flag to one.
-    fn sec(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn sec(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         self.status_register.carry_flag = true;
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: SED (set decimal flag)
     ///
     /// Set the decimal mode flag to one.
-    fn sed(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn sed(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         self.status_register.decimal_mode = true;
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: SEI (set interrupt disable)
     ///
     /// Set the interrupt disable flag to one.
-    fn sei(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    ///
+    /// The poll happens before the flag changes, so an IRQ can still be
+    /// serviced right after SEI.
+    fn sei(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         self.status_register.interrupt_disable = true;
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: STA (store accumulator)
     ///
     /// Stores the contents of the accumulator into memory.
-    fn sta(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
+    fn sta(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.operand_address(bus, opi);
+        let v = self.accumulator;
 
-        mem.write(addr, self.accumulator);
+        self.write(bus, addr, v);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: STX (store x register)
     ///
     /// Stores the contents of the X register into memory.
-    fn stx(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
+    fn stx(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.operand_address(bus, opi);
+        let v = self.index_x;
 
-        mem.write(addr, self.index_x);
+        self.write(bus, addr, v);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: STY (store y register)
     ///
     /// Stores the contents of the Y register into memory.
-    fn sty(&mut self, mem: &mut Memory, opi: &OpInfo) {
-        let addr = self.get_address(mem, opi.mode).unwrap();
+    fn sty(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.operand_address(bus, opi);
+        let v = self.index_y;
 
-        mem.write(addr, self.index_y);
+        self.write(bus, addr, v);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: TAX (transfer accumulator to x register)
     ///
     /// Copies the current contents of the accumulator into the X register and
     /// sets the zero and negative flags as appropriate.
-    fn tax(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn tax(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.accumulator;
 
         self.index_x = r;
         self.update_zero_flag(r);
         self.update_negative_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: TAY (transfer accumulator to y register)
     ///
     /// Copies the current contents of the accumulator into the Y register and
     /// sets the zero and negative flags as appropriate.
-    fn tay(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn tay(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.accumulator;
 
         self.index_y = r;
         self.update_zero_flag(r);
         self.update_negative_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: TSX (transfer stack pointer to x register)
     ///
     /// Copies the current contents of the stack register into the X register
     /// and sets the zero and negative flags as appropriate.
-    fn tsx(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn tsx(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.stack_pointer;
 
         self.index_x = r;
         self.update_zero_flag(r);
         self.update_negative_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: TXA (transfer x register to accumulator)
     ///
     /// Copies the current contents of the X register into the accumulator and
     /// sets the zero and negative flags as appropriate.
-    fn txa(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn txa(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.index_x;
 
         self.accumulator = r;
         self.update_zero_flag(r);
         self.update_negative_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: TXS (transfer x register to stack pointer)
     ///
     /// Copies the current contents of the X register into the stack register.
-    fn txs(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn txs(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         self.stack_pointer = self.index_x;
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
 
     /// CPU instruction: TYA (transfer y register to accumulator)
     ///
     /// Copies the current contents of the Y register into the accumulator and
     /// sets the zero and negative flags as appropriate.
-    fn tya(&mut self, mem: &mut Memory, opi: &OpInfo) {
+    fn tya(&mut self, _bus: &mut dyn Bus, opi: &OpInfo) {
         let r = self.index_y;
 
         self.accumulator = r;
         self.update_zero_flag(r);
         self.update_negative_flag(r);
 
-        self.cycles += opi.cycles;
-        self.program_counter += opi.bytes as u16;
+        self.advance(opi);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Unofficial instructions
+    //
+    // The NMOS 6502 does not trap undefined opcodes. Most of them combine two
+    // official instructions which share the decoded addressing mode, the
+    // descriptions follow the "NMOS 6510 Unintended Opcodes" document.
 
-    #[test]
-    fn cpu_powerup_state() {
-        let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+    /// CPU instruction: ALR (AND then LSR accumulator, unofficial)
+    fn alr(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
-        cpu.powerup(&mut mem);
+        let a = self.accumulator & m;
+        self.accumulator = self.shift_right(a);
 
-        assert_eq!(cpu.accumulator, 0);
-        assert_eq!(cpu.index_x, 0);
-        assert_eq!(cpu.index_y, 0);
-        assert_eq!(cpu.stack_pointer, 0xFD);
+        self.advance(opi);
+    }
 
-        assert_eq!(0x34, 52);
-        assert_eq!(cpu.status_register.unused_bit, true);
-        assert_eq!(cpu.status_register.break_command, true);
-        assert_eq!(cpu.status_register.interrupt_disable, true);
+    /// CPU instruction: ANC (AND with bit 7 copied into carry, unofficial)
+    fn anc(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
-        assert_eq!(cpu.status_register.carry_flag, false);
-        assert_eq!(cpu.status_register.zero_flag, false);
-        assert_eq!(cpu.status_register.decimal_mode, false);
-        assert_eq!(cpu.status_register.overflow_flag, false);
-        assert_eq!(cpu.status_register.negative_flag, false);
+        let r = self.accumulator & m;
+        self.accumulator = r;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+        self.status_register.carry_flag = self.status_register.negative_flag;
 
-        // memory
-        assert_eq!(mem.read(0x4017), 0);
+        self.advance(opi);
     }
 
-    #[test]
-    fn cpu_reset_state() {
-        let mut cpu = CPU::new();
-        let mut mem = Memory::new();
-        cpu.powerup(&mut mem);
-
-        cpu.status_register.interrupt_disable = false;
-        let sp_before = cpu.stack_pointer;
-        println!("{}", sp_before);
+    /// CPU instruction: ARR (AND then ROR accumulator, unofficial)
+    ///
+    /// The carry is taken from bit 6 of the result and the overflow flag is
+    /// bit 6 xor bit 5 of the result.
+    fn arr(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
-        cpu.reset(&mut mem);
+        let r = ((self.accumulator & m) >> 1) | (self.carry_flag() << 7);
+        self.accumulator = r;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+        self.status_register.carry_flag = r & 0x40 == 0x40;
+        self.status_register.overflow_flag = ((r >> 6) ^ (r >> 5)) & 0x01 == 0x01;
 
-        assert_eq!(cpu.stack_pointer, sp_before - 3);
-        assert_eq!(cpu.status_register.interrupt_disable, true);
-        assert_eq!(mem.read(0x4015), 0);
-        // TODO: test remaining memory addresses
+        self.advance(opi);
     }
 
-    #[test]
-    fn cpu_set_negative_true() {
-        let mut cpu = CPU::new();
+    /// CPU instruction: AXS (X = A AND X minus memory, unofficial)
+    ///
+    /// Also known as SBX. The subtraction ignores the carry flag and does not
+    /// affect the overflow flag, the carry is set like in CMP.
+    fn axs(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
-        cpu.accumulator = 0xFF;
-        assert_eq!(cpu.status_register.negative_flag, false);
-        let a = cpu.accumulator;
-        cpu.update_negative_flag(a);
-        assert_eq!(cpu.status_register.negative_flag, true);
+        let t = self.accumulator & self.index_x;
+        self.compare(t, m);
+        self.index_x = t.wrapping_sub(m);
+
+        self.advance(opi);
     }
 
-    #[test]
-    fn cpu_set_negative_false() {
-        let mut cpu = CPU::new();
+    /// CPU instruction: DCP (DEC then CMP, unofficial)
+    fn dcp(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let r = self.read_modify_write(bus, opi, CPU::decrement);
 
-        cpu.accumulator = 0x00;
-        assert_eq!(cpu.status_register.negative_flag, false);
-        let a = cpu.accumulator;
-        cpu.update_negative_flag(a);
-        assert_eq!(cpu.status_register.negative_flag, false);
-    }
+        let a = self.accumulator;
+        self.compare(a, r);
 
-    #[test]
-    fn test_addressing_absolute() {
-        let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        self.advance(opi);
+    }
 
-        cpu.powerup(&mut mem);
-        cpu.program_counter = 0x0000;
+    /// CPU instruction: ISC (INC then SBC, unofficial)
+    fn isc(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let r = self.read_modify_write(bus, opi, CPU::increment);
 
-        mem.write(0x0001, 0xFF);
-        mem.write(0x0002, 0xAA);
+        self.subtract(r);
 
-        let result = cpu.get_address(&mut mem, AddressingMode::Absolute);
-        let expected = Some(0xAAFF);
+        self.advance(opi);
+    }
 
-        assert_eq!(result, expected);
+    /// CPU instruction: JAM (halt the processor, unofficial)
+    ///
+    /// Also known as KIL. The processor locks up and only a reset brings it
+    /// back. The program counter keeps pointing to the JAM opcode.
+    fn jam(&mut self, _bus: &mut dyn Bus, _opi: &OpInfo) {
+        self.fault(CpuErrorKind::Jam);
     }
 
-    #[test]
-    fn test_addressing_absolute_x() {
-        let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+    /// CPU instruction: LAS (load A, X and S from memory AND S, unofficial)
+    fn las(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let r = self.read_operand(bus, opi) & self.stack_pointer;
 
-        cpu.powerup(&mut mem);
-        cpu.program_counter = 0x0000;
-        cpu.index_x = 0x02;
+        self.accumulator = r;
+        self.index_x = r;
+        self.stack_pointer = r;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
 
-        mem.write(0x0001, 0xBB);
-        mem.write(0x0002, 0xAA);
+        self.advance(opi);
+    }
 
-        let result = cpu.get_address(&mut mem, AddressingMode::AbsoluteX);
-        let expected = Some(0xAABD);
+    /// CPU instruction: LAX (LDA and LDX, unofficial)
+    fn lax(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
 
-        assert_eq!(result, expected);
+        self.accumulator = m;
+        self.index_x = m;
+        self.update_zero_flag(m);
+        self.update_negative_flag(m);
+
+        self.advance(opi);
     }
 
-    #[test]
-    fn test_addressing_absolute_y() {
-        let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+    /// CPU instruction: LXA (load A and X with immediate, unofficial)
+    ///
+    /// Unstable, the accumulator is ORed with a chip dependent constant
+    /// before the AND, see `UNSTABLE_MAGIC`.
+    fn lxa(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
+        let r = (self.accumulator | UNSTABLE_MAGIC) & m;
 
-        cpu.powerup(&mut mem);
-        cpu.program_counter = 0x0000;
-        cpu.index_y = 0x04;
+        self.accumulator = r;
+        self.index_x = r;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
 
-        mem.write(0x0001, 0xBB);
-        mem.write(0x0002, 0xAA);
+        self.advance(opi);
+    }
 
-        let result = cpu.get_address(&mut mem, AddressingMode::AbsoluteY);
-        let expected = Some(0xAABF);
+    /// CPU instruction: RLA (ROL then AND, unofficial)
+    fn rla(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_modify_write(bus, opi, CPU::rotate_left);
 
-        assert_eq!(result, expected);
+        let r = self.accumulator & m;
+        self.accumulator = r;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+
+        self.advance(opi);
     }
 
-    #[test]
-    fn test_addressing_indexed_indirect() {
-        let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+    /// CPU instruction: RRA (ROR then ADC, unofficial)
+    fn rra(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_modify_write(bus, opi, CPU::rotate_right);
 
-        cpu.powerup(&mut mem);
-        cpu.program_counter = 0x0000;
-        cpu.index_x = 0x04;
+        self.add(m);
 
-        mem.write(0x0001, 0xA0);
-        mem.write(0x00A4, 0xF0);
-        mem.write(0x00A5, 0xDA);
+        self.advance(opi);
+    }
 
-        let expected = Some(0xDAF0);
-        let result = cpu.get_address(&mut mem, AddressingMode::IndexedIndirect);
+    /// CPU instruction: SAX (store A AND X, unofficial)
+    fn sax(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.operand_address(bus, opi);
+        let v = self.accumulator & self.index_x;
 
-        assert_eq!(result, expected);
+        self.write(bus, addr, v);
+
+        self.advance(opi);
     }
 
-    #[test]
+    /// CPU instruction: SHA (store A AND X AND high byte + 1, unofficial)
+    ///
+    /// Also known as AHX. Unstable, see `unstable_store`.
+    fn sha(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let v = self.accumulator & self.index_x;
+        self.unstable_store(bus, opi, v);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: SHX (store X AND high byte + 1, unofficial)
+    fn shx(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let v = self.index_x;
+        self.unstable_store(bus, opi, v);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: SHY (store Y AND high byte + 1, unofficial)
+    fn shy(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let v = self.index_y;
+        self.unstable_store(bus, opi, v);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: TAS (S = A AND X, then SHA, unofficial)
+    ///
+    /// Also known as XAS or SHS.
+    fn tas(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let v = self.accumulator & self.index_x;
+        self.stack_pointer = v;
+        self.unstable_store(bus, opi, v);
+
+        self.advance(opi);
+    }
+
+    /// Common part of SHA, SHX, SHY and TAS. The stored value is ANDed with
+    /// the high byte of the unindexed address plus one. If indexing crosses a
+    /// page, the stored value also replaces the high byte of the address.
+    fn unstable_store(&mut self, bus: &mut dyn Bus, opi: &OpInfo, v: u8) {
+        let addr = self.operand_address(bus, opi);
+        let index = match opi.mode {
+            AddressingMode::AbsoluteX => self.index_x,
+            _ => self.index_y,
+        };
+        let base = addr.wrapping_sub(index as u16);
+
+        let r = v & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if self.page_crossed {
+            ((r as u16) << 8) | (addr & 0x00FF)
+        } else {
+            addr
+        };
+
+        self.write(bus, addr, r);
+    }
+
+    /// CPU instruction: SLO (ASL then ORA, unofficial)
+    fn slo(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_modify_write(bus, opi, CPU::shift_left);
+
+        let r = self.accumulator | m;
+        self.accumulator = r;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: SRE (LSR then EOR, unofficial)
+    fn sre(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_modify_write(bus, opi, CPU::shift_right);
+
+        let r = self.accumulator ^ m;
+        self.accumulator = r;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: XAA (A = X AND immediate, unofficial)
+    ///
+    /// Also known as ANE. Unstable, the accumulator is ORed with a chip
+    /// dependent constant before the AND, see `UNSTABLE_MAGIC`.
+    fn xaa(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_operand(bus, opi);
+        let r = (self.accumulator | UNSTABLE_MAGIC) & self.index_x & m;
+
+        self.accumulator = r;
+        self.update_zero_flag(r);
+        self.update_negative_flag(r);
+
+        self.advance(opi);
+    }
+    // 65C02 instructions
+    //
+    // Additional instructions of the WDC 65C02. The bit manipulation
+    // instructions RMB, SMB, BBR and BBS exist once per bit, the bit number
+    // is the digit in their mnemonic.
+
+    /// Bit number of RMB, SMB, BBR and BBS.
+    fn bit_number(opi: &OpInfo) -> u8 {
+        opi.mnemonic.as_bytes()[3] - b'0'
+    }
+
+    /// CPU instruction: BBR (branch on bit reset, 65C02)
+    ///
+    /// Tests a bit of a zero page location and branches if it is clear.
+    fn bbr(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_zero_page_operand(bus);
+        let condition = m & (1 << CPU::bit_number(opi)) == 0;
+        self.conditional_branch(bus, opi, condition);
+    }
+
+    /// CPU instruction: BBS (branch on bit set, 65C02)
+    ///
+    /// Tests a bit of a zero page location and branches if it is set.
+    fn bbs(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let m = self.read_zero_page_operand(bus);
+        let condition = m & (1 << CPU::bit_number(opi)) != 0;
+        self.conditional_branch(bus, opi, condition);
+    }
+
+    /// Read the zero page location tested by BBR and BBS. The value is read
+    /// twice before the displacement is fetched.
+    fn read_zero_page_operand(&mut self, bus: &mut dyn Bus) -> u8 {
+        let operand = self.program_counter.wrapping_add(1);
+        let addr = self.read(bus, operand) as u16;
+        let m = self.read(bus, addr);
+        self.dummy_read(bus, addr);
+        m
+    }
+
+    /// CPU instruction: BRA (branch always, 65C02)
+    fn bra(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.conditional_branch(bus, opi, true);
+    }
+
+    /// CPU instruction: NOP (no operation, 65C02 opcode $5C)
+    ///
+    /// Reads its absolute operand and then spends another five cycles
+    /// reading from $FFxx, where xx is the low byte of the operand.
+    fn nop_long(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.address(bus, opi.mode);
+        for _ in 0..5 {
+            self.dummy_read(bus, 0xFF00 | (addr & 0x00FF));
+        }
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: PHX (push x register, 65C02)
+    fn phx(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let x = self.index_x;
+        self.push(bus, x);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: PHY (push y register, 65C02)
+    fn phy(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let y = self.index_y;
+        self.push(bus, y);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: PLX (pull x register, 65C02)
+    ///
+    /// The zero and negative flags are set as appropriate.
+    fn plx(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.stack_dummy_read(bus);
+        let x = self.pop(bus);
+        self.index_x = x;
+        self.update_zero_flag(x);
+        self.update_negative_flag(x);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: PLY (pull y register, 65C02)
+    ///
+    /// The zero and negative flags are set as appropriate.
+    fn ply(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.stack_dummy_read(bus);
+        let y = self.pop(bus);
+        self.index_y = y;
+        self.update_zero_flag(y);
+        self.update_negative_flag(y);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: RMB (reset memory bit, 65C02)
+    fn rmb(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let mask = 1 << CPU::bit_number(opi);
+        self.read_modify_write(bus, opi, |_, m| m & !mask);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: SMB (set memory bit, 65C02)
+    fn smb(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let mask = 1 << CPU::bit_number(opi);
+        self.read_modify_write(bus, opi, |_, m| m | mask);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: STP (stop the processor, 65C02)
+    ///
+    /// Like JAM on the NMOS 6502, only a reset brings the processor back.
+    fn stp(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let pc = self.program_counter.wrapping_add(1);
+        self.dummy_read(bus, pc);
+        self.halted = true;
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: STZ (store zero, 65C02)
+    fn stz(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let addr = self.operand_address(bus, opi);
+
+        self.write(bus, addr, 0x00);
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: TRB (test and reset bits, 65C02)
+    ///
+    /// The zero flag is set like BIT does, then the bits set in the
+    /// accumulator are cleared in memory.
+    fn trb(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.read_modify_write(bus, opi, |cpu, m| {
+            cpu.status_register.zero_flag = m & cpu.accumulator == 0;
+            m & !cpu.accumulator
+        });
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: TSB (test and set bits, 65C02)
+    ///
+    /// The zero flag is set like BIT does, then the bits set in the
+    /// accumulator are set in memory.
+    fn tsb(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        self.read_modify_write(bus, opi, |cpu, m| {
+            cpu.status_register.zero_flag = m & cpu.accumulator == 0;
+            m | cpu.accumulator
+        });
+
+        self.advance(opi);
+    }
+
+    /// CPU instruction: WAI (wait for interrupt, 65C02)
+    ///
+    /// Stops executing until an interrupt is requested, see `step`.
+    fn wai(&mut self, bus: &mut dyn Bus, opi: &OpInfo) {
+        let pc = self.program_counter.wrapping_add(1);
+        self.dummy_read(bus, pc);
+        self.waiting = true;
+
+        self.advance(opi);
+    }
+}
+
+mod breakpoints;
+mod call;
+mod call_stack;
+mod history;
+mod hooks;
+mod lints;
+mod state;
+mod trace;
+
+pub use self::call::{CallError, CallResult, RETURN_SENTINEL};
+pub use self::state::{CpuState, FieldDiff};
+
+#[cfg(test)]
+mod single_step;
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+    use cpu::asm;
+    use cpu::bus::Ram;
+
+    #[test]
+    fn cpu_powerup_state() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+
+        assert_eq!(cpu.accumulator, 0);
+        assert_eq!(cpu.index_x, 0);
+        assert_eq!(cpu.index_y, 0);
+        assert_eq!(cpu.stack_pointer, 0xFD);
+
+        assert_eq!(0x34, 52);
+        assert_eq!(cpu.status_register.unused_bit, true);
+        assert_eq!(cpu.status_register.break_command, true);
+        assert_eq!(cpu.status_register.interrupt_disable, true);
+
+        assert_eq!(cpu.status_register.carry_flag, false);
+        assert_eq!(cpu.status_register.zero_flag, false);
+        assert_eq!(cpu.status_register.decimal_mode, false);
+        assert_eq!(cpu.status_register.overflow_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, false);
+
+        // memory
+        assert_eq!(mem.read(0x4017), 0);
+    }
+
+    #[test]
+    fn cpu_reset_state() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+        cpu.powerup(&mut mem);
+
+        cpu.status_register.interrupt_disable = false;
+        let sp_before = cpu.stack_pointer;
+        println!("{}", sp_before);
+
+        cpu.reset(&mut mem);
+
+        assert_eq!(cpu.stack_pointer, sp_before - 3);
+        assert_eq!(cpu.status_register.interrupt_disable, true);
+        assert_eq!(mem.read(0x4015), 0);
+        // TODO: test remaining memory addresses
+    }
+
+    #[test]
+    fn cpu_set_negative_true() {
+        let mut cpu = CPU::new();
+
+        cpu.accumulator = 0xFF;
+        assert_eq!(cpu.status_register.negative_flag, false);
+        let a = cpu.accumulator;
+        cpu.update_negative_flag(a);
+        assert_eq!(cpu.status_register.negative_flag, true);
+    }
+
+    #[test]
+    fn cpu_set_negative_false() {
+        let mut cpu = CPU::new();
+
+        cpu.accumulator = 0x00;
+        assert_eq!(cpu.status_register.negative_flag, false);
+        let a = cpu.accumulator;
+        cpu.update_negative_flag(a);
+        assert_eq!(cpu.status_register.negative_flag, false);
+    }
+
+    #[test]
+    fn test_addressing_absolute() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.program_counter = 0x0000;
+
+        mem.write(0x0001, 0xFF);
+        mem.write(0x0002, 0xAA);
+
+        let result = cpu.get_address(&mut mem, AddressingMode::Absolute);
+        let expected = Some(0xAAFF);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_addressing_absolute_x() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.program_counter = 0x0000;
+        cpu.index_x = 0x02;
+
+        mem.write(0x0001, 0xBB);
+        mem.write(0x0002, 0xAA);
+
+        let result = cpu.get_address(&mut mem, AddressingMode::AbsoluteX);
+        let expected = Some(0xAABD);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_addressing_absolute_y() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.program_counter = 0x0000;
+        cpu.index_y = 0x04;
+
+        mem.write(0x0001, 0xBB);
+        mem.write(0x0002, 0xAA);
+
+        let result = cpu.get_address(&mut mem, AddressingMode::AbsoluteY);
+        let expected = Some(0xAABF);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_addressing_indexed_indirect() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.program_counter = 0x0000;
+        cpu.index_x = 0x04;
+
+        mem.write(0x0001, 0xA0);
+        mem.write(0x00A4, 0xF0);
+        mem.write(0x00A5, 0xDA);
+
+        let expected = Some(0xDAF0);
+        let result = cpu.get_address(&mut mem, AddressingMode::IndexedIndirect);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
     fn test_addressing_indirect_indexed() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         cpu.powerup(&mut mem);
         cpu.program_counter = 0x0000;
@@ -1560,13 +2648,13 @@ mod tests {
     #[test]
     fn test_read16() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         cpu.powerup(&mut mem);
         mem.write(0x0004, 0xAA);
         mem.write(0x0005, 0xCC);
 
-        let result = cpu.read16(&mem, 0x0004);
+        let result = cpu.read16(&mut mem, 0x0004);
         let expected = 0xCCAA;
         assert_eq!(result, expected);
     }
@@ -1574,7 +2662,7 @@ mod tests {
     #[test]
     fn test_bmi_opcode_30_do_branch_to_same_page() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         cpu.powerup(&mut mem);
 
@@ -1582,12 +2670,12 @@ mod tests {
         cpu.status_register.negative_flag = true;
         cpu.program_counter = 0x05;
         mem.write(0x05, 0x30); // next operation is 0x30
-        mem.write(0x06, 0x04); // relative displacement is 2
-        let expected = 0x09; // expect program counter to have this value
+        mem.write(0x06, 0x04); // relative displacement is 4
+        let expected = 0x07 + 0x04; // relative to the next instruction
 
         // execute
         let cycles_before = cpu.cycles;
-        cpu.step(&mut mem);
+        cpu.step(&mut mem).unwrap();
         let cycles_after = cpu.cycles;
 
         // assert
@@ -1601,7 +2689,7 @@ mod tests {
     #[test]
     fn test_bmi_opcode_30_do_branch_to_new_page() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         cpu.powerup(&mut mem);
 
@@ -1609,12 +2697,12 @@ mod tests {
         cpu.status_register.negative_flag = true;
         cpu.program_counter = 0xEE;
         mem.write(0xEE, 0x30); // next operation is 0x30
-        mem.write(0xEF, 0xDD); // relative displacement is 2
-        let expected = 0xEE + 0xDD; // expect program counter to have this value
+        mem.write(0xEF, 0x20); // relative displacement is 0x20
+        let expected = 0xF0 + 0x20; // relative to the next instruction
 
         // execute
         let cycles_before = cpu.cycles;
-        cpu.step(&mut mem);
+        cpu.step(&mut mem).unwrap();
         let cycles_after = cpu.cycles;
 
         // assert
@@ -1622,13 +2710,13 @@ mod tests {
         let cycles_spent = cycles_after - cycles_before;
 
         assert_eq!(result, expected);
-        assert_eq!(cycles_spent, 5);
+        assert_eq!(cycles_spent, 4);
     }
 
     #[test]
     fn test_bmi_opcode_30_no_branch() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         cpu.powerup(&mut mem);
 
@@ -1641,7 +2729,7 @@ mod tests {
 
         // execute
         let cycles_before = cpu.cycles;
-        cpu.step(&mut mem);
+        cpu.step(&mut mem).unwrap();
         let cycles_after = cpu.cycles;
 
         // assert
@@ -1655,7 +2743,7 @@ mod tests {
     #[test]
     fn test_bit_opcode_24() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         cpu.powerup(&mut mem);
 
@@ -1667,7 +2755,7 @@ mod tests {
 
         let cycles_before = cpu.cycles;
         let pc_before = cpu.program_counter;
-        cpu.step(&mut mem);
+        cpu.step(&mut mem).unwrap();
         let cycles_after = cpu.cycles;
         let pc_after = cpu.program_counter;
 
@@ -1678,9 +2766,10 @@ mod tests {
     }
 
     #[test]
+    #[allow(non_snake_case)]
     fn test_instruction_cmp_with_opcode_C9() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         // a < m
         cpu.powerup(&mut mem);
@@ -1688,7 +2777,7 @@ mod tests {
         mem.write(cpu.program_counter + 1, 0x04);
         cpu.accumulator = 0x02;
 
-        cpu.step(&mut mem);
+        cpu.step(&mut mem).unwrap();
 
         assert_eq!(cpu.status_register.carry_flag, false);
         assert_eq!(cpu.status_register.zero_flag, false);
@@ -1700,7 +2789,7 @@ mod tests {
         mem.write(cpu.program_counter + 1, 0x04);
         cpu.accumulator = 0x06;
 
-        cpu.step(&mut mem);
+        cpu.step(&mut mem).unwrap();
 
         assert_eq!(cpu.status_register.carry_flag, true);
         assert_eq!(cpu.status_register.zero_flag, false);
@@ -1712,7 +2801,7 @@ mod tests {
         mem.write(cpu.program_counter + 1, 0x04);
         cpu.accumulator = 0x04;
 
-        cpu.step(&mut mem);
+        cpu.step(&mut mem).unwrap();
 
         assert_eq!(cpu.status_register.carry_flag, true);
         assert_eq!(cpu.status_register.zero_flag, true);
@@ -1722,7 +2811,7 @@ mod tests {
     #[test]
     fn test_push_and_pop() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         cpu.powerup(&mut mem);
 
@@ -1737,7 +2826,7 @@ mod tests {
     #[test]
     fn test_push16_and_pop16() {
         let mut cpu = CPU::new();
-        let mut mem = Memory::new();
+        let mut mem = Ram::new();
 
         cpu.powerup(&mut mem);
 
@@ -1764,4 +2853,1027 @@ mod tests {
         assert_eq!(res, exp);
     }
 
+    #[test]
+    fn test_bmi_opcode_30_branch_backwards() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+
+        cpu.status_register.negative_flag = true;
+        cpu.program_counter = 0x0210;
+        mem.write(0x0210, 0x30);
+        mem.write(0x0211, 0xFC); // relative displacement is -4
+
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x020E);
+    }
+
+    #[test]
+    fn test_jsr_and_rts() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.program_counter = 0x0400;
+        mem.write(0x0400, 0x20); // JSR $0600
+        mem.write(0x0401, 0x00);
+        mem.write(0x0402, 0x06);
+        mem.write(0x0600, 0x60); // RTS
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0600);
+        assert_eq!(cpu.stack_pointer, 0xFB);
+        assert_eq!(mem.read(0x01FD), 0x04);
+        assert_eq!(mem.read(0x01FC), 0x02);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0403);
+        assert_eq!(cpu.stack_pointer, 0xFD);
+        assert_eq!(cpu.cycles, 12);
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_bug() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        mem.write(0x0000, 0x6C); // JMP ($02FF)
+        mem.write(0x0001, 0xFF);
+        mem.write(0x0002, 0x02);
+        mem.write(0x02FF, 0x34);
+        mem.write(0x0300, 0x56);
+        mem.write(0x0200, 0x12);
+
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x1234);
+    }
+
+    #[test]
+    fn test_inx_iny_wrap() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.index_x = 0xFF;
+        cpu.index_y = 0x7F;
+        mem.write(0x0000, 0xE8); // INX
+        mem.write(0x0001, 0xC8); // INY
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.index_x, 0x00);
+        assert_eq!(cpu.status_register.zero_flag, true);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.index_y, 0x80);
+        assert_eq!(cpu.status_register.zero_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, true);
+    }
+
+    #[test]
+    fn test_cpy_uses_y_register() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.accumulator = 0x00;
+        cpu.index_y = 0x10;
+        mem.write(0x0000, 0xC0); // CPY #$10
+        mem.write(0x0001, 0x10);
+
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.status_register.carry_flag, true);
+        assert_eq!(cpu.status_register.zero_flag, true);
+        assert_eq!(cpu.status_register.negative_flag, false);
+    }
+
+    #[test]
+    fn test_adc_overflow_and_carry() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.accumulator = 0x50;
+        mem.write(0x0000, 0x69); // ADC #$50
+        mem.write(0x0001, 0x50);
+        mem.write(0x0002, 0x69); // ADC #$B0
+        mem.write(0x0003, 0xB0);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.accumulator, 0xA0);
+        assert_eq!(cpu.status_register.overflow_flag, true);
+        assert_eq!(cpu.status_register.carry_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, true);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.accumulator, 0x50);
+        assert_eq!(cpu.status_register.overflow_flag, true);
+        assert_eq!(cpu.status_register.carry_flag, true);
+        assert_eq!(cpu.program_counter, 0x0004);
+    }
+
+    #[test]
+    fn test_sbc_borrow() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.accumulator = 0x10;
+        cpu.status_register.carry_flag = true;
+        mem.write(0x0000, 0xE9); // SBC #$20
+        mem.write(0x0001, 0x20);
+
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.accumulator, 0xF0);
+        assert_eq!(cpu.status_register.carry_flag, false);
+        assert_eq!(cpu.status_register.overflow_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, true);
+    }
+
+    #[test]
+    fn test_rol_ror_through_carry() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.accumulator = 0x81;
+        cpu.status_register.carry_flag = false;
+        mem.write(0x0000, 0x2A); // ROL A
+        mem.write(0x0001, 0x6A); // ROR A
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.accumulator, 0x02);
+        assert_eq!(cpu.status_register.carry_flag, true);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.accumulator, 0x81);
+        assert_eq!(cpu.status_register.carry_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, true);
+    }
+
+    #[test]
+    fn test_php_plp() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.status_register.set_all(0x01);
+        mem.write(0x0000, 0x08); // PHP
+        mem.write(0x0001, 0x28); // PLP
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x01FD), 0x31);
+
+        mem.write(0x01FD, 0xFF);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.status_register.to_u8(), 0xEF);
+    }
+
+    #[test]
+    fn test_lax_and_sax() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        mem.write(0x0000, 0xA7); // LAX $10
+        mem.write(0x0001, 0x10);
+        mem.write(0x0002, 0x87); // SAX $11
+        mem.write(0x0003, 0x11);
+        mem.write(0x0010, 0x8F);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.accumulator, 0x8F);
+        assert_eq!(cpu.index_x, 0x8F);
+        assert_eq!(cpu.status_register.negative_flag, true);
+
+        cpu.accumulator = 0xF1;
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x0011), 0x81);
+        assert_eq!(cpu.cycles, 6);
+    }
+
+    #[test]
+    fn test_dcp_and_isc() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.accumulator = 0x40;
+        cpu.status_register.carry_flag = true;
+        mem.write(0x0000, 0xC7); // DCP $10
+        mem.write(0x0001, 0x10);
+        mem.write(0x0002, 0xE7); // ISC $11
+        mem.write(0x0003, 0x11);
+        mem.write(0x0010, 0x41);
+        mem.write(0x0011, 0x0F);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x0010), 0x40);
+        assert_eq!(cpu.status_register.zero_flag, true);
+        assert_eq!(cpu.status_register.carry_flag, true);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x0011), 0x10);
+        assert_eq!(cpu.accumulator, 0x30);
+        assert_eq!(cpu.cycles, 10);
+    }
+
+    #[test]
+    fn test_slo_rla_sre_rra() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.accumulator = 0x01;
+        mem.write(0x0000, 0x07); // SLO $10
+        mem.write(0x0001, 0x10);
+        mem.write(0x0010, 0x81);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x0010), 0x02);
+        assert_eq!(cpu.accumulator, 0x03);
+        assert_eq!(cpu.status_register.carry_flag, true);
+
+        cpu.accumulator = 0xFF;
+        mem.write(0x0002, 0x27); // RLA $10
+        mem.write(0x0003, 0x10);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x0010), 0x05);
+        assert_eq!(cpu.accumulator, 0x05);
+
+        mem.write(0x0004, 0x47); // SRE $10
+        mem.write(0x0005, 0x10);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x0010), 0x02);
+        assert_eq!(cpu.accumulator, 0x07);
+        assert_eq!(cpu.status_register.carry_flag, true);
+
+        mem.write(0x0006, 0x67); // RRA $10
+        mem.write(0x0007, 0x10);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x0010), 0x81);
+        assert_eq!(cpu.accumulator, 0x88);
+        assert_eq!(cpu.status_register.carry_flag, false);
+    }
+
+    #[test]
+    fn test_anc_alr_arr_axs() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.accumulator = 0xF0;
+        mem.write(0x0000, 0x0B); // ANC #$80
+        mem.write(0x0001, 0x80);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.accumulator, 0x80);
+        assert_eq!(cpu.status_register.carry_flag, true);
+
+        cpu.accumulator = 0x0F;
+        mem.write(0x0002, 0x4B); // ALR #$05
+        mem.write(0x0003, 0x05);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.accumulator, 0x02);
+        assert_eq!(cpu.status_register.carry_flag, true);
+
+        cpu.accumulator = 0xFF;
+        cpu.status_register.carry_flag = true;
+        mem.write(0x0004, 0x6B); // ARR #$C0
+        mem.write(0x0005, 0xC0);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.accumulator, 0xE0);
+        assert_eq!(cpu.status_register.carry_flag, true);
+        assert_eq!(cpu.status_register.overflow_flag, false);
+
+        cpu.accumulator = 0x0F;
+        cpu.index_x = 0x3C;
+        mem.write(0x0006, 0xCB); // AXS #$0D
+        mem.write(0x0007, 0x0D);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.index_x, 0xFF);
+        assert_eq!(cpu.status_register.carry_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, true);
+    }
+
+    #[test]
+    fn test_unofficial_nop_reads_operand_with_penalty() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.index_x = 0x01;
+        mem.write(0x0000, 0x1C); // NOP $02FF,X
+        mem.write(0x0001, 0xFF);
+        mem.write(0x0002, 0x02);
+
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.cycles, 5);
+    }
+
+    #[test]
+    fn test_shx_page_crossing_corrupts_address() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        cpu.index_x = 0x03;
+        cpu.index_y = 0x02;
+        mem.write(0x0000, 0x9E); // SHX $04FF,Y
+        mem.write(0x0001, 0xFF);
+        mem.write(0x0002, 0x04);
+
+        cpu.step(&mut mem).unwrap();
+
+        // X & ($04 + 1) = $01, which also becomes the high byte
+        assert_eq!(mem.read(0x0101), 0x01);
+        assert_eq!(mem.read(0x0501), 0x00);
+    }
+
+    #[test]
+    fn test_jam_halts_until_reset() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        cpu.powerup(&mut mem);
+        mem.write(0x0000, 0x02); // JAM
+
+        let error = CpuError {
+            pc: 0x0000,
+            opcode: 0x02,
+            kind: CpuErrorKind::Jam,
+        };
+        assert_eq!(cpu.step(&mut mem), Err(Stop::Error(error)));
+        assert_eq!(cpu.step(&mut mem), Err(Stop::Error(error)));
+        assert_eq!(error.to_string(), "processor jammed at $0000 (opcode $02)");
+
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.program_counter, 0x0000);
+
+        cpu.reset(&mut mem);
+        assert!(!cpu.is_halted());
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_undocumented_opcodes() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+        cpu.powerup(&mut mem);
+        cpu.set_strict(true);
+        // LDA #$07, then LAX $10
+        mem.load(0x0000, &[0xA9, 0x07, 0xA7, 0x10]);
+
+        assert_eq!(cpu.step(&mut mem), Ok(2));
+        let error = CpuError {
+            pc: 0x0002,
+            opcode: 0xA7,
+            kind: CpuErrorKind::IllegalOpcode,
+        };
+        assert_eq!(cpu.step(&mut mem), Err(Stop::Error(error)));
+        assert_eq!(error.to_string(), "illegal opcode at $0002 (opcode $A7)");
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.accumulator, 0x07);
+
+        // executed without strict mode
+        cpu.reset(&mut mem);
+        cpu.set_strict(false);
+        cpu.program_counter = 0x0002;
+        assert_eq!(cpu.step(&mut mem), Ok(3));
+        assert_eq!(cpu.accumulator, 0x00);
+    }
+
+    /// Executes every opcode of every variant once with all operands and
+    /// pointers zero, so no page is crossed, and checks the spent cycles
+    /// against the opcode table. All flags are clear, which makes BPL, BVC,
+    /// BCC, BNE, BRA and BBR take their branch.
+    #[test]
+    fn test_cycles_match_op_info() {
+        let variants = [Variant::Nmos, Variant::Ricoh2A03, Variant::Cmos65C02];
+
+        for variant in variants.iter() {
+            for timing in [Timing::Instruction, Timing::Cycle].iter() {
+                for (opcode, opi) in opinfo::op_info(*variant).iter().enumerate() {
+                    let mut cpu = CPU::new();
+                    let mut mem = Ram::new();
+                    cpu.set_variant(*variant);
+                    cpu.set_timing(*timing);
+                    cpu.stack_pointer = 0xFD;
+                    cpu.program_counter = 0x0200;
+                    mem.write(0x0200, opcode as u8);
+
+                    let result = cpu.step(&mut mem);
+                    assert!(result.is_ok() || opi.mnemonic == "JAM", "{:02X}", opcode);
+
+                    let taken = match opcode {
+                        0x10 | 0x50 | 0x90 | 0xD0 => 1,
+                        0x80 if *variant == Variant::Cmos65C02 => 1,
+                        _ if opi.mnemonic.starts_with("BBR") => 1,
+                        _ => 0,
+                    };
+                    assert_eq!(
+                        cpu.cycles,
+                        opi.cycles + taken,
+                        "{:?} {:02X} {}",
+                        variant,
+                        opcode,
+                        opi.mnemonic
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_indexed_page_crossing_cycles() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        // LDA $02F0,X; STA $02F0,X; LDA $0210,X; STA $0210,X; INC $0210,X
+        let program = [
+            0xBD, 0xF0, 0x02, 0x9D, 0xF0, 0x02, 0xBD, 0x10, 0x02, 0x9D, 0x10, 0x02, 0xFE, 0x10,
+            0x02,
+        ];
+        for (i, b) in program.iter().enumerate() {
+            mem.write(i as u16, *b);
+        }
+        cpu.index_x = 0x20;
+
+        let mut spent = Vec::new();
+        for _ in 0..5 {
+            let cycles = cpu.cycles;
+            cpu.step(&mut mem).unwrap();
+            spent.push(cpu.cycles - cycles);
+        }
+
+        assert_eq!(spent, vec![5, 5, 4, 5, 7]);
+    }
+
+    #[test]
+    fn test_step_returns_cycles() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        // LDA #$01; STA $0200,X; INC $10; JAM
+        mem.load(0x0000, &[0xA9, 0x01, 0x9D, 0x00, 0x02, 0xE6, 0x10, 0x02]);
+
+        assert_eq!(cpu.step(&mut mem), Ok(2));
+        assert_eq!(cpu.step(&mut mem), Ok(5));
+        assert_eq!(cpu.step(&mut mem), Ok(5));
+        assert!(cpu.step(&mut mem).is_err());
+        assert_eq!(cpu.cycles, 14);
+    }
+
+    #[test]
+    fn test_run_for() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        // INX; JMP $0000
+        mem.load(0x0000, &[0xE8, 0x4C, 0x00, 0x00]);
+
+        // two loops of 5 cycles, the third INX overshoots the budget
+        assert_eq!(cpu.run_for(&mut mem, 11), Ok(12));
+        assert_eq!(cpu.index_x, 3);
+        assert_eq!(cpu.run_for(&mut mem, 3), Ok(3));
+        assert_eq!(cpu.cycles, 15);
+
+        // an error stops the CPU before the budget is used up
+        mem.write(0x0000, 0x02);
+        let stop = cpu.run_for(&mut mem, 100).unwrap_err();
+        match stop {
+            Stop::Error(e) => assert_eq!(e.kind, CpuErrorKind::Jam),
+            _ => panic!("{}", stop),
+        }
+        assert_eq!(cpu.run_for(&mut mem, 100), Err(stop));
+
+        // so does STP, without an error
+        let mut cpu = CPU::new();
+        cpu.set_variant(Variant::Cmos65C02);
+        mem.write(0x0000, 0xDB);
+        assert_eq!(cpu.run_for(&mut mem, 100), Ok(3));
+        assert_eq!(cpu.run_for(&mut mem, 100), Ok(0));
+    }
+
+    #[test]
+    fn test_branch_page_crossing_cycles() {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        // BNE +$10 at $00F0 lands on the next page
+        cpu.program_counter = 0x00F0;
+        mem.write(0x00F0, 0xD0);
+        mem.write(0x00F1, 0x10);
+
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0102);
+        assert_eq!(cpu.cycles, 4);
+    }
+
+    #[test]
+    fn test_timing_modes_agree() {
+        // LDX #$05; loop: INC $10,X; DEX; BNE loop; JSR $0020; JAM
+        let program = [0xA2, 0x05, 0xF6, 0x10, 0xCA, 0xD0, 0xFB, 0x20, 0x20, 0x00, 0x02];
+        let mut results = Vec::new();
+
+        for timing in [Timing::Instruction, Timing::Cycle].iter() {
+            let mut cpu = CPU::new();
+            let mut mem = Ram::new();
+            cpu.set_timing(*timing);
+            cpu.stack_pointer = 0xFD;
+            for (i, b) in program.iter().enumerate() {
+                mem.write(0x0200 + i as u16, *b);
+            }
+            mem.write(0x0020, 0x60); // RTS
+            cpu.program_counter = 0x0200;
+
+            while !cpu.is_halted() {
+                if let Err(Stop::Error(e)) = cpu.step(&mut mem) {
+                    assert_eq!(e.kind, CpuErrorKind::Jam);
+                }
+            }
+
+            let zero_page: Vec<u8> = (0x10..0x18).map(|a| mem.read(a)).collect();
+            results.push((cpu.cycles, cpu.program_counter, cpu.stack_pointer, zero_page));
+        }
+
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[0].3, vec![0, 1, 1, 1, 1, 1, 0, 0]);
+    }
+
+    /// Runs `program` at $0200 on the given variant until it halts.
+    fn run_variant(variant: Variant, program: &[u8]) -> (CPU, Ram) {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+        cpu.set_variant(variant);
+        cpu.stack_pointer = 0xFD;
+        cpu.program_counter = 0x0200;
+        mem.load(0x0200, program);
+
+        let mut steps = 0;
+        while !cpu.is_halted() {
+            if let Err(Stop::Error(e)) = cpu.step(&mut mem) {
+                assert_eq!(e.kind, CpuErrorKind::Jam);
+            }
+            steps += 1;
+            assert!(steps < 1000, "program does not halt");
+        }
+        (cpu, mem)
+    }
+
+    #[test]
+    fn test_decimal_adc() {
+        // SED; CLC; LDA #$99; ADC #$01; JAM
+        let program = [0xF8, 0x18, 0xA9, 0x99, 0x69, 0x01, 0x02];
+
+        let (cpu, _) = run_variant(Variant::Nmos, &program);
+        assert_eq!(cpu.accumulator, 0x00);
+        assert_eq!(cpu.status_register.carry_flag, true);
+        // flags of the binary sum and the unadjusted intermediate result
+        assert_eq!(cpu.status_register.zero_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, true);
+        assert_eq!(cpu.status_register.overflow_flag, false);
+
+        let (cpu, _) = run_variant(Variant::Ricoh2A03, &program);
+        assert_eq!(cpu.accumulator, 0x9A);
+        assert_eq!(cpu.status_register.carry_flag, false);
+
+        // SED; SEC; LDA #$58; ADC #$46; STP
+        let program = [0xF8, 0x38, 0xA9, 0x58, 0x69, 0x46, 0xDB];
+        let (cpu, _) = run_variant(Variant::Cmos65C02, &program);
+        assert_eq!(cpu.accumulator, 0x05);
+        assert_eq!(cpu.status_register.carry_flag, true);
+        assert_eq!(cpu.status_register.zero_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, false);
+
+        // SED; CLC; LDA #$99; ADC #$01; STP
+        let program = [0xF8, 0x18, 0xA9, 0x99, 0x69, 0x01, 0xDB];
+        let (cpu, _) = run_variant(Variant::Cmos65C02, &program);
+        assert_eq!(cpu.accumulator, 0x00);
+        assert_eq!(cpu.status_register.zero_flag, true);
+        assert_eq!(cpu.status_register.negative_flag, false);
+        // SED, CLC and LDA take 6 cycles, ADC 3, STP 3
+        assert_eq!(cpu.cycles, 12);
+    }
+
+    #[test]
+    fn test_decimal_sbc() {
+        // SED; SEC; LDA #$46; SBC #$12; TAX; LDA #$00; SBC #$01; JAM
+        let program = [
+            0xF8, 0x38, 0xA9, 0x46, 0xE9, 0x12, 0xAA, 0xA9, 0x00, 0xE9, 0x01, 0x02,
+        ];
+
+        let (cpu, _) = run_variant(Variant::Nmos, &program);
+        assert_eq!(cpu.index_x, 0x34);
+        assert_eq!(cpu.accumulator, 0x99);
+        assert_eq!(cpu.status_register.carry_flag, false);
+        assert_eq!(cpu.status_register.negative_flag, true);
+
+        let (cpu, _) = run_variant(Variant::Ricoh2A03, &program);
+        assert_eq!(cpu.index_x, 0x34);
+        assert_eq!(cpu.accumulator, 0xFF);
+    }
+
+    #[test]
+    fn test_65c02_instructions() {
+        let program = asm::assemble(
+            Variant::Cmos65C02,
+            "
+                    .org $0200
+                    lda #$F0
+                    stz $10
+                    tsb $10
+                    trb $11
+                    ldx #$42
+                    phx
+                    ply
+                    bra @skip
+                    stp
+            @skip:  lda ($20)
+                    dec a
+                    stz $0400
+                    smb0 $12
+                    bbr0 $12, end   ; not taken
+                    bbs1 $12, end   ; not taken
+            end:    stp
+            ",
+        )
+        .unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        cpu.set_variant(Variant::Cmos65C02);
+        cpu.stack_pointer = 0xFD;
+        cpu.program_counter = 0x0200;
+        mem.write(0x0011, 0xFF);
+        mem.write(0x0020, 0x00);
+        mem.write(0x0021, 0x03);
+        mem.write(0x0300, 0x77);
+        mem.write(0x0400, 0x55);
+
+        while !cpu.is_halted() {
+            cpu.step(&mut mem).unwrap();
+        }
+
+        assert_eq!(mem.read(0x0010), 0xF0);
+        assert_eq!(mem.read(0x0011), 0x0F);
+        assert_eq!(cpu.index_y, 0x42);
+        assert_eq!(cpu.accumulator, 0x76);
+        assert_eq!(mem.read(0x0400), 0x00);
+        assert_eq!(mem.read(0x0012), 0x01);
+        assert_eq!(Some(cpu.program_counter), program.symbol("end").map(|a| a + 1));
+    }
+
+    #[test]
+    fn test_jmp_indirect_fixed_on_65c02() {
+        // JMP ($02FF)
+        let mut mem = Ram::new();
+        mem.load(0x0000, &[0x6C, 0xFF, 0x02]);
+        mem.write(0x02FF, 0x00);
+        mem.write(0x0300, 0x04);
+        mem.write(0x0200, 0x05);
+
+        let mut cpu = CPU::new();
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0500);
+
+        let mut cpu = CPU::new();
+        cpu.set_variant(Variant::Cmos65C02);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.cycles, 6);
+    }
+
+    #[test]
+    fn test_65c02_wai_and_brk_clear_decimal() {
+        let (mut cpu, mut mem) = interrupt_test_setup(&[0xF8, 0xCB, 0xEA]);
+        cpu.set_variant(Variant::Cmos65C02);
+        cpu.status_register.interrupt_disable = false;
+
+        cpu.step(&mut mem).unwrap(); // SED
+        cpu.step(&mut mem).unwrap(); // WAI
+        for _ in 0..10 {
+            cpu.step(&mut mem).unwrap();
+        }
+        assert_eq!(cpu.program_counter, 0x0402);
+
+        cpu.set_irq(IrqSource::External, true);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0500);
+        assert_eq!(cpu.status_register.decimal_mode, false);
+    }
+
+    /// Wraps a `Ram` and records every access as (kind, address, value).
+    struct LoggingBus {
+        ram: Ram,
+        log: Vec<(char, u16, u8)>,
+        ticks: usize,
+    }
+
+    impl LoggingBus {
+        fn new(program: &[u8]) -> LoggingBus {
+            let mut ram = Ram::new();
+            ram.load(0x0000, program);
+
+            LoggingBus {
+                ram,
+                log: Vec::new(),
+                ticks: 0,
+            }
+        }
+    }
+
+    impl Bus for LoggingBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            let val = self.ram.read(addr);
+            self.log.push(('r', addr, val));
+            val
+        }
+
+        fn write(&mut self, addr: u16, val: u8) {
+            self.log.push(('w', addr, val));
+            self.ram.write(addr, val);
+        }
+
+        fn tick(&mut self) {
+            self.ticks += 1;
+        }
+    }
+
+    #[test]
+    fn test_cycle_timing_makes_dummy_accesses() {
+        // INC $02F0,X crossing into page 3, then PLA
+        let mut bus = LoggingBus::new(&[0xFE, 0xF0, 0x02, 0x68]);
+        bus.ram.write(0x0310, 0x41);
+        bus.ram.write(0x01FE, 0x99);
+
+        let mut cpu = CPU::new();
+        cpu.set_timing(Timing::Cycle);
+        cpu.index_x = 0x20;
+        cpu.stack_pointer = 0xFD;
+
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(
+            bus.log,
+            vec![
+                ('r', 0x0000, 0xFE),
+                ('r', 0x0001, 0xF0),
+                ('r', 0x0002, 0x02),
+                ('r', 0x0210, 0x00),
+                ('r', 0x0310, 0x41),
+                ('w', 0x0310, 0x41),
+                ('w', 0x0310, 0x42),
+            ]
+        );
+        assert_eq!(bus.ticks, 7);
+
+        bus.log.clear();
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(
+            bus.log,
+            vec![
+                ('r', 0x0003, 0x68),
+                ('r', 0x0004, 0x00),
+                ('r', 0x01FD, 0x00),
+                ('r', 0x01FE, 0x99),
+            ]
+        );
+        assert_eq!(bus.ticks, 11);
+    }
+
+    #[test]
+    fn test_instruction_timing_skips_dummy_accesses() {
+        let mut bus = LoggingBus::new(&[0xFE, 0xF0, 0x02]);
+        bus.ram.write(0x0310, 0x41);
+
+        let mut cpu = CPU::new();
+        cpu.index_x = 0x20;
+
+        cpu.step(&mut bus).unwrap();
+        assert_eq!(
+            bus.log,
+            vec![
+                ('r', 0x0000, 0xFE),
+                ('r', 0x0001, 0xF0),
+                ('r', 0x0002, 0x02),
+                ('r', 0x0310, 0x41),
+                ('w', 0x0310, 0x42),
+            ]
+        );
+        assert_eq!(bus.ticks, 7);
+        assert_eq!(cpu.cycles, 7);
+    }
+
+    /// Sets up a program at $0400 consisting of `program` and IRQ, NMI and
+    /// reset vectors pointing to $0500, $0600 and $0400.
+    fn interrupt_test_setup(program: &[u8]) -> (CPU, Ram) {
+        let mut cpu = CPU::new();
+        let mut mem = Ram::new();
+
+        for (i, b) in program.iter().enumerate() {
+            mem.write(0x0400 + i as u16, *b);
+        }
+        mem.write(0xFFFA, 0x00);
+        mem.write(0xFFFB, 0x06);
+        mem.write(0xFFFC, 0x00);
+        mem.write(0xFFFD, 0x04);
+        mem.write(0xFFFE, 0x00);
+        mem.write(0xFFFF, 0x05);
+
+        cpu.powerup(&mut mem);
+        cpu.reset(&mut mem);
+        (cpu, mem)
+    }
+
+    #[test]
+    fn test_reset_loads_vector() {
+        let (cpu, _) = interrupt_test_setup(&[]);
+
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.stack_pointer, 0xFA);
+    }
+
+    #[test]
+    fn test_brk_pushes_state_and_loads_vector() {
+        let (mut cpu, mut mem) = interrupt_test_setup(&[0x00, 0xFF]);
+        mem.write(0x0500, 0x40); // RTI
+        cpu.status_register.interrupt_disable = false;
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0500);
+        assert_eq!(cpu.status_register.interrupt_disable, true);
+        assert_eq!(mem.read(0x01FA), 0x04);
+        assert_eq!(mem.read(0x01F9), 0x02);
+        assert_eq!(mem.read(0x01F8) & 0x30, 0x30);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0402);
+        assert_eq!(cpu.status_register.interrupt_disable, false);
+    }
+
+    #[test]
+    fn test_irq_is_serviced_after_instruction() {
+        let (mut cpu, mut mem) = interrupt_test_setup(&[0xEA, 0xEA]);
+        cpu.status_register.interrupt_disable = false;
+
+        cpu.set_irq(IrqSource::External, true);
+        cpu.step(&mut mem).unwrap(); // NOP, polls the IRQ
+        assert_eq!(cpu.program_counter, 0x0401);
+
+        let cycles = cpu.cycles;
+        cpu.step(&mut mem).unwrap(); // interrupt sequence
+        assert_eq!(cpu.program_counter, 0x0500);
+        assert_eq!(cpu.cycles - cycles, 7);
+        assert_eq!(cpu.status_register.interrupt_disable, true);
+        // break bit is clear in the pushed status
+        assert_eq!(mem.read(0x01F8) & 0x30, 0x20);
+    }
+
+    #[test]
+    fn test_irq_is_masked_by_interrupt_disable() {
+        let (mut cpu, mut mem) = interrupt_test_setup(&[0xEA, 0xEA]);
+
+        cpu.set_irq(IrqSource::Dmc, true);
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0402);
+    }
+
+    #[test]
+    fn test_cli_delays_irq_by_one_instruction() {
+        let (mut cpu, mut mem) = interrupt_test_setup(&[0x58, 0xEA, 0xEA]);
+
+        cpu.set_irq(IrqSource::Mapper, true);
+        cpu.step(&mut mem).unwrap(); // CLI
+        cpu.step(&mut mem).unwrap(); // NOP still executes
+        assert_eq!(cpu.program_counter, 0x0402);
+
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0500);
+    }
+
+    #[test]
+    fn test_sei_still_allows_pending_irq() {
+        let (mut cpu, mut mem) = interrupt_test_setup(&[0x78, 0xEA]);
+        cpu.status_register.interrupt_disable = false;
+
+        cpu.set_irq(IrqSource::FrameCounter, true);
+        cpu.step(&mut mem).unwrap(); // SEI
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0500);
+        // the pushed status has the interrupt disable flag set
+        assert_eq!(mem.read(0x01F8) & 0x04, 0x04);
+    }
+
+    #[test]
+    fn test_nmi_is_edge_triggered_and_preferred() {
+        let (mut cpu, mut mem) = interrupt_test_setup(&[0xEA]);
+        mem.write(0x0600, 0x40); // RTI
+        cpu.status_register.interrupt_disable = false;
+
+        cpu.set_nmi(true);
+        cpu.set_irq(IrqSource::External, true);
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0600);
+
+        // the still asserted NMI line does not trigger again, the IRQ is
+        // taken once RTI restores the interrupt disable flag
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0401);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x0500);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        let (mut cpu, mut mem) = interrupt_test_setup(&[0x00, 0x00]);
+
+        // the NMI arrives after BRK was fetched
+        cpu.pending_interrupt = None;
+        cpu.set_nmi(true);
+        cpu.step(&mut mem).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0600);
+        // the break bit identifies the interrupted BRK
+        assert_eq!(mem.read(0x01F8) & 0x10, 0x10);
+        assert!(!cpu.interrupt_lines.nmi_pending());
+    }
+
+    /// Runs the nestest ROM in its automated mode and compares the CPU state
+    /// and the cycle count before every instruction with the reference log. This covers all
+    /// official and the common unofficial opcodes. Expects `nestest.nes`
+    /// and `nestest.log` in the directory given by `NESTEST_DIR` (defaults to
+    /// `tests/roms`), see the README. Run with
+    /// `cargo test nestest -- --ignored`.
+    #[test]
+    #[ignore]
+    fn nestest() {
+        use std::env;
+        use std::fs::File;
+        use std::io::{BufRead, BufReader};
+        use std::path::PathBuf;
+
+        let dir = PathBuf::from(env::var("NESTEST_DIR").unwrap_or(String::from("tests/roms")));
+        let rom = ::rom::load(dir.join("nestest.nes"));
+        let log = BufReader::new(File::open(dir.join("nestest.log")).unwrap());
+
+        // NROM-128: the single 16 kB PRG bank is mirrored at $8000 and $C000
+        let mut mem = Ram::new();
+        for (i, b) in rom[16..16 + 0x4000].iter().enumerate() {
+            mem.write(0x8000 + i as u16, *b);
+            mem.write(0xC000 + i as u16, *b);
+        }
+
+        let mut cpu = CPU::new();
+        cpu.program_counter = 0xC000;
+        cpu.stack_pointer = 0xFD;
+        cpu.status_register.set_all(0x24);
+        cpu.cycles = 7;
+        cpu.set_timing(Timing::Cycle);
+
+        let field = |line: &str, name: &str| {
+            let i = line.find(name).unwrap() + name.len();
+            u8::from_str_radix(&line[i..i + 2], 16).unwrap()
+        };
+
+        for (n, line) in log.lines().enumerate() {
+            let line = line.unwrap();
+
+            let pc = u16::from_str_radix(&line[0..4], 16).unwrap();
+            let state = (
+                cpu.program_counter,
+                cpu.accumulator,
+                cpu.index_x,
+                cpu.index_y,
+                cpu.status_register.to_u8(),
+                cpu.stack_pointer,
+            );
+            let expected = (
+                pc,
+                field(&line, "A:"),
+                field(&line, "X:"),
+                field(&line, "Y:"),
+                field(&line, "P:"),
+                field(&line, "SP:"),
+            );
+            assert_eq!(state, expected, "line {}: {}", n + 1, line);
+
+            let i = line.find("CYC:").unwrap() + 4;
+            let cycles: usize = line[i..].trim().parse().unwrap();
+            assert_eq!(cpu.cycles, cycles, "line {}: {}", n + 1, line);
+
+            cpu.step(&mut mem).unwrap();
+        }
+    }
 }
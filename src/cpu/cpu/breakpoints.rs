@@ -0,0 +1,204 @@
+//! Breakpoints and watchpoints, see `cpu::debug`.
+
+use super::*;
+use cpu::debug::{BreakOn, Breakpoint, BreakpointId, Stop};
+
+impl CPU {
+    /// Register a breakpoint. `step` stops before executing an instruction
+    /// with an execution breakpoint and after an instruction which hit a
+    /// watchpoint.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        let id = BreakpointId(self.next_breakpoint_id);
+        self.next_breakpoint_id += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Option<Breakpoint> {
+        let index = self.breakpoints.iter().position(|b| b.0 == id)?;
+        Some(self.breakpoints.remove(index).1)
+    }
+
+    pub fn breakpoint(&self, id: BreakpointId) -> Option<&Breakpoint> {
+        self.breakpoints.iter().find(|b| b.0 == id).map(|b| &b.1)
+    }
+
+    /// Access to a breakpoint, e.g. to disable it or to reset its hits.
+    pub fn breakpoint_mut(&mut self, id: BreakpointId) -> Option<&mut Breakpoint> {
+        self.breakpoints.iter_mut().find(|b| b.0 == id).map(|b| &mut b.1)
+    }
+
+    /// All breakpoints in the order they were added.
+    pub fn breakpoints(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.breakpoints.iter().map(|b| (b.0, &b.1))
+    }
+
+    pub(super) fn has_breakpoints(&self) -> bool {
+        !self.breakpoints.is_empty()
+    }
+
+    /// Check the execution breakpoints for the next instruction. After a
+    /// stop the same instruction passes once, so the next step executes it.
+    pub(super) fn check_execute(&mut self, bus: &dyn Bus) -> Option<Stop> {
+        let pc = self.program_counter;
+        if self.resume_pc.take() == Some(pc) {
+            return None;
+        }
+
+        let id = self.hit(bus, BreakOn::Execute, pc)?;
+        self.resume_pc = Some(pc);
+        Some(Stop::Breakpoint { id, pc })
+    }
+
+    /// The first enabled execution breakpoint at the program counter whose
+    /// condition is true, without counting a hit.
+    pub(super) fn execute_breakpoint_at(&self, bus: &dyn Bus) -> Option<BreakpointId> {
+        let state = self.state();
+        self.breakpoints
+            .iter()
+            .filter(|b| b.1.matches(BreakOn::Execute, state.pc()))
+            .find(|b| match b.1.condition {
+                Some(ref condition) => condition.is_true(&state, bus),
+                None => true,
+            })
+            .map(|b| b.0)
+    }
+
+    /// Check the watchpoints for a data access. The first hit is kept until
+    /// the instruction completed.
+    pub(super) fn check_access(&mut self, bus: &dyn Bus, addr: u16, value: u8, access: Access) {
+        let on = match access {
+            Access::Read => BreakOn::Read,
+            Access::Write => BreakOn::Write,
+            _ => return,
+        };
+        if self.watch_hit.is_some() {
+            return;
+        }
+
+        if let Some(id) = self.hit(bus, on, addr) {
+            self.watch_hit = Some(Stop::Watchpoint {
+                id,
+                addr,
+                value,
+                access,
+            });
+        }
+    }
+
+    /// Count a hit for every breakpoint matching the event whose condition
+    /// is true and return the first one which passed its ignore count.
+    fn hit(&mut self, bus: &dyn Bus, on: BreakOn, addr: u16) -> Option<BreakpointId> {
+        let state = self.state();
+        let mut stop = None;
+
+        for &mut (id, ref mut breakpoint) in &mut self.breakpoints {
+            if !breakpoint.matches(on, addr) {
+                continue;
+            }
+            if let Some(ref condition) = breakpoint.condition {
+                if !condition.is_true(&state, bus) {
+                    continue;
+                }
+            }
+
+            breakpoint.hits += 1;
+            if breakpoint.hits > breakpoint.ignore && stop.is_none() {
+                stop = Some(id);
+            }
+        }
+        stop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::asm;
+    use cpu::bus::Ram;
+
+    fn setup(source: &str) -> (CPU, Ram, asm::Program) {
+        let program = asm::assemble(Variant::Ricoh2A03, source).unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        cpu.program_counter = program.symbol("start").unwrap();
+        (cpu, mem, program)
+    }
+
+    const COUNT_LOOP: &str = "
+            .org $8000
+    start:  ldx #0
+    loop:   inx
+            stx $0300
+            lda $0300
+            jmp loop
+    ";
+
+    #[test]
+    fn execution_breakpoint() {
+        let (mut cpu, mut mem, program) = setup(COUNT_LOOP);
+        let loop_addr = program.symbol("loop").unwrap();
+        let id = cpu.add_breakpoint(Breakpoint::execute(loop_addr));
+
+        assert_eq!(cpu.step(&mut mem), Ok(2));
+        assert_eq!(cpu.step(&mut mem), Err(Stop::Breakpoint { id, pc: loop_addr }));
+        assert_eq!(cpu.program_counter, loop_addr);
+
+        // continuing executes the instruction at the breakpoint
+        assert_eq!(cpu.run_for(&mut mem, 1000), Err(Stop::Breakpoint { id, pc: loop_addr }));
+        assert_eq!(cpu.index_x, 1);
+        assert_eq!(cpu.breakpoint(id).unwrap().hits, 2);
+
+        cpu.breakpoint_mut(id).unwrap().enabled = false;
+        assert!(cpu.run_for(&mut mem, 100).unwrap() >= 100);
+        assert!(cpu.remove_breakpoint(id).is_some());
+        assert_eq!(cpu.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn conditional_breakpoint_with_ignore_count() {
+        let (mut cpu, mut mem, program) = setup(COUNT_LOOP);
+        let loop_addr = program.symbol("loop").unwrap();
+        let mut breakpoint = Breakpoint::execute(loop_addr)
+            .with_condition("X >= 3 && P.Z == 0")
+            .unwrap();
+        breakpoint.ignore = 2;
+        let id = cpu.add_breakpoint(breakpoint);
+
+        assert!(cpu.run_for(&mut mem, 10000).is_err());
+        assert_eq!(cpu.index_x, 5);
+        assert_eq!(cpu.breakpoint(id).unwrap().hits, 3);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (mut cpu, mut mem, _) = setup(COUNT_LOOP);
+        let write = cpu.add_breakpoint(
+            Breakpoint::watch(BreakOn::Write, 0x0300, 0x0300)
+                .with_condition("X == 2")
+                .unwrap(),
+        );
+
+        let stop = cpu.run_for(&mut mem, 10000).unwrap_err();
+        assert_eq!(
+            stop,
+            Stop::Watchpoint {
+                id: write,
+                addr: 0x0300,
+                value: 2,
+                access: Access::Write
+            }
+        );
+        // the instruction completed
+        assert_eq!(mem.read(0x0300), 2);
+        cpu.remove_breakpoint(write);
+
+        // opcode and operand fetches are no data reads
+        let read = cpu.add_breakpoint(Breakpoint::watch(BreakOn::Read, 0x0000, 0xFFFF));
+        let stop = cpu.run_for(&mut mem, 10000).unwrap_err();
+        assert_eq!(stop.to_string(), "watchpoint 1: Read of $02 at $0300");
+        assert_eq!(cpu.breakpoint(read).unwrap().hits, 1);
+    }
+}
@@ -0,0 +1,368 @@
+//! Calling 6502 subroutines from the host, e.g. to run a decompressor or the
+//! random number generator of a ROM from a tool or test.
+
+use super::*;
+use std::mem;
+
+/// Return address pushed by `CPU::call`, the high byte of the IRQ vector,
+/// which is hardly ever an instruction. The call completes at the RTS which
+/// pulls it, the routine reaching this or any other address does not end
+/// it.
+pub const RETURN_SENTINEL: u16 = 0xFFFF;
+
+/// A `CPU::call` in progress.
+pub(super) struct Calling {
+    /// the stack pointer before the sentinel was pushed
+    sp: u8,
+    /// set by the RTS which pulled the sentinel
+    returned: bool,
+}
+
+/// Result of a successful `CPU::call`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CallResult {
+    /// the registers after the RTS of the routine, the program counter is
+    /// `RETURN_SENTINEL`
+    pub state: CpuState,
+    /// cycles from the first instruction of the routine to its RTS inclusive
+    pub cycles: usize,
+}
+
+/// Why `CPU::call` did not return normally.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CallError {
+    /// the routine did not return within the timeout, with the registers at
+    /// that point
+    Timeout(CpuState),
+    /// the CPU was halted before the call or by a STP in the routine
+    Halted(CpuState),
+    /// an error, breakpoint or watchpoint stopped the routine
+    Stop(Stop),
+}
+
+impl From<Stop> for CallError {
+    fn from(stop: Stop) -> CallError {
+        CallError::Stop(stop)
+    }
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CallError::Timeout(ref state) => write!(f, "timeout at ${:04X}", state.pc()),
+            CallError::Halted(ref state) => write!(f, "processor halted at ${:04X}", state.pc()),
+            CallError::Stop(ref stop) => write!(f, "{}", stop),
+        }
+    }
+}
+
+impl error::Error for CallError {}
+
+impl CPU {
+    /// Call the subroutine at `addr` with the registers A, X, Y, P and SP of
+    /// `regs` and run it until it returns, like a JSR would.
+    ///
+    /// The return address pushed is `RETURN_SENTINEL`, the call completes
+    /// when an RTS pulls it at the stack level it was pushed at. Fails if
+    /// that takes more than `timeout` cycles. Interrupts are not
+    /// serviced during the call and its steps are not recorded in the undo
+    /// history. Afterwards, also on failure, the registers and the interrupt
+    /// state of the CPU are restored except for the cycle counter, so an
+    /// interrupt pending before the call is serviced once after it. Memory
+    /// written by the routine keeps its new values.
+    pub fn call<B: Bus>(
+        &mut self,
+        bus: &mut B,
+        addr: u16,
+        regs: &CpuState,
+        timeout: usize,
+    ) -> Result<CallResult, CallError> {
+        if self.halted {
+            return Err(match self.error {
+                Some(error) => CallError::Stop(Stop::Error(error)),
+                None => CallError::Halted(self.state()),
+            });
+        }
+
+        let saved = self.state();
+        let status = self.status_register.to_u8();
+        let waiting = self.waiting;
+        let pending_interrupt = self.pending_interrupt.take();
+        let interrupt_lines = mem::replace(&mut self.interrupt_lines, InterruptLines::new());
+        let sampled = (
+            self.irq_sampled,
+            self.nmi_sampled,
+            self.irq_sampled_prev,
+            self.nmi_sampled_prev,
+        );
+        self.irq_sampled = false;
+        self.nmi_sampled = false;
+        self.irq_sampled_prev = false;
+        self.nmi_sampled_prev = false;
+        let history_capacity = mem::replace(&mut self.history_capacity, 0);
+        // breakpoints of the caller do not apply to the routine and the
+        // other way round
+        let resume_pc = self.resume_pc.take();
+        let watch_hit = self.watch_hit.take();
+
+        let mut state = *regs;
+        state.set_cycles(self.cycles);
+        self.set_state(&state);
+        self.waiting = false;
+
+        let depth = self.call_stack().len();
+        let result = self.run_call(bus, addr, saved.pc(), timeout);
+        self.calling = None;
+        if result.is_err() {
+            // drop the frame of the routine and those it entered, the
+            // program did not abandon them
+            if let Some(ref mut frames) = self.call_stack {
+                frames.truncate(depth);
+            }
+        }
+
+        let mut restored = saved;
+        restored.set_cycles(self.cycles);
+        self.set_state(&restored);
+        self.status_register.set_all(status);
+        self.waiting = waiting;
+        self.pending_interrupt = pending_interrupt;
+        self.interrupt_lines = interrupt_lines;
+        let (irq_sampled, nmi_sampled, irq_sampled_prev, nmi_sampled_prev) = sampled;
+        self.irq_sampled = irq_sampled;
+        self.nmi_sampled = nmi_sampled;
+        self.irq_sampled_prev = irq_sampled_prev;
+        self.nmi_sampled_prev = nmi_sampled_prev;
+        self.history_capacity = history_capacity;
+        self.resume_pc = resume_pc;
+        self.watch_hit = watch_hit;
+        // an error of the routine does not halt the caller
+        self.halted = false;
+        self.error = None;
+
+        result
+    }
+
+    /// Note an RTS which returned to `target`, it completes the call in
+    /// progress if it pulled the sentinel.
+    pub(super) fn return_from_call(&mut self, target: u16) {
+        if let Some(ref mut calling) = self.calling {
+            if target == RETURN_SENTINEL && self.stack_pointer == calling.sp {
+                calling.returned = true;
+            }
+        }
+    }
+
+    fn run_call<B: Bus>(
+        &mut self,
+        bus: &mut B,
+        addr: u16,
+        caller: u16,
+        timeout: usize,
+    ) -> Result<CallResult, CallError> {
+        // push the return address like JSR does
+        let start = self.cycles;
+        let sp = self.stack_pointer;
+        self.push16(bus, RETURN_SENTINEL.wrapping_sub(1));
+        self.catch_up(bus, start);
+        self.program_counter = addr;
+        self.enter_frame(FrameKind::Subroutine, caller, RETURN_SENTINEL, sp);
+        self.calling = Some(Calling {
+            sp,
+            returned: false,
+        });
+
+        let start = self.cycles;
+        loop {
+            if self.cycles - start >= timeout {
+                return Err(CallError::Timeout(self.state()));
+            }
+            if self.step(bus)? == 0 {
+                return Err(CallError::Halted(self.state()));
+            }
+            if self.calling.as_ref().is_some_and(|c| c.returned) {
+                return Ok(CallResult {
+                    state: self.state(),
+                    cycles: self.cycles - start,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::asm;
+    use cpu::bus::Ram;
+    use cpu::debug::Breakpoint;
+
+    fn setup() -> (CPU, Ram, asm::Program) {
+        let program = asm::assemble(
+            Variant::Ricoh2A03,
+            "
+                    .org $8000
+            ; A = A * X, both 4 bit
+            multiply:
+                    sta $00
+                    lda #0
+            @loop:  cpx #0
+                    beq @done
+                    clc
+                    adc $00
+                    dex
+                    jmp @loop
+            @done:  rts
+            forever:
+                    jmp forever
+            jam:    .byte $02
+            ; drop the return address and continue at the caller
+            reach:  pla
+                    pla
+                    jmp $9000
+            nmi:    rti
+                    .org $FFFA
+                    .word nmi
+            ",
+        )
+        .unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        let mut state = cpu.state();
+        state.set_pc(0x9000);
+        state.set_sp(0xFD);
+        state.set_a(0x55);
+        cpu.set_state(&state);
+        (cpu, mem, program)
+    }
+
+    #[test]
+    fn call_returns_registers() {
+        let (mut cpu, mut mem, program) = setup();
+        let before = cpu.state();
+
+        let mut regs = CpuState::default();
+        regs.set_a(6);
+        regs.set_x(7);
+        regs.set_sp(0xFF);
+        let multiply = program.symbol("multiply").unwrap();
+        let result = cpu.call(&mut mem, multiply, &regs, 1000).unwrap();
+
+        assert_eq!(result.state.a(), 42);
+        assert_eq!(result.state.x(), 0);
+        assert_eq!(result.state.sp(), 0xFF);
+        assert_eq!(result.state.pc(), RETURN_SENTINEL);
+        // STA 3, LDA 2, 7 times CPX 2, BEQ 2, CLC 2, ADC 3, DEX 2, JMP 3,
+        // then CPX 2, BEQ 3 and RTS 6
+        assert_eq!(result.cycles, 3 + 2 + 7 * 14 + 2 + 3 + 6);
+        // the return address was pushed on the stack of the routine
+        assert_eq!(mem.read(0x01FF), 0xFF);
+        assert_eq!(mem.read(0x01FE), 0xFE);
+
+        let mut after = cpu.state();
+        assert_eq!(after.cycles(), before.cycles() + 2 + result.cycles);
+        after.set_cycles(before.cycles());
+        assert_eq!(after, before);
+    }
+
+    #[test]
+    fn call_with_pending_nmi() {
+        let (mut cpu, mut mem, program) = setup();
+        mem.write(0x9000, 0xEA);
+        cpu.set_history(10);
+        cpu.set_nmi(true);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.pending_interrupt, Some(Interrupt::Nmi));
+        let before = cpu.state();
+
+        let mut regs = CpuState::default();
+        regs.set_a(6);
+        regs.set_x(7);
+        let multiply = program.symbol("multiply").unwrap();
+        let result = cpu.call(&mut mem, multiply, &regs, 1000).unwrap();
+        // not interrupted
+        assert_eq!(result.state.a(), 42);
+        assert_eq!(result.cycles, 3 + 2 + 7 * 14 + 2 + 3 + 6);
+        assert_eq!(cpu.history_len(), 1);
+
+        // the NMI is serviced once, after the call
+        assert_eq!(cpu.state().pc(), before.pc());
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.state().pc(), program.symbol("nmi").unwrap());
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.state().pc(), before.pc());
+        assert_eq!(cpu.pending_interrupt, None);
+
+        // stepping back does not rewind into the call
+        for _ in 0..2 {
+            assert!(cpu.step_back(&mut mem));
+        }
+        let mut after_call = before;
+        after_call.set_cycles(before.cycles() + 2 + result.cycles);
+        assert_eq!(cpu.state(), after_call);
+        assert_eq!(cpu.pending_interrupt, Some(Interrupt::Nmi));
+    }
+
+    #[test]
+    fn call_ends_at_rts() {
+        let (mut cpu, mut mem, program) = setup();
+        // JMP $9000
+        mem.load(0x9000, &[0x4C, 0x00, 0x90]);
+        let mut regs = CpuState::default();
+        regs.set_sp(0xFD);
+
+        // the caller's address at the caller's stack level is not a return
+        let reach = program.symbol("reach").unwrap();
+        match cpu.call(&mut mem, reach, &regs, 100) {
+            Err(CallError::Timeout(state)) => {
+                assert_eq!(state.pc(), 0x9000);
+                assert_eq!(state.sp(), 0xFD);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn call_errors() {
+        let (mut cpu, mut mem, program) = setup();
+        cpu.set_call_stack(true);
+        let regs = CpuState::default();
+
+        let forever = program.symbol("forever").unwrap();
+        match cpu.call(&mut mem, forever, &regs, 100) {
+            Err(CallError::Timeout(state)) => assert_eq!(state.pc(), forever),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let jam = program.symbol("jam").unwrap();
+        match cpu.call(&mut mem, jam, &regs, 100) {
+            Err(CallError::Stop(Stop::Error(error))) => {
+                assert_eq!(error.pc, jam);
+                assert_eq!(
+                    error.to_string(),
+                    format!("processor jammed at ${:04X} (opcode $02)", jam)
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // the caller is unaffected
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.state().pc(), 0x9000);
+        assert!(cpu.call_stack().is_empty());
+        assert!(cpu.take_stack_anomalies().is_empty());
+
+        // a breakpoint in the routine stops the call, but it does not keep
+        // the caller from stopping at the same address
+        let multiply = program.symbol("multiply").unwrap();
+        let id = cpu.add_breakpoint(Breakpoint::execute(multiply));
+        let stop = Stop::Breakpoint { id, pc: multiply };
+        assert_eq!(cpu.call(&mut mem, multiply, &regs, 100), Err(CallError::Stop(stop)));
+        let mut state = cpu.state();
+        state.set_pc(multiply);
+        cpu.set_state(&state);
+        assert_eq!(cpu.step(&mut mem), Err(stop));
+    }
+}
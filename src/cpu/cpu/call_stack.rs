@@ -0,0 +1,291 @@
+//! Shadow call stack, see `cpu::debug::Frame`.
+//!
+//! JSR, BRK and the hardware interrupts push a frame, RTS and RTI pop the
+//! frame at the stack level they return to. Frames are matched by the stack
+//! pointer rather than by their order, so code which pushes its own return
+//! addresses or drops them from the stack does not derail the call stack,
+//! it is reported as a `StackAnomaly` instead.
+
+use super::*;
+use cpu::debug::{Backtrace, Frame, FrameKind, Return, StackAnomaly};
+
+/// Anomalies kept until they are taken, older ones are dropped.
+const MAX_ANOMALIES: usize = 256;
+
+impl CPU {
+    /// Turn the shadow call stack on or off. It is off by default, turning it
+    /// on starts with an empty stack.
+    pub fn set_call_stack(&mut self, enabled: bool) {
+        self.call_stack = if enabled { Some(Vec::new()) } else { None };
+        self.stack_anomalies.clear();
+    }
+
+    /// The frames of the shadow call stack, the outermost first. Empty if it
+    /// is turned off.
+    pub fn call_stack(&self) -> &[Frame] {
+        match self.call_stack {
+            Some(ref frames) => frames,
+            None => &[],
+        }
+    }
+
+    /// The call stack and the program counter, for printing.
+    pub fn backtrace(&self) -> Backtrace {
+        Backtrace {
+            pc: self.program_counter,
+            frames: self.call_stack().to_vec(),
+        }
+    }
+
+    /// The anomalies detected since the last call, the oldest first.
+    pub fn take_stack_anomalies(&mut self) -> Vec<StackAnomaly> {
+        ::std::mem::take(&mut self.stack_anomalies)
+    }
+
+    /// Push a frame for a call or interrupt of the instruction at `caller`.
+    /// `sp` is the stack pointer before the return address was pushed and
+    /// the program counter is the entry of the subroutine or handler.
+    pub(super) fn enter_frame(
+        &mut self,
+        kind: FrameKind,
+        caller: u16,
+        return_address: u16,
+        sp: u8,
+    ) {
+        // frames at or below the new one were overwritten
+        let live = match self.call_stack {
+            Some(ref frames) => frames.iter().take_while(|f| f.sp > sp).count(),
+            None => return,
+        };
+        self.abandon_frames(caller, live);
+
+        let frame = Frame {
+            kind,
+            caller,
+            entry: self.program_counter,
+            return_address,
+            sp,
+        };
+        if let Some(ref mut frames) = self.call_stack {
+            frames.push(frame);
+        }
+        self.record_enter_frame();
+        self.notify_enter_frame(&frame);
+    }
+
+    /// Pop the frame a return of the instruction at `pc` to `target` leaves.
+    pub(super) fn leave_frame(&mut self, ret: Return, pc: u16, target: u16) {
+        let sp = self.stack_pointer;
+        let (left, live) = match self.call_stack {
+            Some(ref frames) => {
+                let left = frames.iter().rposition(|f| f.sp == sp);
+                // frames above the stack pointer are gone
+                let live = match left {
+                    Some(index) => index + 1,
+                    None => frames.iter().take_while(|f| f.sp >= sp).count(),
+                };
+                (left, live)
+            }
+            None => return,
+        };
+        self.abandon_frames(pc, live);
+
+        let frame = match left.and_then(|_| self.call_stack.as_mut()?.pop()) {
+            Some(frame) => frame,
+            None => {
+                self.stack_anomaly(StackAnomaly::Unmatched { pc, ret, target });
+                return;
+            }
+        };
+        self.record_leave_frame(&frame);
+        self.notify_leave_frame(&frame);
+
+        let kind = match frame.kind {
+            FrameKind::Subroutine => Return::Rts,
+            _ => Return::Rti,
+        };
+        if kind != ret || frame.return_address != target {
+            self.stack_anomaly(StackAnomaly::Mismatch {
+                pc,
+                ret,
+                target,
+                frame,
+            });
+        }
+    }
+
+    /// Drop all but the `live` outermost frames, they were left without a
+    /// return. `pc` is the instruction which noticed.
+    pub(super) fn abandon_frames(&mut self, pc: u16, live: usize) {
+        let dropped = match self.call_stack {
+            Some(ref mut frames) if frames.len() > live => frames.split_off(live),
+            _ => return,
+        };
+
+        for frame in dropped.iter().rev() {
+            self.record_leave_frame(frame);
+            self.notify_leave_frame(frame);
+        }
+        self.stack_anomaly(StackAnomaly::Abandoned {
+            pc,
+            frames: dropped.len(),
+        });
+    }
+
+    fn stack_anomaly(&mut self, anomaly: StackAnomaly) {
+        if self.stack_anomalies.len() == MAX_ANOMALIES {
+            self.stack_anomalies.remove(0);
+        }
+        self.stack_anomalies.push(anomaly);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::asm;
+    use cpu::bus::Ram;
+
+    fn run(source: &str, steps: usize) -> (CPU, Ram, asm::Program) {
+        let program = asm::assemble(Variant::Ricoh2A03, source).unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xFD;
+        cpu.program_counter = program.symbol("start").unwrap();
+        cpu.set_call_stack(true);
+        for _ in 0..steps {
+            cpu.step(&mut mem).unwrap();
+        }
+        (cpu, mem, program)
+    }
+
+    #[test]
+    fn nested_calls() {
+        let source = "
+                    .org $8000
+            start:  jsr outer
+                    jmp start
+            outer:  jsr inner
+                    rts
+            inner:  brk
+                    .byte 0
+                    rts
+            irq:    nop
+                    rti
+                    .org $FFFE
+                    .word irq
+        ";
+        let (mut cpu, mut mem, program) = run(source, 4);
+        let symbol = |name| program.symbol(name).unwrap();
+
+        let frames = cpu.call_stack().to_vec();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].kind, FrameKind::Subroutine);
+        assert_eq!(frames[0].entry, symbol("outer"));
+        assert_eq!(frames[0].return_address, 0x8003);
+        assert_eq!(frames[0].sp, 0xFD);
+        assert_eq!(frames[2].kind, FrameKind::Brk);
+        assert_eq!(frames[2].return_address, symbol("inner") + 2);
+
+        assert_eq!(
+            cpu.backtrace().to_string(),
+            "#0  $800E in $800D (BRK from $800A)\n\
+             #1  $800A in $800A (JSR from $8006)\n\
+             #2  $8006 in $8006 (JSR from $8000)\n\
+             #3  $8000"
+        );
+
+        // return from all three and call again
+        for _ in 0..5 {
+            cpu.step(&mut mem).unwrap();
+        }
+        assert_eq!(cpu.program_counter, symbol("outer"));
+        assert_eq!(cpu.call_stack().len(), 1);
+        assert!(cpu.take_stack_anomalies().is_empty());
+    }
+
+    #[test]
+    fn rts_jump_table() {
+        let source = "
+                    .org $8000
+            start:  jsr dispatch
+                    jmp start
+            dispatch:
+                    lda #>(target - 1)
+                    pha
+                    lda #<(target - 1)
+                    pha
+                    rts
+            target: rts
+        ";
+        let (mut cpu, mut mem, program) = run(source, 6);
+        assert_eq!(cpu.program_counter, program.symbol("target").unwrap());
+        assert_eq!(
+            cpu.take_stack_anomalies(),
+            vec![StackAnomaly::Unmatched {
+                pc: program.symbol("target").unwrap() - 1,
+                ret: Return::Rts,
+                target: program.symbol("target").unwrap(),
+            }]
+        );
+        // the frame of the JSR survives and the jump target returns from it
+        assert_eq!(cpu.call_stack().len(), 1);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.program_counter, 0x8003);
+        assert!(cpu.call_stack().is_empty());
+        assert!(cpu.take_stack_anomalies().is_empty());
+    }
+
+    #[test]
+    fn mismatched_returns() {
+        // `skip` drops its return address and returns to the caller of
+        // `outer`, `patch` returns past the byte after its JSR
+        let source = "
+                    .org $8000
+            start:  jsr outer
+                    jsr patch
+                    .byte 0
+                    jmp start
+            outer:  jsr skip
+                    rts
+            skip:   pla
+                    pla
+                    rts
+            patch:  pla
+                    clc
+                    adc #1
+                    tax
+                    pla
+                    adc #0
+                    pha
+                    txa
+                    pha
+                    rts
+        ";
+        let (mut cpu, _, program) = run(source, 5);
+        let symbol = |name| program.symbol(name).unwrap();
+        assert_eq!(cpu.program_counter, 0x8003);
+        assert_eq!(
+            cpu.take_stack_anomalies(),
+            vec![StackAnomaly::Abandoned {
+                pc: symbol("skip") + 2,
+                frames: 1,
+            }]
+        );
+        assert!(cpu.call_stack().is_empty());
+
+        let (mut cpu, _, _) = run(source, 16);
+        assert_eq!(cpu.program_counter, 0x8007);
+        let anomalies = cpu.take_stack_anomalies();
+        assert_eq!(anomalies.len(), 2);
+        assert_eq!(
+            anomalies[1].to_string(),
+            format!(
+                "${:04X}: Rts to $8007 leaves the JSR from $8003 returning to $8006",
+                symbol("patch") + 11
+            )
+        );
+    }
+}
@@ -0,0 +1,406 @@
+//! Undo log for stepping backwards.
+//!
+//! While the history is enabled every step records the registers and the
+//! internal state of the CPU before it, the old value of every byte it
+//! writes and the frames it pushes onto or pops from the shadow call stack.
+//! Undoing a step restores all of them. The old values and the popped frames
+//! of all steps are kept in two shared logs, a step only counts its share,
+//! so recording a step does not allocate.
+//!
+//! The old values are read with `Bus::peek` and restored with `Bus::poke`,
+//! memory the bus cannot peek or poke is not restored. Devices on the bus
+//! are not rewound either. The anomalies the shadow call stack reported are
+//! not restored, and steps before it was turned on or off do not restore
+//! its frames correctly.
+
+use super::*;
+use std::collections::VecDeque;
+
+/// Everything needed to undo one step, except for its share of the logs of
+/// the `History`.
+pub(super) struct UndoEntry {
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: u8,
+    /// the raw status register, including the break bit
+    status: u8,
+    pc: u16,
+    cycles: usize,
+    halted: bool,
+    waiting: bool,
+    error: Option<CpuError>,
+    interrupt_lines: InterruptLines,
+    pending_interrupt: Option<Interrupt>,
+    irq_sampled: bool,
+    nmi_sampled: bool,
+    irq_sampled_prev: bool,
+    nmi_sampled_prev: bool,
+    /// bytes written by the step, their old values are at the end of
+    /// `History::writes`
+    writes: u16,
+    /// frames pushed onto the shadow call stack and not popped again
+    frames_pushed: u16,
+    /// frames popped from the shadow call stack, they are at the end of
+    /// `History::frames`
+    frames_popped: u16,
+}
+
+/// The recorded steps, the most recent at the back.
+#[derive(Default)]
+pub(super) struct History {
+    entries: VecDeque<UndoEntry>,
+    /// addresses written with their previous values, in the order of the
+    /// writes
+    writes: VecDeque<(u16, Option<u8>)>,
+    /// frames popped from the shadow call stack, in the order they were
+    /// popped
+    frames: VecDeque<Frame>,
+}
+
+impl History {
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+        self.writes.clear();
+        self.frames.clear();
+    }
+
+    /// Drop the oldest step.
+    fn pop_front(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            self.writes.drain(..entry.writes as usize);
+            self.frames.drain(..entry.frames_popped as usize);
+        }
+    }
+}
+
+impl CPU {
+    /// Keep the undo information of the last `steps` steps, see `step_back`.
+    /// 0, the default, turns the history off and drops what was recorded.
+    pub fn set_history(&mut self, steps: usize) {
+        self.history_capacity = steps;
+        while self.history.entries.len() > steps {
+            self.history.pop_front();
+        }
+    }
+
+    /// Number of steps which can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.entries.len()
+    }
+
+    /// Undo the last step, an instruction or an interrupt sequence. Returns
+    /// `false` if the history is empty.
+    pub fn step_back<B: Bus>(&mut self, bus: &mut B) -> bool {
+        let entry = match self.history.entries.pop_back() {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        // the last write first
+        for _ in 0..entry.writes {
+            if let Some((addr, Some(val))) = self.history.writes.pop_back() {
+                bus.poke(addr, val);
+            }
+        }
+
+        if let Some(ref mut frames) = self.call_stack {
+            let live = frames.len().saturating_sub(entry.frames_pushed as usize);
+            frames.truncate(live);
+        }
+        // the last frame popped was the outermost
+        for _ in 0..entry.frames_popped {
+            let frame = self.history.frames.pop_back();
+            if let (Some(frames), Some(frame)) = (self.call_stack.as_mut(), frame) {
+                frames.push(frame);
+            }
+        }
+
+        self.accumulator = entry.a;
+        self.index_x = entry.x;
+        self.index_y = entry.y;
+        self.stack_pointer = entry.sp;
+        self.program_counter = entry.pc;
+        self.status_register.set_all(entry.status);
+        self.cycles = entry.cycles;
+        self.halted = entry.halted;
+        self.waiting = entry.waiting;
+        self.error = entry.error;
+        self.interrupt_lines = entry.interrupt_lines;
+        self.pending_interrupt = entry.pending_interrupt;
+        self.irq_sampled = entry.irq_sampled;
+        self.nmi_sampled = entry.nmi_sampled;
+        self.irq_sampled_prev = entry.irq_sampled_prev;
+        self.nmi_sampled_prev = entry.nmi_sampled_prev;
+        self.resume_pc = None;
+        true
+    }
+
+    /// Step back until the next instruction has an execution breakpoint
+    /// whose condition is true, and return it. Hits are not counted. Returns
+    /// `None` if the history ran out first.
+    pub fn reverse_continue<B: Bus>(&mut self, bus: &mut B) -> Option<Stop> {
+        while self.step_back(bus) {
+            if self.pending_interrupt.is_some() {
+                continue;
+            }
+            if let Some(id) = self.execute_breakpoint_at(bus) {
+                let pc = self.program_counter;
+                // continuing forward executes the instruction
+                self.resume_pc = Some(pc);
+                return Some(Stop::Breakpoint { id, pc });
+            }
+        }
+        None
+    }
+
+    /// Start recording the step about to be executed.
+    pub(super) fn begin_undo(&mut self) {
+        self.recording = Some(UndoEntry {
+            a: self.accumulator,
+            x: self.index_x,
+            y: self.index_y,
+            sp: self.stack_pointer,
+            status: self.status_register.to_u8(),
+            pc: self.program_counter,
+            cycles: self.cycles,
+            halted: self.halted,
+            waiting: self.waiting,
+            error: self.error,
+            interrupt_lines: self.interrupt_lines.clone(),
+            pending_interrupt: self.pending_interrupt,
+            irq_sampled: self.irq_sampled,
+            nmi_sampled: self.nmi_sampled,
+            irq_sampled_prev: self.irq_sampled_prev,
+            nmi_sampled_prev: self.nmi_sampled_prev,
+            writes: 0,
+            frames_pushed: 0,
+            frames_popped: 0,
+        });
+    }
+
+    /// Remember the old value before `addr` is written.
+    pub(super) fn record_write(&mut self, bus: &dyn Bus, addr: u16) {
+        if let Some(ref mut entry) = self.recording {
+            entry.writes += 1;
+            self.history.writes.push_back((addr, bus.peek(addr)));
+        }
+    }
+
+    /// Remember that a frame was pushed onto the shadow call stack.
+    pub(super) fn record_enter_frame(&mut self) {
+        if let Some(ref mut entry) = self.recording {
+            entry.frames_pushed += 1;
+        }
+    }
+
+    /// Remember `frame` which was popped from the shadow call stack.
+    pub(super) fn record_leave_frame(&mut self, frame: &Frame) {
+        if let Some(ref mut entry) = self.recording {
+            if entry.frames_pushed > 0 {
+                // pushed by the same step, undoing it drops it anyway
+                entry.frames_pushed -= 1;
+            } else {
+                entry.frames_popped += 1;
+                self.history.frames.push_back(*frame);
+            }
+        }
+    }
+
+    /// Add the recorded step to the history.
+    pub(super) fn end_undo(&mut self) {
+        if let Some(entry) = self.recording.take() {
+            if self.history.entries.len() == self.history_capacity {
+                self.history.pop_front();
+            }
+            self.history.entries.push_back(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::asm;
+    use cpu::bus::Ram;
+    use cpu::debug::Breakpoint;
+
+    fn setup() -> (CPU, Ram, asm::Program) {
+        let program = asm::assemble(
+            Variant::Ricoh2A03,
+            "
+                    .org $8000
+            start:  ldx #0
+            loop:   inx
+                    stx $0300
+                    txa
+                    pha
+                    jmp loop
+            ",
+        )
+        .unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xFD;
+        cpu.program_counter = 0x8000;
+        cpu.set_history(100);
+        (cpu, mem, program)
+    }
+
+    #[test]
+    fn step_back_restores_registers_and_memory() {
+        let (mut cpu, mut mem, _) = setup();
+
+        let mut states = vec![cpu.state()];
+        let mut memory = vec![(mem.read(0x0300), mem.read(0x01FD))];
+        for _ in 0..12 {
+            cpu.step(&mut mem).unwrap();
+            states.push(cpu.state());
+            memory.push((mem.read(0x0300), mem.read(0x01FD)));
+        }
+        assert_eq!(cpu.history_len(), 12);
+
+        while cpu.step_back(&mut mem) {
+            states.pop();
+            memory.pop();
+            assert_eq!(cpu.state(), *states.last().unwrap());
+            assert_eq!((mem.read(0x0300), mem.read(0x01FD)), *memory.last().unwrap());
+        }
+        assert_eq!(states.len(), 1);
+
+        // replaying gives the same result
+        for _ in 0..12 {
+            cpu.step(&mut mem).unwrap();
+        }
+        assert_eq!(cpu.index_x, 3);
+    }
+
+    #[test]
+    fn step_back_restores_call_stack() {
+        let program = asm::assemble(
+            Variant::Ricoh2A03,
+            "
+                    .org $8000
+            start:  jsr outer
+            outer:  jsr inner
+            inner:  ldx #$FF
+                    txs
+                    jsr done
+            done:   rts
+            ",
+        )
+        .unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xFD;
+        cpu.program_counter = 0x8000;
+        cpu.set_call_stack(true);
+        cpu.set_history(10);
+
+        // the last JSR abandons both frames and pushes a new one, the RTS
+        // pops it
+        let mut stacks = vec![cpu.call_stack().to_vec()];
+        for _ in 0..6 {
+            cpu.step(&mut mem).unwrap();
+            stacks.push(cpu.call_stack().to_vec());
+        }
+        assert_eq!(stacks[4].len(), 2);
+        assert_eq!(stacks[5].len(), 1);
+        assert_eq!(stacks[6].len(), 0);
+
+        while cpu.step_back(&mut mem) {
+            stacks.pop();
+            assert_eq!(cpu.call_stack(), &stacks.last().unwrap()[..]);
+        }
+        assert_eq!(stacks.len(), 1);
+        assert!(cpu.history.writes.is_empty());
+        assert!(cpu.history.frames.is_empty());
+    }
+
+    #[test]
+    fn step_back_restores_wai() {
+        let program = asm::assemble(
+            Variant::Cmos65C02,
+            "
+                    .org $8000
+            start:  sei
+                    wai
+            wake:   nop
+            ",
+        )
+        .unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        cpu.set_variant(Variant::Cmos65C02);
+        cpu.program_counter = 0x8000;
+        cpu.set_history(10);
+        let wake = program.symbol("wake").unwrap();
+        let id = cpu.add_breakpoint(Breakpoint::execute(wake));
+
+        for _ in 0..3 {
+            cpu.step(&mut mem).unwrap();
+        }
+        assert!(cpu.waiting);
+        let waiting = cpu.state();
+
+        // a masked IRQ ends the wait at the breakpoint, which is undone as
+        // a step of its own
+        cpu.set_irq(IrqSource::External, true);
+        assert_eq!(cpu.step(&mut mem), Err(Stop::Breakpoint { id, pc: wake }));
+        assert!(!cpu.waiting);
+        assert_eq!(cpu.history_len(), 4);
+
+        assert!(cpu.step_back(&mut mem));
+        assert!(cpu.waiting);
+        assert_eq!(cpu.state(), waiting);
+    }
+
+    #[test]
+    fn history_window() {
+        let (mut cpu, mut mem, _) = setup();
+        cpu.set_history(5);
+        cpu.run_for(&mut mem, 100).unwrap();
+        assert_eq!(cpu.history_len(), 5);
+
+        let mut undone = 0;
+        while cpu.step_back(&mut mem) {
+            undone += 1;
+        }
+        assert_eq!(undone, 5);
+        assert!(cpu.history.writes.is_empty());
+
+        cpu.set_history(0);
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(cpu.history_len(), 0);
+    }
+
+    #[test]
+    fn reverse_continue_to_breakpoint() {
+        let (mut cpu, mut mem, program) = setup();
+        let store = program.symbol("loop").unwrap() + 1;
+        let id = cpu.add_breakpoint(Breakpoint::execute(store).with_condition("X == 2").unwrap());
+
+        cpu.breakpoint_mut(id).unwrap().enabled = false;
+        cpu.run_for(&mut mem, 200).unwrap();
+        cpu.breakpoint_mut(id).unwrap().enabled = true;
+
+        assert_eq!(cpu.reverse_continue(&mut mem), Some(Stop::Breakpoint { id, pc: store }));
+        assert_eq!(cpu.index_x, 2);
+        assert_eq!(mem.read(0x0300), 1);
+        assert_eq!(cpu.breakpoint(id).unwrap().hits, 0);
+
+        // forward again executes the instruction at the breakpoint
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(mem.read(0x0300), 2);
+
+        cpu.remove_breakpoint(id);
+        assert_eq!(cpu.reverse_continue(&mut mem), None);
+        assert_eq!(cpu.program_counter, 0x8000);
+    }
+}
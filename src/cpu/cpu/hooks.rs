@@ -0,0 +1,176 @@
+//! Registration and notification of the hooks of `cpu::hook`.
+
+use super::*;
+use cpu::debug::Frame;
+use cpu::hook::{Access, Hook, HookId, InstructionEvent};
+
+impl CPU {
+    /// Register `hook` to be called for every instruction and bus access
+    /// from now on. Hooks are called in the order they were added.
+    pub fn add_hook(&mut self, hook: Box<dyn Hook>) -> HookId {
+        let id = HookId(self.next_hook_id);
+        self.next_hook_id += 1;
+        self.hooks.push((id, hook));
+        id
+    }
+
+    /// Unregister a hook and hand it back, `None` if it was already removed.
+    pub fn remove_hook(&mut self, id: HookId) -> Option<Box<dyn Hook>> {
+        let index = self.hooks.iter().position(|h| h.0 == id)?;
+        Some(self.hooks.remove(index).1)
+    }
+
+    pub(super) fn has_hooks(&self) -> bool {
+        !self.hooks.is_empty()
+    }
+
+    /// Report a bus access.
+    pub(super) fn notify_access(&mut self, addr: u16, value: u8, kind: Access) {
+        for hook in &mut self.hooks {
+            hook.1.access(addr, value, kind);
+        }
+    }
+
+    /// Report the instruction whose opcode was just fetched.
+    pub(super) fn notify_before(&mut self, bus: &dyn Bus, pc: u16, opcode: u8, opi: &OpInfo) {
+        let mut operand = [0; 2];
+        for (i, b) in operand.iter_mut().enumerate().take(opi.bytes - 1) {
+            *b = bus.peek(pc.wrapping_add(i as u16 + 1)).unwrap_or(0);
+        }
+
+        let event = InstructionEvent {
+            pc,
+            opcode,
+            len: opi.bytes,
+            operand,
+            state: self.state(),
+            cycles: 0,
+        };
+        self.current_instruction = Some(event);
+
+        for hook in &mut self.hooks {
+            hook.1.before_instruction(&event);
+        }
+    }
+
+    /// Report the completion of the current instruction.
+    pub(super) fn notify_after(&mut self, cycles: usize) {
+        if let Some(mut event) = self.current_instruction.take() {
+            event.state = self.state();
+            event.cycles = cycles;
+
+            for hook in &mut self.hooks {
+                hook.1.after_instruction(&event);
+            }
+        }
+    }
+
+    /// Report a frame pushed on the shadow call stack.
+    pub(super) fn notify_enter_frame(&mut self, frame: &Frame) {
+        for hook in &mut self.hooks {
+            hook.1.enter_frame(frame);
+        }
+    }
+
+    /// Report a frame popped off the shadow call stack.
+    pub(super) fn notify_leave_frame(&mut self, frame: &Frame) {
+        for hook in &mut self.hooks {
+            hook.1.leave_frame(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::bus::Ram;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Recorder {
+        accesses: Vec<(u16, u8, Access)>,
+        before: Vec<InstructionEvent>,
+        after: Vec<InstructionEvent>,
+    }
+
+    impl Hook for Recorder {
+        fn before_instruction(&mut self, event: &InstructionEvent) {
+            self.before.push(*event);
+        }
+
+        fn after_instruction(&mut self, event: &InstructionEvent) {
+            self.after.push(*event);
+        }
+
+        fn access(&mut self, addr: u16, value: u8, kind: Access) {
+            self.accesses.push((addr, value, kind));
+        }
+    }
+
+    #[test]
+    fn hooks_see_instructions_and_accesses() {
+        let mut mem = Ram::new();
+        // LDA $10; STA $0200
+        mem.load(0x0000, &[0xA5, 0x10, 0x8D, 0x00, 0x02]);
+        mem.write(0x0010, 0x42);
+
+        let mut cpu = CPU::new();
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let id = cpu.add_hook(Box::new(recorder.clone()));
+
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+
+        {
+            let r = recorder.borrow();
+            assert_eq!(
+                r.accesses,
+                vec![
+                    (0x0000, 0xA5, Access::Execute),
+                    (0x0001, 0x10, Access::Operand),
+                    (0x0010, 0x42, Access::Read),
+                    (0x0002, 0x8D, Access::Execute),
+                    (0x0003, 0x00, Access::Operand),
+                    (0x0004, 0x02, Access::Operand),
+                    (0x0200, 0x42, Access::Write),
+                ]
+            );
+
+            assert_eq!(r.before.len(), 2);
+            assert_eq!(r.before[1].pc, 0x0002);
+            assert_eq!(r.before[1].opcode, 0x8D);
+            assert_eq!(r.before[1].len, 3);
+            assert_eq!(r.before[1].operand, [0x00, 0x02]);
+            assert_eq!(r.before[0].state.a(), 0x00);
+            assert_eq!(r.after[0].state.a(), 0x42);
+            assert_eq!(r.after[0].cycles, 3);
+            assert_eq!(r.after[1].cycles, 4);
+        }
+
+        assert!(cpu.remove_hook(id).is_some());
+        assert!(cpu.remove_hook(id).is_none());
+        cpu.program_counter = 0x0000;
+        cpu.step(&mut mem).unwrap();
+        assert_eq!(recorder.borrow().before.len(), 2);
+    }
+
+    #[test]
+    fn hooks_see_dummy_accesses_in_cycle_timing() {
+        let mut mem = Ram::new();
+        // INC $10
+        mem.load(0x0000, &[0xE6, 0x10]);
+
+        let mut cpu = CPU::new();
+        cpu.set_timing(Timing::Cycle);
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        cpu.add_hook(Box::new(recorder.clone()));
+        cpu.step(&mut mem).unwrap();
+
+        let kinds: Vec<Access> = recorder.borrow().accesses.iter().map(|a| a.2).collect();
+        assert_eq!(
+            kinds,
+            vec![Access::Execute, Access::Operand, Access::Read, Access::DummyWrite, Access::Write]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! Checks of the lint mode, see `cpu::lint`.
+//!
+//! The memory map is that of the NES, see `memory`: 2 kB of internal RAM
+//! mirrored up to $1FFF, I/O registers and the expansion area up to $5FFF,
+//! cartridge RAM from $6000 and the cartridge ROM from $8000. Until the PPU
+//! is emulated its position is derived from the cycle counter like in the
+//! trace.
+
+use super::*;
+use cpu::lint::{LintKind, LintReport, LintWarning};
+use memory::{
+    CARTRIDGE_RAM, CARTRIDGE_ROM, PPU_REGISTERS_END, PPU_REGISTER_MASK, RAM_END, RAM_SIZE,
+};
+use std::collections::HashMap;
+
+/// Frames kept in the backtrace of a warning.
+const BACKTRACE_DEPTH: usize = 4;
+
+/// Visible scanlines and the pre-render scanline, the PPU renders in these
+/// if the background or the sprites are enabled.
+const VISIBLE_SCANLINES: usize = 240;
+const PRE_RENDER_SCANLINE: usize = 261;
+
+/// PPUMASK bits enabling the background and the sprites.
+const RENDERING_ENABLED: u8 = 0x18;
+
+pub(super) struct Linter {
+    allowed: Vec<LintKind>,
+    warnings: Vec<LintWarning>,
+    /// index of the warning of a kind and instruction
+    seen: HashMap<(LintKind, u16), usize>,
+    /// internal RAM written so far
+    written: Vec<bool>,
+    /// last value written to PPUMASK
+    ppu_mask: u8,
+    /// the instruction or interrupt being executed
+    pub(super) pc: u16,
+    /// whether the previous instruction was in ROM
+    in_rom: bool,
+}
+
+impl CPU {
+    /// Turn the lint mode on or off, it is off by default. Turning it on
+    /// also turns on the shadow call stack for the backtraces of the
+    /// warnings and starts with an empty report and unwritten RAM.
+    pub fn set_lint(&mut self, enabled: bool) {
+        if !enabled {
+            self.lint = None;
+            return;
+        }
+
+        if self.call_stack.is_none() {
+            self.set_call_stack(true);
+        }
+        self.lint = Some(Box::new(Linter {
+            allowed: Vec::new(),
+            warnings: Vec::new(),
+            seen: HashMap::new(),
+            written: vec![false; RAM_SIZE],
+            ppu_mask: 0,
+            pc: self.program_counter,
+            in_rom: true,
+        }));
+    }
+
+    /// Stop warning about `kind`, e.g. `LintKind::RomWrite` for a cartridge
+    /// with mapper registers.
+    pub fn allow_lint(&mut self, kind: LintKind) {
+        if let Some(ref mut lint) = self.lint {
+            lint.allowed.push(kind);
+        }
+    }
+
+    /// The warnings so far, empty if the lint mode is off.
+    pub fn lint_report(&self) -> LintReport {
+        LintReport {
+            warnings: match self.lint {
+                Some(ref lint) => lint.warnings.clone(),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    /// Check the instruction at `pc` whose opcode was just fetched.
+    pub(super) fn lint_instruction(&mut self, bus: &dyn Bus, pc: u16, opcode: u8) {
+        let in_rom = pc >= CARTRIDGE_ROM;
+        let was_in_rom = match self.lint {
+            Some(ref mut lint) => ::std::mem::replace(&mut lint.in_rom, in_rom),
+            None => return,
+        };
+        if was_in_rom && !in_rom {
+            let kind = if (RAM_END..CARTRIDGE_RAM).contains(&pc) {
+                LintKind::ExecuteIo
+            } else {
+                LintKind::ExecuteRam
+            };
+            self.lint_warn(kind, pc);
+        }
+
+        // fixed by the 65C02
+        if opcode == 0x6C && self.variant != Variant::Cmos65C02 {
+            let lo = bus.peek(pc.wrapping_add(1));
+            let hi = bus.peek(pc.wrapping_add(2));
+            if let (Some(0xFF), Some(hi)) = (lo, hi) {
+                self.lint_warn(LintKind::JmpIndirectPageBug, (hi as u16) << 8 | 0xFF);
+            }
+        }
+    }
+
+    /// Check a data read.
+    pub(super) fn lint_read(&mut self, addr: u16) {
+        if addr >= RAM_END {
+            return;
+        }
+        let written = match self.lint {
+            Some(ref lint) => lint.written[addr as usize % RAM_SIZE],
+            None => return,
+        };
+        if !written {
+            self.lint_warn(LintKind::UninitializedRead, addr);
+        }
+    }
+
+    pub(super) fn lint_write(&mut self, addr: u16, val: u8) {
+        let rendering = self.is_rendering();
+        let warning = match self.lint {
+            Some(ref mut lint) if addr < RAM_END => {
+                lint.written[addr as usize % RAM_SIZE] = true;
+                None
+            }
+            Some(ref mut lint) if addr < PPU_REGISTERS_END => match addr & PPU_REGISTER_MASK {
+                1 => {
+                    lint.ppu_mask = val;
+                    None
+                }
+                3 | 4 | 7 if rendering => Some(LintKind::PpuWriteWhileRendering),
+                _ => None,
+            },
+            Some(_) if addr >= CARTRIDGE_ROM => Some(LintKind::RomWrite),
+            _ => None,
+        };
+
+        if let Some(kind) = warning {
+            self.lint_warn(kind, addr);
+        }
+    }
+
+    /// Check the stack pointer before a push or pull.
+    pub(super) fn lint_stack(&mut self, push: bool) {
+        match (push, self.stack_pointer) {
+            (true, 0x00) => self.lint_warn(LintKind::StackOverflow, STACK_BASE_ADDRESS),
+            (false, 0xFF) => self.lint_warn(LintKind::StackUnderflow, STACK_BASE_ADDRESS + 0xFF),
+            _ => {}
+        }
+    }
+
+    fn is_rendering(&self) -> bool {
+        let mask = match self.lint {
+            Some(ref lint) => lint.ppu_mask,
+            None => return false,
+        };
+        let (scanline, _) = self.ppu_position();
+        mask & RENDERING_ENABLED != 0
+            && (scanline < VISIBLE_SCANLINES || scanline == PRE_RENDER_SCANLINE)
+    }
+
+    fn lint_warn(&mut self, kind: LintKind, addr: u16) {
+        let pc = match self.lint {
+            Some(ref mut lint) => {
+                if lint.allowed.contains(&kind) {
+                    return;
+                }
+                if let Some(&index) = lint.seen.get(&(kind, lint.pc)) {
+                    lint.warnings[index].count += 1;
+                    return;
+                }
+                lint.pc
+            }
+            None => return,
+        };
+
+        let mut backtrace = self.backtrace();
+        backtrace.pc = pc;
+        let outer = backtrace.frames.len().saturating_sub(BACKTRACE_DEPTH);
+        backtrace.frames.drain(..outer);
+
+        let warning = LintWarning {
+            kind,
+            pc,
+            addr,
+            backtrace,
+            cycles: self.cycles,
+            count: 1,
+        };
+        if let Some(ref mut lint) = self.lint {
+            lint.seen.insert((kind, pc), lint.warnings.len());
+            lint.warnings.push(warning);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::asm;
+    use cpu::bus::Ram;
+
+    fn run(source: &str, sp: u8, steps: usize) -> (CPU, asm::Program) {
+        let program = asm::assemble(Variant::Ricoh2A03, source).unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = sp;
+        cpu.program_counter = program.symbol("start").unwrap();
+        cpu.set_lint(true);
+        for _ in 0..steps {
+            cpu.step(&mut mem).unwrap();
+        }
+        (cpu, program)
+    }
+
+    fn kinds(cpu: &CPU) -> Vec<(LintKind, u16, u16, usize)> {
+        cpu.lint_report()
+            .warnings
+            .iter()
+            .map(|w| (w.kind, w.pc, w.addr, w.count))
+            .collect()
+    }
+
+    #[test]
+    fn memory_and_stack() {
+        let (cpu, program) = run(
+            "
+                    .org $8000
+            start:  ldx #2
+            @loop:  lda $0300
+                    sta $0301
+                    lda $0B01
+                    sta $8000
+                    dex
+                    bne @loop
+                    jsr sub
+            sub:    rts
+            ",
+            0x01,
+            15,
+        );
+        let symbol = |name| program.symbol(name).unwrap();
+        let sub = symbol("sub");
+        assert_eq!(
+            kinds(&cpu),
+            vec![
+                (LintKind::UninitializedRead, 0x8002, 0x0300, 2),
+                (LintKind::RomWrite, 0x800B, 0x8000, 2),
+                (LintKind::StackOverflow, 0x8011, 0x0100, 1),
+                (LintKind::StackUnderflow, sub, 0x01FF, 1),
+            ]
+        );
+
+        let report = cpu.lint_report();
+        assert_eq!(report.warnings[2].backtrace.frames.len(), 0);
+        assert_eq!(
+            report.to_string().lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "4 warnings",
+                "$8002: read of RAM before it was written ($0300), 2 times, first at cycle 5",
+                "    #0  $8002",
+            ]
+        );
+    }
+
+    #[test]
+    fn execution_and_ppu() {
+        let (mut cpu, _) = run(
+            "
+                    .org $8000
+            start:  lda #$60
+                    sta $0200
+                    lda #$08
+                    sta $2001
+                    sta $2007
+                    jsr $0200
+                    jmp ($10FF)
+            ",
+            0xFD,
+            8,
+        );
+        assert_eq!(
+            kinds(&cpu),
+            vec![
+                (LintKind::PpuWriteWhileRendering, 0x800A, 0x2007, 1),
+                (LintKind::ExecuteRam, 0x0200, 0x0200, 1),
+                (LintKind::JmpIndirectPageBug, 0x8010, 0x10FF, 1),
+                (LintKind::UninitializedRead, 0x8010, 0x10FF, 2),
+            ]
+        );
+        assert_eq!(
+            cpu.lint_report().warnings[1].backtrace.frames[0].caller,
+            0x800D
+        );
+
+        cpu.set_lint(false);
+        assert!(cpu.lint_report().warnings.is_empty());
+    }
+}
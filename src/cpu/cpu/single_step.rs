@@ -0,0 +1,263 @@
+//! Conformance harness for the SingleStepTests single instruction vectors
+//! (https://github.com/SingleStepTests/65x02).
+//!
+//! Each JSON file holds an array of cases for one opcode. A case gives the
+//! registers and the RAM contents before and after one instruction and the
+//! bus activity of every cycle. The harness runs all cases of all files in
+//! a directory and reports the mismatches grouped by opcode and field.
+//!
+//! The vectors are not part of the repository. Run the harness with
+//!
+//! ```text
+//! SINGLE_STEP_DIR=path/to/65x02/nes6502/v1 cargo test single_step -- --ignored
+//! ```
+//!
+//! `SINGLE_STEP_DIR` defaults to `tests/single_step`. `SINGLE_STEP_VARIANT`
+//! selects the processor, `nmos` for the `6502` vectors, `65c02` for the
+//! `wdc65c02` vectors and `2a03` (the default) for the `nes6502` vectors.
+//! Setting `SINGLE_STEP_BUS` also compares the bus activity cycle by cycle.
+
+use super::*;
+use cpu::bus::Ram;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+/// Records the bus activity in the format of the vectors.
+struct RecordingBus {
+    ram: Ram,
+    cycles: Vec<(u16, u8, &'static str)>,
+}
+
+impl Bus for RecordingBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = self.ram.read(addr);
+        self.cycles.push((addr, val, "read"));
+        val
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.cycles.push((addr, val, "write"));
+        self.ram.write(addr, val);
+    }
+}
+
+/// First mismatch of a group and the number of mismatches in it.
+struct Mismatch {
+    count: usize,
+    example: String,
+}
+
+#[derive(Default)]
+struct Report {
+    cases: usize,
+    skipped: usize,
+    mismatches: BTreeMap<(u8, &'static str), Mismatch>,
+}
+
+impl Report {
+    fn check<T: PartialEq + Display>(
+        &mut self,
+        opcode: u8,
+        field: &'static str,
+        case: &str,
+        expected: T,
+        actual: T,
+    ) {
+        if expected == actual {
+            return;
+        }
+
+        let example = format!("{}: expected {}, got {}", case, expected, actual);
+        let mismatch = self.mismatches.entry((opcode, field)).or_insert(Mismatch {
+            count: 0,
+            example,
+        });
+        mismatch.count += 1;
+    }
+
+    fn print(&self, variant: Variant) {
+        let table = opinfo::op_info(variant);
+        println!(
+            "{} cases, {} skipped, {} mismatching fields",
+            self.cases,
+            self.skipped,
+            self.mismatches.len()
+        );
+        for (&(opcode, field), m) in self.mismatches.iter() {
+            println!(
+                "{:02X} {:<4} {:<6} {:>5}x  {}",
+                opcode, table[opcode as usize].mnemonic, field, m.count, m.example
+            );
+        }
+    }
+}
+
+fn number(v: &Value) -> u64 {
+    v.as_u64().expect("number expected")
+}
+
+fn load_state(cpu: &mut CPU, ram: &mut Ram, state: &Value) {
+    let mut registers = CpuState::default();
+    registers.set_pc(number(&state["pc"]) as u16);
+    registers.set_sp(number(&state["s"]) as u8);
+    registers.set_a(number(&state["a"]) as u8);
+    registers.set_x(number(&state["x"]) as u8);
+    registers.set_y(number(&state["y"]) as u8);
+    registers.set_p(number(&state["p"]) as u8);
+    cpu.set_state(&registers);
+
+    for cell in state["ram"].as_array().unwrap() {
+        ram.write(number(&cell[0]) as u16, number(&cell[1]) as u8);
+    }
+}
+
+/// Run one case and record its mismatches. The break and unused bits of
+/// the status register are no real flags and are not compared.
+fn run_case(case: &Value, variant: Variant, check_bus: bool, report: &mut Report) {
+    let name = case["name"].as_str().unwrap_or("?");
+    let initial = &case["initial"];
+    let expected = &case["final"];
+
+    let mut cpu = CPU::new();
+    let mut bus = RecordingBus {
+        ram: Ram::new(),
+        cycles: Vec::new(),
+    };
+    cpu.set_variant(variant);
+    cpu.set_timing(Timing::Cycle);
+    load_state(&mut cpu, &mut bus.ram, initial);
+
+    let opcode = bus.ram.read(cpu.program_counter);
+    let result = cpu.step(&mut bus);
+
+    report.cases += 1;
+    if result.is_err() || cpu.is_halted() {
+        // JAM and STP lock up the bus, the vectors record what happens
+        // afterwards
+        report.skipped += 1;
+        return;
+    }
+
+    let hex = |v: u64| format!("{:02X}", v);
+    report.check(opcode, "pc", name, hex(number(&expected["pc"])), hex(cpu.program_counter as u64));
+    report.check(opcode, "s", name, hex(number(&expected["s"])), hex(cpu.stack_pointer as u64));
+    report.check(opcode, "a", name, hex(number(&expected["a"])), hex(cpu.accumulator as u64));
+    report.check(opcode, "x", name, hex(number(&expected["x"])), hex(cpu.index_x as u64));
+    report.check(opcode, "y", name, hex(number(&expected["y"])), hex(cpu.index_y as u64));
+    report.check(
+        opcode,
+        "p",
+        name,
+        hex(number(&expected["p"]) & 0xCF),
+        hex(cpu.status_register.to_u8() as u64 & 0xCF),
+    );
+
+    for cell in expected["ram"].as_array().unwrap() {
+        let addr = number(&cell[0]) as u16;
+        let val = bus.ram.read(addr);
+        let e = format!("{:04X}={:02X}", addr, number(&cell[1]));
+        let a = format!("{:04X}={:02X}", addr, val);
+        report.check(opcode, "ram", name, e, a);
+    }
+
+    let cycles = case["cycles"].as_array().unwrap();
+    report.check(opcode, "cycles", name, cycles.len(), cpu.cycles);
+
+    if check_bus {
+        let expected: Vec<String> = cycles
+            .iter()
+            .map(|c| {
+                let kind = c[2].as_str().unwrap();
+                format!("{:04X} {:02X} {}", number(&c[0]), number(&c[1]), kind)
+            })
+            .collect();
+        let actual: Vec<String> = bus
+            .cycles
+            .iter()
+            .map(|&(addr, val, kind)| format!("{:04X} {:02X} {}", addr, val, kind))
+            .collect();
+        report.check(opcode, "bus", name, expected.join(", "), actual.join(", "));
+    }
+}
+
+fn variant_from_env() -> Variant {
+    match env::var("SINGLE_STEP_VARIANT").as_ref().map(|s| s.as_str()) {
+        Ok("nmos") => Variant::Nmos,
+        Ok("65c02") => Variant::Cmos65C02,
+        Ok("2a03") | Err(_) => Variant::Ricoh2A03,
+        Ok(other) => panic!("unknown variant {}", other),
+    }
+}
+
+#[test]
+#[ignore]
+fn single_step() {
+    let dir = PathBuf::from(env::var("SINGLE_STEP_DIR").unwrap_or(String::from("tests/single_step")));
+    let variant = variant_from_env();
+    let check_bus = env::var("SINGLE_STEP_BUS").is_ok();
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    files.sort();
+
+    let mut report = Report::default();
+    for file in files {
+        let cases: Value = serde_json::from_reader(File::open(&file).unwrap()).unwrap();
+        for case in cases.as_array().unwrap() {
+            run_case(case, variant, check_bus, &mut report);
+        }
+    }
+
+    report.print(variant);
+    assert!(report.cases > 0, "no vectors found in {}", dir.display());
+    assert!(report.mismatches.is_empty());
+}
+
+/// A case in the format of the vectors: LDA $10,X with the dummy read of
+/// the unindexed zero page address.
+const LDA_ZERO_PAGE_X: &str = r#"{
+    "name": "b5 10 00",
+    "initial": {
+        "pc": 512, "s": 253, "a": 0, "x": 2, "y": 0, "p": 36,
+        "ram": [[512, 181], [513, 16], [18, 128]]
+    },
+    "final": {
+        "pc": 514, "s": 253, "a": 128, "x": 2, "y": 0, "p": 164,
+        "ram": [[512, 181], [513, 16], [18, 128]]
+    },
+    "cycles": [[512, 181, "read"], [513, 16, "read"], [16, 0, "read"], [18, 128, "read"]]
+}"#;
+
+#[test]
+fn single_step_matching_case() {
+    let case: Value = serde_json::from_str(LDA_ZERO_PAGE_X).unwrap();
+    let mut report = Report::default();
+
+    run_case(&case, Variant::Ricoh2A03, true, &mut report);
+
+    assert_eq!(report.cases, 1);
+    assert!(report.mismatches.is_empty());
+}
+
+#[test]
+fn single_step_mismatches_are_grouped() {
+    let mut case: Value = serde_json::from_str(LDA_ZERO_PAGE_X).unwrap();
+    case["final"]["a"] = Value::from(0x7F);
+    case["final"]["ram"][2][1] = Value::from(0x7F);
+    case["cycles"].as_array_mut().unwrap().pop();
+
+    let mut report = Report::default();
+    run_case(&case, Variant::Ricoh2A03, true, &mut report);
+    run_case(&case, Variant::Ricoh2A03, true, &mut report);
+
+    let fields: Vec<&str> = report.mismatches.keys().map(|&(_, f)| f).collect();
+    assert_eq!(fields, vec!["a", "bus", "cycles", "ram"]);
+    assert_eq!(report.mismatches[&(0xB5, "a")].count, 2);
+}
@@ -0,0 +1,218 @@
+//! Snapshot of the registers of the CPU, for debuggers, test harnesses and
+//! save states.
+
+use super::*;
+
+/// The programmer visible registers and the cycle counter.
+///
+/// The status register is stored as the byte PHP would push, without the
+/// break bit, which only exists on the stack.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuState {
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: u8,
+    pc: u16,
+    p: u8,
+    cycles: usize,
+}
+
+/// The registers of `CPU::new`, zero except for the unused status bit.
+impl Default for CpuState {
+    fn default() -> CpuState {
+        CpuState {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0,
+            pc: 0,
+            p: 0x20,
+            cycles: 0,
+        }
+    }
+}
+
+/// A register which differs between two states, see `CpuState::diff`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.field {
+            "CYC" => write!(f, "{}: {} != {}", self.field, self.left, self.right),
+            "PC" => write!(f, "{}: {:04X} != {:04X}", self.field, self.left, self.right),
+            _ => write!(f, "{}: {:02X} != {:02X}", self.field, self.left, self.right),
+        }
+    }
+}
+
+impl CpuState {
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn set_a(&mut self, a: u8) {
+        self.a = a;
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn set_x(&mut self, x: u8) {
+        self.x = x;
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn set_y(&mut self, y: u8) {
+        self.y = y;
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, sp: u8) {
+        self.sp = sp;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn p(&self) -> u8 {
+        self.p
+    }
+
+    /// The break bit is dropped and the unused bit is always set.
+    pub fn set_p(&mut self, p: u8) {
+        self.p = (p & !0x10) | 0x20;
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn set_cycles(&mut self, cycles: usize) {
+        self.cycles = cycles;
+    }
+
+    /// The registers which differ from `other`, in the order of a nestest
+    /// log.
+    pub fn diff(&self, other: &CpuState) -> Vec<FieldDiff> {
+        let fields = [
+            ("PC", self.pc as usize, other.pc as usize),
+            ("A", self.a as usize, other.a as usize),
+            ("X", self.x as usize, other.x as usize),
+            ("Y", self.y as usize, other.y as usize),
+            ("P", self.p as usize, other.p as usize),
+            ("SP", self.sp as usize, other.sp as usize),
+            ("CYC", self.cycles, other.cycles),
+        ];
+
+        fields
+            .iter()
+            .filter(|f| f.1 != f.2)
+            .map(|&(field, left, right)| FieldDiff { field, left, right })
+            .collect()
+    }
+}
+
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.pc, self.a, self.x, self.y, self.p, self.sp, self.cycles
+        )
+    }
+}
+
+impl CPU {
+    /// Snapshot of the registers.
+    pub fn state(&self) -> CpuState {
+        let mut state = CpuState {
+            a: self.accumulator,
+            x: self.index_x,
+            y: self.index_y,
+            sp: self.stack_pointer,
+            pc: self.program_counter,
+            p: 0,
+            cycles: self.cycles,
+        };
+        state.set_p(self.status_register.to_u8());
+        state
+    }
+
+    /// Load the registers from `state`. The next instruction is fetched from
+    /// the program counter of the state, an interrupt detected before is
+    /// dropped.
+    pub fn set_state(&mut self, state: &CpuState) {
+        self.accumulator = state.a;
+        self.index_x = state.x;
+        self.index_y = state.y;
+        self.stack_pointer = state.sp;
+        self.program_counter = state.pc;
+        self.status_register.set_all(state.p);
+        self.cycles = state.cycles;
+        self.pending_interrupt = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trip() {
+        assert_eq!(CpuState::default(), CPU::new().state());
+        assert_eq!(CpuState::default().p(), 0x20);
+
+        let mut state = CpuState::default();
+        state.set_a(0x12);
+        state.set_x(0x34);
+        state.set_y(0x56);
+        state.set_sp(0xFD);
+        state.set_pc(0xC000);
+        state.set_p(0x14);
+        state.set_cycles(7);
+        assert_eq!(state.p(), 0x24);
+
+        let mut cpu = CPU::new();
+        cpu.set_state(&state);
+        assert_eq!(cpu.accumulator, 0x12);
+        assert_eq!(cpu.program_counter, 0xC000);
+        assert_eq!(cpu.status_register.to_u8(), 0x24);
+        assert_eq!(cpu.state(), state);
+        assert_eq!(
+            state.to_string(),
+            "PC:C000 A:12 X:34 Y:56 P:24 SP:FD CYC:7"
+        );
+    }
+
+    #[test]
+    fn state_diff() {
+        let mut cpu = CPU::new();
+        let before = cpu.state();
+        assert!(before.diff(&cpu.state()).is_empty());
+
+        cpu.index_x = 0x01;
+        cpu.program_counter = 0x8000;
+        cpu.cycles = 2;
+        let diff = before.diff(&cpu.state());
+        let text: Vec<String> = diff.iter().map(|d| d.to_string()).collect();
+        assert_eq!(text, vec!["PC: 0000 != 8000", "X: 00 != 01", "CYC: 0 != 2"]);
+    }
+}
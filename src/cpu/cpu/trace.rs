@@ -0,0 +1,241 @@
+//! Trace log in the format of the nestest reference log, as written by
+//! Nintendulator:
+//!
+//! ```text
+//! C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+//! ```
+//!
+//! Each line shows the state before the instruction is executed: the
+//! program counter, the instruction bytes, the disassembly with the memory
+//! contents the operand refers to, the registers, the PPU position and the
+//! CPU cycle count. Unofficial opcodes are marked with a `*`.
+
+use super::*;
+use cpu::disasm::{self, Instruction};
+
+/// Dots per scanline and scanlines per frame of the NTSC PPU, which runs
+/// three dots per CPU cycle.
+const DOTS_PER_SCANLINE: usize = 341;
+const SCANLINES_PER_FRAME: usize = 262;
+
+impl CPU {
+    /// Format the state before the next instruction as a line of a nestest
+    /// log. Memory is read with `Bus::peek`, values the bus cannot peek are
+    /// shown as `??`.
+    ///
+    /// The PPU position is derived from the cycle count, assuming the PPU
+    /// started at dot 0 of scanline 0 together with the CPU.
+    pub fn trace_line(&self, bus: &dyn Bus) -> String {
+        let pc = self.program_counter;
+        if bus.peek(pc).is_none() {
+            return format!("{:04X}  ??", pc);
+        }
+
+        let peeked: Vec<Option<u8>> = (0..3).map(|i| bus.peek(pc.wrapping_add(i))).collect();
+        let data: Vec<u8> = peeked.iter().map(|b| b.unwrap_or(0)).collect();
+        // three bytes always decode, show the opcode as data otherwise
+        let instruction = match disasm::decode(self.variant, &data, pc) {
+            Some(instruction) => instruction,
+            None => Instruction {
+                address: pc,
+                bytes: vec![data[0]],
+                mnemonic: ".byte",
+                mode: AddressingMode::Implicit,
+                operand: format!("${:02X}", data[0]),
+                target: None,
+                official: true,
+            },
+        };
+
+        let bytes: Vec<String> = peeked[..instruction.len()].iter().cloned().map(hex8).collect();
+
+        // Nintendulator calls ISC by its other name
+        let mnemonic = match instruction.mnemonic {
+            "ISC" => "ISB",
+            m => m,
+        };
+        let operand = match instruction.mnemonic {
+            ".byte" => instruction.operand.clone(),
+            _ => self.trace_operand(bus, &instruction),
+        };
+        let text = if operand.is_empty() {
+            mnemonic.to_string()
+        } else {
+            format!("{} {}", mnemonic, operand)
+        };
+
+        let (scanline, dot) = self.ppu_position();
+        format!(
+            "{:04X}  {:<8} {}{:<31} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            pc,
+            bytes.join(" "),
+            if instruction.official { ' ' } else { '*' },
+            text,
+            self.accumulator,
+            self.index_x,
+            self.index_y,
+            self.status_register.to_u8(),
+            self.stack_pointer,
+            scanline,
+            dot,
+            self.cycles
+        )
+    }
+
+    /// Scanline and dot of the PPU, derived from the cycle count like in
+    /// `trace_line`.
+    pub(super) fn ppu_position(&self) -> (usize, usize) {
+        let dot = self.cycles * 3;
+        (
+            dot / DOTS_PER_SCANLINE % SCANLINES_PER_FRAME,
+            dot % DOTS_PER_SCANLINE,
+        )
+    }
+
+    /// The operand of the disassembled instruction, annotated with the
+    /// effective address and the value stored there like nestest does.
+    fn trace_operand(&self, bus: &dyn Bus, instruction: &Instruction) -> String {
+        use cpu::cpu::AddressingMode::*;
+
+        let byte = |addr: u16| bus.peek(addr).unwrap_or(0);
+        let word = |lo: u16, hi: u16| (byte(hi) as u16) << 8 | byte(lo) as u16;
+        let value = |addr: u16| hex8(bus.peek(addr));
+
+        let operand = &instruction.operand;
+        let b1 = instruction.bytes.get(1).cloned().unwrap_or(0);
+        let w = (instruction.bytes.get(2).cloned().unwrap_or(0) as u16) << 8 | b1 as u16;
+        let x = self.index_x;
+        let y = self.index_y;
+
+        match instruction.mode {
+            ZeroPage => format!("{} = {}", operand, value(b1 as u16)),
+            ZeroPageX | ZeroPageY => {
+                let index = if instruction.mode == ZeroPageX { x } else { y };
+                let addr = b1.wrapping_add(index);
+                format!("{} @ {:02X} = {}", operand, addr, value(addr as u16))
+            }
+            Absolute if instruction.target.is_none() => format!("{} = {}", operand, value(w)),
+            AbsoluteX | AbsoluteY => {
+                let index = if instruction.mode == AbsoluteX { x } else { y };
+                let addr = w.wrapping_add(index as u16);
+                format!("{} @ {:04X} = {}", operand, addr, value(addr))
+            }
+            IndexedIndirect => {
+                let pointer = b1.wrapping_add(x);
+                let addr = word(pointer as u16, pointer.wrapping_add(1) as u16);
+                format!("{} @ {:02X} = {:04X} = {}", operand, pointer, addr, value(addr))
+            }
+            IndirectIndexed => {
+                let base = word(b1 as u16, b1.wrapping_add(1) as u16);
+                let addr = base.wrapping_add(y as u16);
+                format!("{} = {:04X} @ {:04X} = {}", operand, base, addr, value(addr))
+            }
+            Indirect => {
+                let hi = if self.variant == Variant::Cmos65C02 {
+                    w.wrapping_add(1)
+                } else {
+                    (w & 0xFF00) | (w.wrapping_add(1) & 0x00FF)
+                };
+                format!("{} = {:04X}", operand, word(w, hi))
+            }
+            ZeroPageIndirect => {
+                let addr = word(b1 as u16, b1.wrapping_add(1) as u16);
+                format!("{} = {:04X} = {}", operand, addr, value(addr))
+            }
+            _ => operand.clone(),
+        }
+    }
+}
+
+fn hex8(v: Option<u8>) -> String {
+    match v {
+        Some(v) => format!("{:02X}", v),
+        None => String::from("??"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::bus::Ram;
+
+    /// Start like the automated mode of nestest.
+    fn nestest_cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.program_counter = 0xC000;
+        cpu.stack_pointer = 0xFD;
+        cpu.status_register.set_all(0x24);
+        cpu.cycles = 7;
+        cpu
+    }
+
+    #[test]
+    fn trace_first_line_of_nestest() {
+        let mut mem = Ram::new();
+        mem.load(0xC000, &[0x4C, 0xF5, 0xC5]);
+
+        let cpu = nestest_cpu();
+
+        assert_eq!(
+            cpu.trace_line(&mem),
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+        );
+    }
+
+    #[test]
+    fn trace_annotates_operands() {
+        let mut mem = Ram::new();
+        let mut cpu = nestest_cpu();
+        cpu.index_y = 0x34;
+        cpu.cycles = 1000;
+        mem.load(0xC000, &[0xB1, 0x89]);
+        mem.load(0x0089, &[0x00, 0x03]);
+        mem.write(0x0334, 0x5A);
+
+        assert_eq!(
+            cpu.trace_line(&mem),
+            "C000  B1 89     LDA ($89),Y = 0300 @ 0334 = 5A  A:00 X:00 Y:34 P:24 SP:FD PPU:  8,272 CYC:1000"
+        );
+
+        mem.load(0xC000, &[0xE3, 0x45]);
+        cpu.index_x = 0x02;
+        mem.load(0x0047, &[0x47, 0x06]);
+        let line = cpu.trace_line(&mem);
+        assert!(line.starts_with("C000  E3 45    *ISB ($45,X) @ 47 = 0647 = 00    A:00"), "{}", line);
+    }
+
+    #[test]
+    fn trace_written_by_step() {
+        use std::io::{self, Write};
+        use std::sync::{Arc, Mutex};
+
+        /// Collects the trace in a buffer shared with the test.
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut mem = Ram::new();
+        mem.load(0xC000, &[0xE8, 0xE8]);
+        let mut cpu = nestest_cpu();
+
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        cpu.set_trace(Some(Box::new(Shared(buffer.clone()))));
+        cpu.step(&mut mem).unwrap();
+        cpu.step(&mut mem).unwrap();
+
+        let trace = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("C000  E8        INX"));
+        assert!(lines[1].starts_with("C001  E8        INX"));
+        assert!(lines[1].ends_with("A:00 X:01 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9"));
+    }
+}
@@ -0,0 +1,593 @@
+//! Breakpoints, watchpoints and the expression language of their conditions,
+//! and the frames of the shadow call stack.
+//!
+//! Conditions are C like expressions over the registers and memory:
+//!
+//! ```text
+//! A == $10 && [$0300] > 5
+//! X & $80
+//! P.C || CYC >= 100000
+//! ```
+//!
+//! The registers are `A`, `X`, `Y`, `S` (or `SP`), `P`, `PC` and the cycle
+//! counter `CYC`, the flags are `P.N`, `P.V`, `P.D`, `P.I`, `P.Z` and `P.C`.
+//! `[addr]` is the byte at `addr`, read with `Bus::peek` so it has no side
+//! effects. Numbers are decimal, `$hex` or `%binary`. The operators are
+//! those of C: `! ~ -`, `* / %`, `+ -`, `<< >>`, `< <= > >=`, `== !=`, `&`,
+//! `^`, `|`, `&&` and `||`. A condition is true if its value is not zero.
+
+use cpu::bus::Bus;
+use cpu::cpu::{CpuError, CpuState};
+use cpu::hook::Access;
+use std::fmt;
+
+/// Error in the source of an `Expr`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExprError {
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Register {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    PC,
+    Cycles,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Number(i64),
+    Register(Register),
+    /// a flag of the status register, given by its mask
+    Flag(u8),
+    Memory(Box<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+/// A parsed condition.
+#[derive(Clone, Debug)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+/// Binary operators from the lowest to the highest precedence.
+const PRECEDENCE: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Expr, ExprError> {
+        let mut parser = Parser {
+            s: source,
+            pos: 0,
+        };
+        let root = parser.binary(0)?;
+        parser.skip_space();
+        if parser.pos != source.len() {
+            return parser.error("unexpected input");
+        }
+
+        Ok(Expr {
+            source: source.trim().to_string(),
+            root,
+        })
+    }
+
+    /// Value of the expression for the registers in `state` and the memory
+    /// of `bus`. Memory the bus cannot peek reads as zero.
+    pub fn eval(&self, state: &CpuState, bus: &dyn Bus) -> i64 {
+        eval(&self.root, state, bus)
+    }
+
+    /// Whether the value is not zero.
+    pub fn is_true(&self, state: &CpuState, bus: &dyn Bus) -> bool {
+        self.eval(state, bus) != 0
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn eval(node: &Node, state: &CpuState, bus: &dyn Bus) -> i64 {
+    match *node {
+        Node::Number(v) => v,
+        Node::Register(r) => match r {
+            Register::A => state.a() as i64,
+            Register::X => state.x() as i64,
+            Register::Y => state.y() as i64,
+            Register::S => state.sp() as i64,
+            Register::P => state.p() as i64,
+            Register::PC => state.pc() as i64,
+            Register::Cycles => state.cycles() as i64,
+        },
+        Node::Flag(mask) => (state.p() & mask != 0) as i64,
+        Node::Memory(ref addr) => {
+            let addr = eval(addr, state, bus) as u16;
+            bus.peek(addr).unwrap_or(0) as i64
+        }
+        Node::Unary(op, ref v) => {
+            let v = eval(v, state, bus);
+            match op {
+                "!" => (v == 0) as i64,
+                "~" => !v,
+                _ => v.wrapping_neg(),
+            }
+        }
+        Node::Binary(op, ref l, ref r) => {
+            let l = eval(l, state, bus);
+            // && and || only evaluate the right side if needed
+            match op {
+                "&&" => return (l != 0 && eval(r, state, bus) != 0) as i64,
+                "||" => return (l != 0 || eval(r, state, bus) != 0) as i64,
+                _ => {}
+            }
+            let r = eval(r, state, bus);
+            match op {
+                "|" => l | r,
+                "^" => l ^ r,
+                "&" => l & r,
+                "==" => (l == r) as i64,
+                "!=" => (l != r) as i64,
+                "<=" => (l <= r) as i64,
+                ">=" => (l >= r) as i64,
+                "<" => (l < r) as i64,
+                ">" => (l > r) as i64,
+                "<<" => l.checked_shl(r as u32).unwrap_or(0),
+                ">>" => l.checked_shr(r as u32).unwrap_or(0),
+                "+" => l.wrapping_add(r),
+                "-" => l.wrapping_sub(r),
+                "*" => l.wrapping_mul(r),
+                "/" => l.checked_div(r).unwrap_or(0),
+                _ => l.checked_rem(r).unwrap_or(0),
+            }
+        }
+    }
+}
+
+/// Recursive descent parser building the tree of an `Expr`.
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, ExprError> {
+        Err(ExprError {
+            message: format!("{} at column {} of '{}'", message, self.pos + 1, self.s),
+        })
+    }
+
+    fn skip_space(&mut self) {
+        for c in self.s[self.pos..].chars() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        if self.s[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Node, ExprError> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+
+        let mut node = self.binary(level + 1)?;
+        'operators: loop {
+            for op in PRECEDENCE[level] {
+                // & and | must not match the start of && and ||, < and >
+                // not the start of << and >>
+                let rest = &self.s[self.pos..].trim_start();
+                let longer = PRECEDENCE
+                    .iter()
+                    .flat_map(|ops| ops.iter())
+                    .any(|o| o.len() > op.len() && o.starts_with(op) && rest.starts_with(o));
+                if !longer && self.eat(op) {
+                    let rhs = self.binary(level + 1)?;
+                    node = Node::Binary(op, Box::new(node), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+            return Ok(node);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        for op in &["!", "~", "-"] {
+            if self.eat(op) {
+                let v = self.unary()?;
+                return Ok(Node::Unary(op, Box::new(v)));
+            }
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Node, ExprError> {
+        if self.eat("(") {
+            let node = self.binary(0)?;
+            if !self.eat(")") {
+                return self.error("expected )");
+            }
+            return Ok(node);
+        }
+        if self.eat("[") {
+            let node = self.binary(0)?;
+            if !self.eat("]") {
+                return self.error("expected ]");
+            }
+            return Ok(Node::Memory(Box::new(node)));
+        }
+
+        let rest = &self.s[self.pos..];
+        let (radix, skip) = match rest.chars().next() {
+            Some('$') => (16, 1),
+            Some('%') => (2, 1),
+            Some(c) if c.is_ascii_digit() => (10, 0),
+            _ => (0, 0),
+        };
+        let len = rest[skip..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+            .unwrap_or(rest.len() - skip);
+        let word = &rest[skip..skip + len];
+
+        if radix != 0 {
+            return match i64::from_str_radix(word, radix) {
+                Ok(v) => {
+                    self.pos += skip + len;
+                    Ok(Node::Number(v))
+                }
+                Err(_) => self.error("invalid number"),
+            };
+        }
+
+        let node = match word.to_uppercase().as_str() {
+            "A" => Node::Register(Register::A),
+            "X" => Node::Register(Register::X),
+            "Y" => Node::Register(Register::Y),
+            "S" | "SP" => Node::Register(Register::S),
+            "P" => Node::Register(Register::P),
+            "PC" => Node::Register(Register::PC),
+            "CYC" => Node::Register(Register::Cycles),
+            "P.N" => Node::Flag(0x80),
+            "P.V" => Node::Flag(0x40),
+            "P.D" => Node::Flag(0x08),
+            "P.I" => Node::Flag(0x04),
+            "P.Z" => Node::Flag(0x02),
+            "P.C" => Node::Flag(0x01),
+            "" => return self.error("expected a value"),
+            _ => return self.error("unknown register"),
+        };
+        self.pos += len;
+        Ok(node)
+    }
+}
+
+/// What a `Breakpoint` watches.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BreakOn {
+    /// execution of an instruction starting in the range
+    Execute,
+    /// reads of the data in the range, not of opcodes or operands
+    Read,
+    Write,
+    /// reads and writes
+    Access,
+}
+
+/// Identifies a breakpoint registered with `CPU::add_breakpoint`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BreakpointId(pub(crate) usize);
+
+/// A breakpoint or watchpoint on the addresses from `start` to `end`
+/// inclusive, optionally with a condition.
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub on: BreakOn,
+    pub start: u16,
+    pub end: u16,
+    pub condition: Option<Expr>,
+    pub enabled: bool,
+    /// number of times the breakpoint was reached with its condition true
+    pub hits: usize,
+    /// number of hits to pass before execution stops
+    pub ignore: usize,
+}
+
+impl Breakpoint {
+    /// Stop before the instruction at `addr` is executed.
+    pub fn execute(addr: u16) -> Breakpoint {
+        Breakpoint::watch(BreakOn::Execute, addr, addr)
+    }
+
+    /// Stop after the instruction which accessed an address from `start` to
+    /// `end`.
+    pub fn watch(on: BreakOn, start: u16, end: u16) -> Breakpoint {
+        Breakpoint {
+            on,
+            start,
+            end,
+            condition: None,
+            enabled: true,
+            hits: 0,
+            ignore: 0,
+        }
+    }
+
+    /// Only stop if `condition` is true.
+    pub fn with_condition(mut self, condition: &str) -> Result<Breakpoint, ExprError> {
+        self.condition = Some(Expr::parse(condition)?);
+        Ok(self)
+    }
+
+    /// Whether the breakpoint applies to an event `on` at `addr`.
+    pub(crate) fn matches(&self, on: BreakOn, addr: u16) -> bool {
+        let kind = match (self.on, on) {
+            (BreakOn::Access, BreakOn::Read) | (BreakOn::Access, BreakOn::Write) => true,
+            (a, b) => a == b,
+        };
+        self.enabled && kind && self.start <= addr && addr <= self.end
+    }
+}
+
+/// Why `CPU::step` did not complete normally.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// a breakpoint is set on the next instruction, which was not executed.
+    /// The next step executes it.
+    Breakpoint { id: BreakpointId, pc: u16 },
+    /// the instruction accessed a watched address, it completed
+    Watchpoint {
+        id: BreakpointId,
+        addr: u16,
+        value: u8,
+        access: Access,
+    },
+    /// the instruction failed and the CPU is halted
+    Error(CpuError),
+}
+
+impl From<CpuError> for Stop {
+    fn from(error: CpuError) -> Stop {
+        Stop::Error(error)
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stop::Breakpoint { id, pc } => write!(f, "breakpoint {} at ${:04X}", id.0, pc),
+            Stop::Watchpoint {
+                id,
+                addr,
+                value,
+                access,
+            } => write!(
+                f,
+                "watchpoint {}: {:?} of ${:02X} at ${:04X}",
+                id.0, access, value, addr
+            ),
+            Stop::Error(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// How a frame of the shadow call stack was entered.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    Subroutine,
+    Brk,
+    Nmi,
+    Irq,
+}
+
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            FrameKind::Subroutine => "JSR",
+            FrameKind::Brk => "BRK",
+            FrameKind::Nmi => "NMI",
+            FrameKind::Irq => "IRQ",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A subroutine call or interrupt on the shadow call stack, see
+/// `CPU::set_call_stack`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// address of the JSR or BRK, or of the instruction preempted by an
+    /// interrupt
+    pub caller: u16,
+    /// address of the subroutine or interrupt handler
+    pub entry: u16,
+    /// where execution continues after the matching return
+    pub return_address: u16,
+    /// stack pointer before the return address was pushed, the matching
+    /// return restores it
+    pub sp: u8,
+}
+
+/// An instruction leaving a frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Return {
+    Rts,
+    Rti,
+}
+
+/// Use of the stack the shadow call stack does not follow, see
+/// `CPU::take_stack_anomalies`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StackAnomaly {
+    /// a return without a frame at its stack level, typically an RTS used as
+    /// a jump to an address pushed by the program
+    Unmatched { pc: u16, ret: Return, target: u16 },
+    /// a return continued somewhere else than the frame it left, or left a
+    /// frame of the other kind, e.g. RTS from an interrupt handler
+    Mismatch {
+        pc: u16,
+        ret: Return,
+        target: u16,
+        frame: Frame,
+    },
+    /// frames were dropped without returning from them, e.g. after the
+    /// program pulled a return address or reset the stack pointer
+    Abandoned { pc: u16, frames: usize },
+}
+
+impl fmt::Display for StackAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StackAnomaly::Unmatched { pc, ret, target } => write!(
+                f,
+                "${:04X}: {:?} to ${:04X} without a matching call",
+                pc, ret, target
+            ),
+            StackAnomaly::Mismatch {
+                pc,
+                ret,
+                target,
+                frame,
+            } => write!(
+                f,
+                "${:04X}: {:?} to ${:04X} leaves the {} from ${:04X} returning to ${:04X}",
+                pc, ret, target, frame.kind, frame.caller, frame.return_address
+            ),
+            StackAnomaly::Abandoned { pc, frames } => {
+                write!(f, "${:04X}: {} frames abandoned", pc, frames)
+            }
+        }
+    }
+}
+
+/// The shadow call stack together with the program counter, printed like
+/// the backtrace of a debugger with the innermost frame first:
+///
+/// ```text
+/// #0  $C460 in $C456 (JSR from $C012)
+/// #1  $C012 in $C000 (NMI from $8003)
+/// #2  $8003
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Backtrace {
+    pub pc: u16,
+    /// the outermost frame first
+    pub frames: Vec<Frame>,
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut pc = self.pc;
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            writeln!(
+                f,
+                "#{:<2} ${:04X} in ${:04X} ({} from ${:04X})",
+                depth, pc, frame.entry, frame.kind, frame.caller
+            )?;
+            pc = frame.caller;
+        }
+        write!(f, "#{:<2} ${:04X}", self.frames.len(), pc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::bus::Ram;
+
+    fn eval(source: &str) -> i64 {
+        let mut state = CpuState::default();
+        state.set_a(0x10);
+        state.set_x(0x81);
+        state.set_p(0x01);
+        state.set_pc(0xC000);
+        let mut ram = Ram::new();
+        ram.load(0x0300, &[7]);
+
+        Expr::parse(source).unwrap().eval(&state, &ram)
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(eval("A == $10 && [$0300] > 5"), 1);
+        assert_eq!(eval("X & $80"), 0x80);
+        assert_eq!(eval("P.C"), 1);
+        assert_eq!(eval("p.z"), 0);
+        assert_eq!(eval("P"), 0x21);
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("1 << 4 | 1"), 17);
+        assert_eq!(eval("%101 ^ 1"), 4);
+        assert_eq!(eval("-1 < 0 || 1 / 0"), 1);
+        assert_eq!(eval("!A"), 0);
+        assert_eq!(eval("~0 & $FF"), 0xFF);
+        assert_eq!(eval("PC >> 8 != $C0"), 0);
+        assert_eq!(eval("[$02FF + 1] % 4"), 3);
+        assert_eq!(eval("X >= 129 && X <= 129"), 1);
+    }
+
+    #[test]
+    fn expression_errors() {
+        assert!(Expr::parse("A ==").is_err());
+        assert!(Expr::parse("Q").is_err());
+        assert!(Expr::parse("[1").is_err());
+        assert!(Expr::parse("$G").is_err());
+        assert_eq!(
+            Expr::parse("A 1").unwrap_err().message,
+            "unexpected input at column 3 of 'A 1'"
+        );
+
+        // non-ASCII whitespace is skipped, other characters are rejected
+        assert!(Expr::parse("A\u{a0}==\u{3000}1").is_ok());
+        assert!(Expr::parse("A ==\u{a0}").is_err());
+        assert!(Expr::parse("A == \u{e9}").is_err());
+    }
+
+    #[test]
+    fn breakpoint_matches() {
+        let bp = Breakpoint::watch(BreakOn::Access, 0x2000, 0x2007);
+        assert!(bp.matches(BreakOn::Read, 0x2007));
+        assert!(bp.matches(BreakOn::Write, 0x2000));
+        assert!(!bp.matches(BreakOn::Execute, 0x2000));
+        assert!(!bp.matches(BreakOn::Read, 0x2008));
+
+        let mut bp = Breakpoint::execute(0xC000);
+        assert!(bp.matches(BreakOn::Execute, 0xC000));
+        bp.enabled = false;
+        assert!(!bp.matches(BreakOn::Execute, 0xC000));
+    }
+}
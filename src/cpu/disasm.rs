@@ -0,0 +1,193 @@
+//! Disassembler for all variants of the CPU.
+//!
+//! Operands are written in the usual assembler syntax, e.g. `LDA ($12),Y`
+//! or `JMP ($1234)`. Branches show their resolved target instead of the
+//! displacement.
+
+use cpu::cpu::{AddressingMode, Variant};
+use cpu::opinfo;
+use std::fmt;
+
+/// A single decoded instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    /// address of the opcode
+    pub address: u16,
+    /// the opcode followed by its operand bytes
+    pub bytes: Vec<u8>,
+    /// `.byte` for data at the end of the input which is too short for the
+    /// instruction its first byte encodes
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub operand: String,
+    /// destination of branches, JMP and JSR where it is known without
+    /// executing the code
+    pub target: Option<u16>,
+    /// `false` for the undocumented opcodes of the NMOS 6502
+    pub official: bool,
+}
+
+impl Instruction {
+    /// Number of bytes of the instruction.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.operand.is_empty() {
+            write!(f, "{}", self.mnemonic)
+        } else {
+            write!(f, "{} {}", self.mnemonic, self.operand)
+        }
+    }
+}
+
+/// Decode the instruction at the start of `data`, which is located at
+/// `address`. Returns `None` if `data` is empty.
+pub fn decode(variant: Variant, data: &[u8], address: u16) -> Option<Instruction> {
+    use cpu::cpu::AddressingMode::*;
+
+    let opcode = *data.first()?;
+    let opi = &opinfo::op_info(variant)[opcode as usize];
+
+    if data.len() < opi.bytes {
+        let operand: Vec<String> = data.iter().map(|b| format!("${:02X}", b)).collect();
+        return Some(Instruction {
+            address,
+            bytes: data.to_vec(),
+            mnemonic: ".byte",
+            mode: Implicit,
+            operand: operand.join(","),
+            target: None,
+            official: true,
+        });
+    }
+
+    let b1 = data.get(1).cloned().unwrap_or(0);
+    let w = (data.get(2).cloned().unwrap_or(0) as u16) << 8 | b1 as u16;
+    let next = address.wrapping_add(opi.bytes as u16);
+
+    let mut target = None;
+    let operand = match opi.mode {
+        Implicit => String::new(),
+        Accumulator => String::from("A"),
+        Immediate => format!("#${:02X}", b1),
+        ZeroPage => format!("${:02X}", b1),
+        ZeroPageX => format!("${:02X},X", b1),
+        ZeroPageY => format!("${:02X},Y", b1),
+        Relative => {
+            let dest = next.wrapping_add(b1 as i8 as u16);
+            target = Some(dest);
+            format!("${:04X}", dest)
+        }
+        Absolute => {
+            if opi.mnemonic == "JMP" || opi.mnemonic == "JSR" {
+                target = Some(w);
+            }
+            format!("${:04X}", w)
+        }
+        AbsoluteX => format!("${:04X},X", w),
+        AbsoluteY => format!("${:04X},Y", w),
+        IndexedIndirect => format!("(${:02X},X)", b1),
+        IndirectIndexed => format!("(${:02X}),Y", b1),
+        Indirect => format!("(${:04X})", w),
+        ZeroPageIndirect => format!("(${:02X})", b1),
+        AbsoluteIndexedIndirect => format!("(${:04X},X)", w),
+        ZeroPageRelative => {
+            let dest = next.wrapping_add(data[2] as i8 as u16);
+            target = Some(dest);
+            format!("${:02X},${:04X}", b1, dest)
+        }
+    };
+
+    Some(Instruction {
+        address,
+        bytes: data[..opi.bytes].to_vec(),
+        mnemonic: opi.mnemonic,
+        mode: opi.mode,
+        operand,
+        target,
+        official: opi.official,
+    })
+}
+
+/// Disassemble `data` loaded at `base` linearly from the first byte to the
+/// end. Data between the code is decoded as if it were instructions.
+pub fn disassemble(variant: Variant, data: &[u8], base: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while let Some(instruction) = decode(variant, &data[offset..], base.wrapping_add(offset as u16)) {
+        offset += instruction.len();
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(variant: Variant, data: &[u8]) -> String {
+        decode(variant, data, 0x8000).unwrap().to_string()
+    }
+
+    #[test]
+    fn operand_syntax() {
+        let v = Variant::Ricoh2A03;
+        assert_eq!(text(v, &[0xEA]), "NOP");
+        assert_eq!(text(v, &[0x0A]), "ASL A");
+        assert_eq!(text(v, &[0xA9, 0x12]), "LDA #$12");
+        assert_eq!(text(v, &[0xA5, 0x12]), "LDA $12");
+        assert_eq!(text(v, &[0xB5, 0x12]), "LDA $12,X");
+        assert_eq!(text(v, &[0xB6, 0x12]), "LDX $12,Y");
+        assert_eq!(text(v, &[0xAD, 0x34, 0x12]), "LDA $1234");
+        assert_eq!(text(v, &[0xBD, 0x34, 0x12]), "LDA $1234,X");
+        assert_eq!(text(v, &[0xB9, 0x34, 0x12]), "LDA $1234,Y");
+        assert_eq!(text(v, &[0xA1, 0x12]), "LDA ($12,X)");
+        assert_eq!(text(v, &[0xB1, 0x12]), "LDA ($12),Y");
+        assert_eq!(text(v, &[0x6C, 0x34, 0x12]), "JMP ($1234)");
+        assert_eq!(text(v, &[0xD0, 0xFE]), "BNE $8000");
+
+        let v = Variant::Cmos65C02;
+        assert_eq!(text(v, &[0xB2, 0x12]), "LDA ($12)");
+        assert_eq!(text(v, &[0x7C, 0x34, 0x12]), "JMP ($1234,X)");
+        assert_eq!(text(v, &[0x0F, 0x12, 0x10]), "BBR0 $12,$8013");
+    }
+
+    #[test]
+    fn targets() {
+        let v = Variant::Ricoh2A03;
+        let branch = decode(v, &[0x10, 0x80], 0x8000).unwrap();
+        assert_eq!(branch.target, Some(0x7F82));
+        assert_eq!(decode(v, &[0x20, 0x00, 0xC0], 0x8000).unwrap().target, Some(0xC000));
+        assert_eq!(decode(v, &[0x6C, 0x00, 0xC0], 0x8000).unwrap().target, None);
+        assert_eq!(decode(v, &[0xAD, 0x00, 0xC0], 0x8000).unwrap().target, None);
+    }
+
+    #[test]
+    fn disassemble_linear() {
+        let code = [0xA2, 0x00, 0xE8, 0xD0, 0xFD, 0x4C];
+        let listing = disassemble(Variant::Ricoh2A03, &code, 0xC000);
+
+        let lines: Vec<(u16, String)> = listing.iter().map(|i| (i.address, i.to_string())).collect();
+        assert_eq!(
+            lines,
+            vec![
+                (0xC000, String::from("LDX #$00")),
+                (0xC002, String::from("INX")),
+                (0xC003, String::from("BNE $C002")),
+                (0xC005, String::from(".byte $4C")),
+            ]
+        );
+        assert_eq!(listing[2].len(), 2);
+        assert!(!listing[1].is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! Callbacks to observe the CPU from outside of the core, e.g. for trace
+//! loggers, code/data loggers, profilers or cheat tools.
+//!
+//! A `Hook` is registered with `CPU::add_hook` and is called before and
+//! after every instruction and for every access the CPU makes to the bus.
+//! Without any registered hook the CPU skips all of this.
+
+use cpu::cpu::CpuState;
+use cpu::debug::Frame;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Kind of a bus access reported to `Hook::access`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// fetch of an opcode
+    Execute,
+    /// fetch of one of the operand bytes following the opcode
+    Operand,
+    Read,
+    Write,
+    /// a read whose value the processor discards, only made in
+    /// `Timing::Cycle`
+    DummyRead,
+    /// the write of the unmodified value by a read-modify-write instruction,
+    /// only made in `Timing::Cycle`
+    DummyWrite,
+}
+
+/// An instruction as reported to `Hook::before_instruction` and
+/// `Hook::after_instruction`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InstructionEvent {
+    /// address of the opcode
+    pub pc: u16,
+    pub opcode: u8,
+    /// length of the instruction in bytes, including the opcode
+    pub len: usize,
+    /// the `len - 1` operand bytes, read with `Bus::peek` before the
+    /// instruction is executed. Zero where the bus cannot peek.
+    pub operand: [u8; 2],
+    /// the registers before, respectively after the instruction
+    pub state: CpuState,
+    /// cycles spent by the instruction, 0 before it is executed
+    pub cycles: usize,
+}
+
+/// Receives the events of a CPU. All methods do nothing by default.
+pub trait Hook {
+    /// Called after the opcode is fetched, before the instruction executes.
+    fn before_instruction(&mut self, _event: &InstructionEvent) {}
+
+    /// Called once the instruction completed.
+    fn after_instruction(&mut self, _event: &InstructionEvent) {}
+
+    /// Called for every access to the bus with the value read or written.
+    fn access(&mut self, _addr: u16, _value: u8, _kind: Access) {}
+
+    /// Called when JSR, BRK or an interrupt pushed a frame on the shadow
+    /// call stack, only while it is turned on, see `CPU::set_call_stack`.
+    fn enter_frame(&mut self, _frame: &Frame) {}
+
+    /// Called when a frame is popped off the shadow call stack, by a return
+    /// or because it was abandoned. Frames are popped innermost first.
+    fn leave_frame(&mut self, _frame: &Frame) {}
+}
+
+/// Lets the caller keep a handle to a registered hook to look at what it
+/// collected.
+impl<H: Hook> Hook for Rc<RefCell<H>> {
+    fn before_instruction(&mut self, event: &InstructionEvent) {
+        self.borrow_mut().before_instruction(event);
+    }
+
+    fn after_instruction(&mut self, event: &InstructionEvent) {
+        self.borrow_mut().after_instruction(event);
+    }
+
+    fn access(&mut self, addr: u16, value: u8, kind: Access) {
+        self.borrow_mut().access(addr, value, kind);
+    }
+
+    fn enter_frame(&mut self, frame: &Frame) {
+        self.borrow_mut().enter_frame(frame);
+    }
+
+    fn leave_frame(&mut self, frame: &Frame) {
+        self.borrow_mut().leave_frame(frame);
+    }
+}
+
+/// Identifies a registered hook, see `CPU::remove_hook`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(pub(crate) usize);
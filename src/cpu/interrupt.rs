@@ -0,0 +1,135 @@
+//! Interrupt inputs of the 6502.
+//!
+//! The processor has two interrupt inputs. The NMI line is edge triggered, an
+//! interrupt is requested once per transition from inactive to active. The
+//! IRQ line is level triggered and shared by several sources on the NES, it
+//! stays asserted as long as any source holds it.
+
+/// Address of the NMI vector.
+pub const NMI_VECTOR: u16 = 0xFFFA;
+
+/// Address of the reset vector.
+pub const RESET_VECTOR: u16 = 0xFFFC;
+
+/// Address of the IRQ vector, also used by BRK.
+pub const IRQ_VECTOR: u16 = 0xFFFE;
+
+/// Kind of a hardware interrupt serviced by the CPU.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
+impl Interrupt {
+    pub fn vector(self) -> u16 {
+        match self {
+            Interrupt::Nmi => NMI_VECTOR,
+            Interrupt::Irq => IRQ_VECTOR,
+        }
+    }
+}
+
+/// Devices which can drive the shared IRQ line. Each source holds the line
+/// independently, the line is released when all sources are released.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IrqSource {
+    /// APU frame counter
+    FrameCounter = 0x01,
+    /// APU delta modulation channel
+    Dmc = 0x02,
+    /// cartridge mapper, e.g. the MMC3 scanline counter
+    Mapper = 0x04,
+    /// anything else, e.g. tests or the expansion port
+    External = 0x08,
+}
+
+/// State of the interrupt inputs as seen by the CPU.
+#[derive(Clone, Debug, Default)]
+pub struct InterruptLines {
+    /// current level of the NMI input
+    nmi: bool,
+    /// an active edge was detected on the NMI input and not yet serviced
+    nmi_pending: bool,
+    /// bit set of the `IrqSource`s holding the IRQ line
+    irq: u8,
+}
+
+impl InterruptLines {
+    pub fn new() -> InterruptLines {
+        InterruptLines::default()
+    }
+
+    /// Drive the NMI input. Only the transition from inactive to active
+    /// requests an interrupt.
+    pub fn set_nmi(&mut self, active: bool) {
+        if active && !self.nmi {
+            self.nmi_pending = true;
+        }
+        self.nmi = active;
+    }
+
+    /// Assert or release the IRQ line on behalf of `source`.
+    pub fn set_irq(&mut self, source: IrqSource, active: bool) {
+        if active {
+            self.irq |= source as u8;
+        } else {
+            self.irq &= !(source as u8);
+        }
+    }
+
+    /// True if any source holds the IRQ line.
+    pub fn irq(&self) -> bool {
+        self.irq != 0
+    }
+
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    /// Acknowledge the pending NMI, returns whether there was one.
+    pub fn take_nmi(&mut self) -> bool {
+        let pending = self.nmi_pending;
+        self.nmi_pending = false;
+        pending
+    }
+
+    /// Forget a detected NMI edge, as a reset does.
+    pub fn clear_nmi(&mut self) {
+        self.nmi_pending = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nmi_is_edge_triggered() {
+        let mut lines = InterruptLines::new();
+
+        lines.set_nmi(true);
+        assert!(lines.take_nmi());
+
+        // holding the line does not request another interrupt
+        lines.set_nmi(true);
+        assert!(!lines.take_nmi());
+
+        lines.set_nmi(false);
+        lines.set_nmi(true);
+        assert!(lines.take_nmi());
+    }
+
+    #[test]
+    fn irq_is_shared_by_sources() {
+        let mut lines = InterruptLines::new();
+
+        lines.set_irq(IrqSource::FrameCounter, true);
+        lines.set_irq(IrqSource::Mapper, true);
+        lines.set_irq(IrqSource::FrameCounter, false);
+        assert!(lines.irq());
+
+        lines.set_irq(IrqSource::Mapper, false);
+        assert!(!lines.irq());
+    }
+}
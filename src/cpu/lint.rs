@@ -0,0 +1,98 @@
+//! Warnings about programs doing things the hardware silently tolerates,
+//! for homebrew developers. Turned on with `CPU::set_lint`.
+
+use cpu::debug::Backtrace;
+use std::fmt;
+
+/// Something suspicious a program did.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LintKind {
+    /// a read of internal RAM which was never written, its power up value
+    /// is random on the hardware
+    UninitializedRead,
+    /// a write to the cartridge ROM at $8000-$FFFF. Allow it for mappers
+    /// with registers there.
+    RomWrite,
+    /// a push wrapped the stack pointer from $0100 to $01FF
+    StackOverflow,
+    /// a pull wrapped the stack pointer from $01FF to $0100
+    StackUnderflow,
+    /// execution continued in RAM, the address is the first instruction
+    ExecuteRam,
+    /// execution continued in the I/O registers or expansion area
+    ExecuteIo,
+    /// JMP ($xxFF) takes the high byte of the target from $xx00
+    JmpIndirectPageBug,
+    /// a write to OAMADDR, OAMDATA or PPUDATA while the PPU renders, which
+    /// corrupts sprite memory or VRAM. Until the PPU is emulated its
+    /// position is derived from the cycle counter, assuming it started at
+    /// dot 0 of scanline 0 together with the CPU. On the hardware the
+    /// alignment differs, so writes near the start or end of vblank may be
+    /// missed or reported wrongly.
+    PpuWriteWhileRendering,
+}
+
+impl LintKind {
+    pub fn description(self) -> &'static str {
+        match self {
+            LintKind::UninitializedRead => "read of RAM before it was written",
+            LintKind::RomWrite => "write to ROM",
+            LintKind::StackOverflow => "stack overflow, push wrapped to $01FF",
+            LintKind::StackUnderflow => "stack underflow, pull wrapped to $0100",
+            LintKind::ExecuteRam => "execution in RAM",
+            LintKind::ExecuteIo => "execution in I/O space",
+            LintKind::JmpIndirectPageBug => "JMP indirect through a pointer crossing a page",
+            LintKind::PpuWriteWhileRendering => "PPU register written while rendering",
+        }
+    }
+}
+
+/// A warning, reported once per kind and instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintWarning {
+    pub kind: LintKind,
+    /// address of the instruction
+    pub pc: u16,
+    /// the address accessed, executed or jumped through
+    pub addr: u16,
+    /// the innermost frames of the shadow call stack at the first occurrence
+    pub backtrace: Backtrace,
+    /// cycle counter at the first occurrence
+    pub cycles: usize,
+    /// number of occurrences
+    pub count: usize,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "${:04X}: {} (${:04X}), {} times, first at cycle {}",
+            self.pc,
+            self.kind.description(),
+            self.addr,
+            self.count,
+            self.cycles
+        )?;
+        for line in self.backtrace.to_string().lines() {
+            write!(f, "\n    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// All warnings in the order they first occurred, see `CPU::lint_report`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LintReport {
+    pub warnings: Vec<LintWarning>,
+}
+
+impl fmt::Display for LintReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} warnings", self.warnings.len())?;
+        for warning in &self.warnings {
+            write!(f, "\n{}", warning)?;
+        }
+        Ok(())
+    }
+}
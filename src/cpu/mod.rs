@@ -1,4 +1,12 @@
+#[allow(clippy::module_inception)]
 pub mod cpu;
+pub mod bus;
 mod status_register;
 mod utils;
-mod opinfo;
+pub mod interrupt;
+pub mod opinfo;
+pub mod disasm;
+pub mod asm;
+pub mod hook;
+pub mod debug;
+pub mod lint;
@@ -1,330 +1,856 @@
-use cpu::cpu::{AddressingMode, OpResponse};
+use cpu::cpu::AddressingMode;
 use cpu::cpu::AddressingMode::*;
+use cpu::cpu::Variant;
+use self::Penalty::*;
+
+/// Conditional extra cycles an instruction may take on top of its base
+/// cycles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Penalty {
+    /// The instruction always takes its base cycles.
+    Never,
+    /// One extra cycle if the indexed address crosses a page boundary. Only
+    /// instructions which read their operand take this penalty, stores and
+    /// read-modify-write instructions always spend the extra cycle.
+    PageCrossed,
+    /// One extra cycle if the branch is taken and another one if the target
+    /// is on a different page.
+    Branch,
+}
 
 /// Information required to correctly execute an instruction. The cycles
 /// _shall _ be  stored without conditional cycles such as extra cycles caused
 /// by page crossings.
 #[derive(Debug)]
 pub struct OpInfo {
+    pub mnemonic: &'static str,
     pub mode: AddressingMode,
-    pub cycles: usize,
     pub bytes: usize,
+    pub cycles: usize,
+    pub penalty: Penalty,
+    /// `false` for the undocumented opcodes of the NMOS 6502
+    pub official: bool,
 }
 
-//impl OpInfo {
-    //pub fn new(mode: AddressingMode, bytes: usize, cycles: usize) -> OpInfo {
-        //OpInfo {
-            //mode,
-            //bytes,
-            //cycles,
-        //}
-    //}
-//}
-
-/// currently not used
-//pub static JUMP_TABLE: [fn(&mut CPU, &mut Memory, &OpInfo) -> OpResponse; 1] = [
-    //CPU::bcc,
-//];
+const fn official(
+    mnemonic: &'static str,
+    mode: AddressingMode,
+    bytes: usize,
+    cycles: usize,
+    penalty: Penalty,
+) -> OpInfo {
+    OpInfo {
+        mnemonic,
+        mode,
+        bytes,
+        cycles,
+        penalty,
+        official: true,
+    }
+}
 
+const fn unofficial(
+    mnemonic: &'static str,
+    mode: AddressingMode,
+    bytes: usize,
+    cycles: usize,
+    penalty: Penalty,
+) -> OpInfo {
+    OpInfo {
+        mnemonic,
+        mode,
+        bytes,
+        cycles,
+        penalty,
+        official: false,
+    }
+}
 
+/// Metadata of all 256 opcodes, indexed by the opcode.
+///
 /// bytes in OpInfo do not contain conditional cycles like they occur on page
 /// crossings
-///
-/// Currently not in use in favour for direct encoding in cpu struct.
-#[cfg_attr(rustfmt, rustfmt_skip)]
+#[rustfmt::skip]
 pub static OP_INFO: [OpInfo; 256] = [
     // 0x00
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BRK", Implicit, 1, 7, Never),
+    official("ORA", IndexedIndirect, 2, 6, Never),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("SLO", IndexedIndirect, 2, 8, Never),
+
+    unofficial("NOP", ZeroPage, 2, 3, Never),
+    official("ORA", ZeroPage, 2, 3, Never),
+    official("ASL", ZeroPage, 2, 5, Never),
+    unofficial("SLO", ZeroPage, 2, 5, Never),
+
+    // 0x08
+    official("PHP", Implicit, 1, 3, Never),
+    official("ORA", Immediate, 2, 2, Never),
+    official("ASL", Accumulator, 1, 2, Never),
+    unofficial("ANC", Immediate, 2, 2, Never),
+
+    unofficial("NOP", Absolute, 3, 4, Never),
+    official("ORA", Absolute, 3, 4, Never),
+    official("ASL", Absolute, 3, 6, Never),
+    unofficial("SLO", Absolute, 3, 6, Never),
+
+    // 0x10
+    official("BPL", Relative, 2, 2, Branch),
+    official("ORA", IndirectIndexed, 2, 5, PageCrossed),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("SLO", IndirectIndexed, 2, 8, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("ORA", ZeroPageX, 2, 4, Never),
+    official("ASL", ZeroPageX, 2, 6, Never),
+    unofficial("SLO", ZeroPageX, 2, 6, Never),
+
+    // 0x18
+    official("CLC", Implicit, 1, 2, Never),
+    official("ORA", AbsoluteY, 3, 4, PageCrossed),
+    unofficial("NOP", Implicit, 1, 2, Never),
+    unofficial("SLO", AbsoluteY, 3, 7, Never),
+
+    unofficial("NOP", AbsoluteX, 3, 4, PageCrossed),
+    official("ORA", AbsoluteX, 3, 4, PageCrossed),
+    official("ASL", AbsoluteX, 3, 7, Never),
+    unofficial("SLO", AbsoluteX, 3, 7, Never),
+
+    // 0x20
+    official("JSR", Absolute, 3, 6, Never),
+    official("AND", IndexedIndirect, 2, 6, Never),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("RLA", IndexedIndirect, 2, 8, Never),
+
+    official("BIT", ZeroPage, 2, 3, Never),
+    official("AND", ZeroPage, 2, 3, Never),
+    official("ROL", ZeroPage, 2, 5, Never),
+    unofficial("RLA", ZeroPage, 2, 5, Never),
+
+    // 0x28
+    official("PLP", Implicit, 1, 4, Never),
+    official("AND", Immediate, 2, 2, Never),
+    official("ROL", Accumulator, 1, 2, Never),
+    unofficial("ANC", Immediate, 2, 2, Never),
+
+    official("BIT", Absolute, 3, 4, Never),
+    official("AND", Absolute, 3, 4, Never),
+    official("ROL", Absolute, 3, 6, Never),
+    unofficial("RLA", Absolute, 3, 6, Never),
+
+    // 0x30
+    official("BMI", Relative, 2, 2, Branch),
+    official("AND", IndirectIndexed, 2, 5, PageCrossed),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("RLA", IndirectIndexed, 2, 8, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("AND", ZeroPageX, 2, 4, Never),
+    official("ROL", ZeroPageX, 2, 6, Never),
+    unofficial("RLA", ZeroPageX, 2, 6, Never),
+
+    // 0x38
+    official("SEC", Implicit, 1, 2, Never),
+    official("AND", AbsoluteY, 3, 4, PageCrossed),
+    unofficial("NOP", Implicit, 1, 2, Never),
+    unofficial("RLA", AbsoluteY, 3, 7, Never),
+
+    unofficial("NOP", AbsoluteX, 3, 4, PageCrossed),
+    official("AND", AbsoluteX, 3, 4, PageCrossed),
+    official("ROL", AbsoluteX, 3, 7, Never),
+    unofficial("RLA", AbsoluteX, 3, 7, Never),
+
+    // 0x40
+    official("RTI", Implicit, 1, 6, Never),
+    official("EOR", IndexedIndirect, 2, 6, Never),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("SRE", IndexedIndirect, 2, 8, Never),
+
+    unofficial("NOP", ZeroPage, 2, 3, Never),
+    official("EOR", ZeroPage, 2, 3, Never),
+    official("LSR", ZeroPage, 2, 5, Never),
+    unofficial("SRE", ZeroPage, 2, 5, Never),
+
+    // 0x48
+    official("PHA", Implicit, 1, 3, Never),
+    official("EOR", Immediate, 2, 2, Never),
+    official("LSR", Accumulator, 1, 2, Never),
+    unofficial("ALR", Immediate, 2, 2, Never),
+
+    official("JMP", Absolute, 3, 3, Never),
+    official("EOR", Absolute, 3, 4, Never),
+    official("LSR", Absolute, 3, 6, Never),
+    unofficial("SRE", Absolute, 3, 6, Never),
+
+    // 0x50
+    official("BVC", Relative, 2, 2, Branch),
+    official("EOR", IndirectIndexed, 2, 5, PageCrossed),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("SRE", IndirectIndexed, 2, 8, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("EOR", ZeroPageX, 2, 4, Never),
+    official("LSR", ZeroPageX, 2, 6, Never),
+    unofficial("SRE", ZeroPageX, 2, 6, Never),
+
+    // 0x58
+    official("CLI", Implicit, 1, 2, Never),
+    official("EOR", AbsoluteY, 3, 4, PageCrossed),
+    unofficial("NOP", Implicit, 1, 2, Never),
+    unofficial("SRE", AbsoluteY, 3, 7, Never),
+
+    unofficial("NOP", AbsoluteX, 3, 4, PageCrossed),
+    official("EOR", AbsoluteX, 3, 4, PageCrossed),
+    official("LSR", AbsoluteX, 3, 7, Never),
+    unofficial("SRE", AbsoluteX, 3, 7, Never),
+
+    // 0x60
+    official("RTS", Implicit, 1, 6, Never),
+    official("ADC", IndexedIndirect, 2, 6, Never),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("RRA", IndexedIndirect, 2, 8, Never),
+
+    unofficial("NOP", ZeroPage, 2, 3, Never),
+    official("ADC", ZeroPage, 2, 3, Never),
+    official("ROR", ZeroPage, 2, 5, Never),
+    unofficial("RRA", ZeroPage, 2, 5, Never),
+
+    // 0x68
+    official("PLA", Implicit, 1, 4, Never),
+    official("ADC", Immediate, 2, 2, Never),
+    official("ROR", Accumulator, 1, 2, Never),
+    unofficial("ARR", Immediate, 2, 2, Never),
+
+    official("JMP", Indirect, 3, 5, Never),
+    official("ADC", Absolute, 3, 4, Never),
+    official("ROR", Absolute, 3, 6, Never),
+    unofficial("RRA", Absolute, 3, 6, Never),
+
+    // 0x70
+    official("BVS", Relative, 2, 2, Branch),
+    official("ADC", IndirectIndexed, 2, 5, PageCrossed),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("RRA", IndirectIndexed, 2, 8, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("ADC", ZeroPageX, 2, 4, Never),
+    official("ROR", ZeroPageX, 2, 6, Never),
+    unofficial("RRA", ZeroPageX, 2, 6, Never),
+
+    // 0x78
+    official("SEI", Implicit, 1, 2, Never),
+    official("ADC", AbsoluteY, 3, 4, PageCrossed),
+    unofficial("NOP", Implicit, 1, 2, Never),
+    unofficial("RRA", AbsoluteY, 3, 7, Never),
+
+    unofficial("NOP", AbsoluteX, 3, 4, PageCrossed),
+    official("ADC", AbsoluteX, 3, 4, PageCrossed),
+    official("ROR", AbsoluteX, 3, 7, Never),
+    unofficial("RRA", AbsoluteX, 3, 7, Never),
+
+    // 0x80
+    unofficial("NOP", Immediate, 2, 2, Never),
+    official("STA", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("SAX", IndexedIndirect, 2, 6, Never),
+
+    official("STY", ZeroPage, 2, 3, Never),
+    official("STA", ZeroPage, 2, 3, Never),
+    official("STX", ZeroPage, 2, 3, Never),
+    unofficial("SAX", ZeroPage, 2, 3, Never),
+
+    // 0x88
+    official("DEY", Implicit, 1, 2, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    official("TXA", Implicit, 1, 2, Never),
+    unofficial("XAA", Immediate, 2, 2, Never),
+
+    official("STY", Absolute, 3, 4, Never),
+    official("STA", Absolute, 3, 4, Never),
+    official("STX", Absolute, 3, 4, Never),
+    unofficial("SAX", Absolute, 3, 4, Never),
+
+    // 0x90
+    official("BCC", Relative, 2, 2, Branch),
+    official("STA", IndirectIndexed, 2, 6, Never),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("SHA", IndirectIndexed, 2, 6, Never),
+
+    official("STY", ZeroPageX, 2, 4, Never),
+    official("STA", ZeroPageX, 2, 4, Never),
+    official("STX", ZeroPageY, 2, 4, Never),
+    unofficial("SAX", ZeroPageY, 2, 4, Never),
+
+    // 0x98
+    official("TYA", Implicit, 1, 2, Never),
+    official("STA", AbsoluteY, 3, 5, Never),
+    official("TXS", Implicit, 1, 2, Never),
+    unofficial("TAS", AbsoluteY, 3, 5, Never),
+
+    unofficial("SHY", AbsoluteX, 3, 5, Never),
+    official("STA", AbsoluteX, 3, 5, Never),
+    unofficial("SHX", AbsoluteY, 3, 5, Never),
+    unofficial("SHA", AbsoluteY, 3, 5, Never),
+
+    // 0xA0
+    official("LDY", Immediate, 2, 2, Never),
+    official("LDA", IndexedIndirect, 2, 6, Never),
+    official("LDX", Immediate, 2, 2, Never),
+    unofficial("LAX", IndexedIndirect, 2, 6, Never),
+
+    official("LDY", ZeroPage, 2, 3, Never),
+    official("LDA", ZeroPage, 2, 3, Never),
+    official("LDX", ZeroPage, 2, 3, Never),
+    unofficial("LAX", ZeroPage, 2, 3, Never),
+
+    // 0xA8
+    official("TAY", Implicit, 1, 2, Never),
+    official("LDA", Immediate, 2, 2, Never),
+    official("TAX", Implicit, 1, 2, Never),
+    unofficial("LXA", Immediate, 2, 2, Never),
+
+    official("LDY", Absolute, 3, 4, Never),
+    official("LDA", Absolute, 3, 4, Never),
+    official("LDX", Absolute, 3, 4, Never),
+    unofficial("LAX", Absolute, 3, 4, Never),
+
+    // 0xB0
+    official("BCS", Relative, 2, 2, Branch),
+    official("LDA", IndirectIndexed, 2, 5, PageCrossed),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("LAX", IndirectIndexed, 2, 5, PageCrossed),
+
+    official("LDY", ZeroPageX, 2, 4, Never),
+    official("LDA", ZeroPageX, 2, 4, Never),
+    official("LDX", ZeroPageY, 2, 4, Never),
+    unofficial("LAX", ZeroPageY, 2, 4, Never),
+
+    // 0xB8
+    official("CLV", Implicit, 1, 2, Never),
+    official("LDA", AbsoluteY, 3, 4, PageCrossed),
+    official("TSX", Implicit, 1, 2, Never),
+    unofficial("LAS", AbsoluteY, 3, 4, PageCrossed),
+
+    official("LDY", AbsoluteX, 3, 4, PageCrossed),
+    official("LDA", AbsoluteX, 3, 4, PageCrossed),
+    official("LDX", AbsoluteY, 3, 4, PageCrossed),
+    unofficial("LAX", AbsoluteY, 3, 4, PageCrossed),
+
+    // 0xC0
+    official("CPY", Immediate, 2, 2, Never),
+    official("CMP", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("DCP", IndexedIndirect, 2, 8, Never),
+
+    official("CPY", ZeroPage, 2, 3, Never),
+    official("CMP", ZeroPage, 2, 3, Never),
+    official("DEC", ZeroPage, 2, 5, Never),
+    unofficial("DCP", ZeroPage, 2, 5, Never),
+
+    // 0xC8
+    official("INY", Implicit, 1, 2, Never),
+    official("CMP", Immediate, 2, 2, Never),
+    official("DEX", Implicit, 1, 2, Never),
+    unofficial("AXS", Immediate, 2, 2, Never),
+
+    official("CPY", Absolute, 3, 4, Never),
+    official("CMP", Absolute, 3, 4, Never),
+    official("DEC", Absolute, 3, 6, Never),
+    unofficial("DCP", Absolute, 3, 6, Never),
+
+    // 0xD0
+    official("BNE", Relative, 2, 2, Branch),
+    official("CMP", IndirectIndexed, 2, 5, PageCrossed),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("DCP", IndirectIndexed, 2, 8, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("CMP", ZeroPageX, 2, 4, Never),
+    official("DEC", ZeroPageX, 2, 6, Never),
+    unofficial("DCP", ZeroPageX, 2, 6, Never),
+
+    // 0xD8
+    official("CLD", Implicit, 1, 2, Never),
+    official("CMP", AbsoluteY, 3, 4, PageCrossed),
+    unofficial("NOP", Implicit, 1, 2, Never),
+    unofficial("DCP", AbsoluteY, 3, 7, Never),
+
+    unofficial("NOP", AbsoluteX, 3, 4, PageCrossed),
+    official("CMP", AbsoluteX, 3, 4, PageCrossed),
+    official("DEC", AbsoluteX, 3, 7, Never),
+    unofficial("DCP", AbsoluteX, 3, 7, Never),
+
+    // 0xE0
+    official("CPX", Immediate, 2, 2, Never),
+    official("SBC", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("ISC", IndexedIndirect, 2, 8, Never),
+
+    official("CPX", ZeroPage, 2, 3, Never),
+    official("SBC", ZeroPage, 2, 3, Never),
+    official("INC", ZeroPage, 2, 5, Never),
+    unofficial("ISC", ZeroPage, 2, 5, Never),
+
+    // 0xE8
+    official("INX", Implicit, 1, 2, Never),
+    official("SBC", Immediate, 2, 2, Never),
+    official("NOP", Implicit, 1, 2, Never),
+    unofficial("SBC", Immediate, 2, 2, Never),
+
+    official("CPX", Absolute, 3, 4, Never),
+    official("SBC", Absolute, 3, 4, Never),
+    official("INC", Absolute, 3, 6, Never),
+    unofficial("ISC", Absolute, 3, 6, Never),
+
+    // 0xF0
+    official("BEQ", Relative, 2, 2, Branch),
+    official("SBC", IndirectIndexed, 2, 5, PageCrossed),
+    unofficial("JAM", Implicit, 1, 2, Never),
+    unofficial("ISC", IndirectIndexed, 2, 8, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("SBC", ZeroPageX, 2, 4, Never),
+    official("INC", ZeroPageX, 2, 6, Never),
+    unofficial("ISC", ZeroPageX, 2, 6, Never),
+
+    // 0xF8
+    official("SED", Implicit, 1, 2, Never),
+    official("SBC", AbsoluteY, 3, 4, PageCrossed),
+    unofficial("NOP", Implicit, 1, 2, Never),
+    unofficial("ISC", AbsoluteY, 3, 7, Never),
+
+    unofficial("NOP", AbsoluteX, 3, 4, PageCrossed),
+    official("SBC", AbsoluteX, 3, 4, PageCrossed),
+    official("INC", AbsoluteX, 3, 7, Never),
+    unofficial("ISC", AbsoluteX, 3, 7, Never),
+
+];
+
+/// Metadata of the WDC 65C02, indexed by the opcode. All undefined opcodes
+/// are NOPs of one to three bytes.
+#[rustfmt::skip]
+pub static OP_INFO_65C02: [OpInfo; 256] = [
+    // 0x00
+    official("BRK", Implicit, 1, 7, Never),
+    official("ORA", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
 
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("TSB", ZeroPage, 2, 5, Never),
+    official("ORA", ZeroPage, 2, 3, Never),
+    official("ASL", ZeroPage, 2, 5, Never),
+    official("RMB0", ZeroPage, 2, 5, Never),
 
     // 0x08
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("PHP", Implicit, 1, 3, Never),
+    official("ORA", Immediate, 2, 2, Never),
+    official("ASL", Accumulator, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
 
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("TSB", Absolute, 3, 6, Never),
+    official("ORA", Absolute, 3, 4, Never),
+    official("ASL", Absolute, 3, 6, Never),
+    official("BBR0", ZeroPageRelative, 3, 5, Branch),
 
     // 0x10
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BPL", Relative, 2, 2, Branch),
+    official("ORA", IndirectIndexed, 2, 5, PageCrossed),
+    official("ORA", ZeroPageIndirect, 2, 5, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("TRB", ZeroPage, 2, 5, Never),
+    official("ORA", ZeroPageX, 2, 4, Never),
+    official("ASL", ZeroPageX, 2, 6, Never),
+    official("RMB1", ZeroPage, 2, 5, Never),
+
+    // 0x18
+    official("CLC", Implicit, 1, 2, Never),
+    official("ORA", AbsoluteY, 3, 4, PageCrossed),
+    official("INC", Accumulator, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("TRB", Absolute, 3, 6, Never),
+    official("ORA", AbsoluteX, 3, 4, PageCrossed),
+    official("ASL", AbsoluteX, 3, 6, PageCrossed),
+    official("BBR1", ZeroPageRelative, 3, 5, Branch),
 
     // 0x20
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("JSR", Absolute, 3, 6, Never),
+    official("AND", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("BIT", ZeroPage, 2, 3, Never),
+    official("AND", ZeroPage, 2, 3, Never),
+    official("ROL", ZeroPage, 2, 5, Never),
+    official("RMB2", ZeroPage, 2, 5, Never),
+
+    // 0x28
+    official("PLP", Implicit, 1, 4, Never),
+    official("AND", Immediate, 2, 2, Never),
+    official("ROL", Accumulator, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("BIT", Absolute, 3, 4, Never),
+    official("AND", Absolute, 3, 4, Never),
+    official("ROL", Absolute, 3, 6, Never),
+    official("BBR2", ZeroPageRelative, 3, 5, Branch),
 
     // 0x30
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BMI", Relative, 2, 2, Branch),
+    official("AND", IndirectIndexed, 2, 5, PageCrossed),
+    official("AND", ZeroPageIndirect, 2, 5, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("BIT", ZeroPageX, 2, 4, Never),
+    official("AND", ZeroPageX, 2, 4, Never),
+    official("ROL", ZeroPageX, 2, 6, Never),
+    official("RMB3", ZeroPage, 2, 5, Never),
+
+    // 0x38
+    official("SEC", Implicit, 1, 2, Never),
+    official("AND", AbsoluteY, 3, 4, PageCrossed),
+    official("DEC", Accumulator, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("BIT", AbsoluteX, 3, 4, PageCrossed),
+    official("AND", AbsoluteX, 3, 4, PageCrossed),
+    official("ROL", AbsoluteX, 3, 6, PageCrossed),
+    official("BBR3", ZeroPageRelative, 3, 5, Branch),
 
     // 0x40
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("RTI", Implicit, 1, 6, Never),
+    official("EOR", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    unofficial("NOP", ZeroPage, 2, 3, Never),
+    official("EOR", ZeroPage, 2, 3, Never),
+    official("LSR", ZeroPage, 2, 5, Never),
+    official("RMB4", ZeroPage, 2, 5, Never),
+
+    // 0x48
+    official("PHA", Implicit, 1, 3, Never),
+    official("EOR", Immediate, 2, 2, Never),
+    official("LSR", Accumulator, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("JMP", Absolute, 3, 3, Never),
+    official("EOR", Absolute, 3, 4, Never),
+    official("LSR", Absolute, 3, 6, Never),
+    official("BBR4", ZeroPageRelative, 3, 5, Branch),
 
     // 0x50
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BVC", Relative, 2, 2, Branch),
+    official("EOR", IndirectIndexed, 2, 5, PageCrossed),
+    official("EOR", ZeroPageIndirect, 2, 5, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("EOR", ZeroPageX, 2, 4, Never),
+    official("LSR", ZeroPageX, 2, 6, Never),
+    official("RMB5", ZeroPage, 2, 5, Never),
+
+    // 0x58
+    official("CLI", Implicit, 1, 2, Never),
+    official("EOR", AbsoluteY, 3, 4, PageCrossed),
+    official("PHY", Implicit, 1, 3, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    unofficial("NOP", Absolute, 3, 8, Never),
+    official("EOR", AbsoluteX, 3, 4, PageCrossed),
+    official("LSR", AbsoluteX, 3, 6, PageCrossed),
+    official("BBR5", ZeroPageRelative, 3, 5, Branch),
 
     // 0x60
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("RTS", Implicit, 1, 6, Never),
+    official("ADC", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("STZ", ZeroPage, 2, 3, Never),
+    official("ADC", ZeroPage, 2, 3, Never),
+    official("ROR", ZeroPage, 2, 5, Never),
+    official("RMB6", ZeroPage, 2, 5, Never),
+
+    // 0x68
+    official("PLA", Implicit, 1, 4, Never),
+    official("ADC", Immediate, 2, 2, Never),
+    official("ROR", Accumulator, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("JMP", Indirect, 3, 6, Never),
+    official("ADC", Absolute, 3, 4, Never),
+    official("ROR", Absolute, 3, 6, Never),
+    official("BBR6", ZeroPageRelative, 3, 5, Branch),
 
     // 0x70
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BVS", Relative, 2, 2, Branch),
+    official("ADC", IndirectIndexed, 2, 5, PageCrossed),
+    official("ADC", ZeroPageIndirect, 2, 5, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("STZ", ZeroPageX, 2, 4, Never),
+    official("ADC", ZeroPageX, 2, 4, Never),
+    official("ROR", ZeroPageX, 2, 6, Never),
+    official("RMB7", ZeroPage, 2, 5, Never),
+
+    // 0x78
+    official("SEI", Implicit, 1, 2, Never),
+    official("ADC", AbsoluteY, 3, 4, PageCrossed),
+    official("PLY", Implicit, 1, 4, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("JMP", AbsoluteIndexedIndirect, 3, 6, Never),
+    official("ADC", AbsoluteX, 3, 4, PageCrossed),
+    official("ROR", AbsoluteX, 3, 6, PageCrossed),
+    official("BBR7", ZeroPageRelative, 3, 5, Branch),
 
     // 0x80
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BRA", Relative, 2, 2, Branch),
+    official("STA", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("STY", ZeroPage, 2, 3, Never),
+    official("STA", ZeroPage, 2, 3, Never),
+    official("STX", ZeroPage, 2, 3, Never),
+    official("SMB0", ZeroPage, 2, 5, Never),
+
+    // 0x88
+    official("DEY", Implicit, 1, 2, Never),
+    official("BIT", Immediate, 2, 2, Never),
+    official("TXA", Implicit, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("STY", Absolute, 3, 4, Never),
+    official("STA", Absolute, 3, 4, Never),
+    official("STX", Absolute, 3, 4, Never),
+    official("BBS0", ZeroPageRelative, 3, 5, Branch),
 
     // 0x90
-    OpInfo{mode: Relative, bytes: 2, cycles: 2},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BCC", Relative, 2, 2, Branch),
+    official("STA", IndirectIndexed, 2, 6, Never),
+    official("STA", ZeroPageIndirect, 2, 5, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("STY", ZeroPageX, 2, 4, Never),
+    official("STA", ZeroPageX, 2, 4, Never),
+    official("STX", ZeroPageY, 2, 4, Never),
+    official("SMB1", ZeroPage, 2, 5, Never),
+
+    // 0x98
+    official("TYA", Implicit, 1, 2, Never),
+    official("STA", AbsoluteY, 3, 5, Never),
+    official("TXS", Implicit, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("STZ", Absolute, 3, 4, Never),
+    official("STA", AbsoluteX, 3, 5, Never),
+    official("STZ", AbsoluteX, 3, 5, Never),
+    official("BBS1", ZeroPageRelative, 3, 5, Branch),
 
     // 0xA0
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 2, cycles: 2}, // ldx
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("LDY", Immediate, 2, 2, Never),
+    official("LDA", IndexedIndirect, 2, 6, Never),
+    official("LDX", Immediate, 2, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("LDY", ZeroPage, 2, 3, Never),
+    official("LDA", ZeroPage, 2, 3, Never),
+    official("LDX", ZeroPage, 2, 3, Never),
+    official("SMB2", ZeroPage, 2, 5, Never),
+
+    // 0xA8
+    official("TAY", Implicit, 1, 2, Never),
+    official("LDA", Immediate, 2, 2, Never),
+    official("TAX", Implicit, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("LDY", Absolute, 3, 4, Never),
+    official("LDA", Absolute, 3, 4, Never),
+    official("LDX", Absolute, 3, 4, Never),
+    official("BBS2", ZeroPageRelative, 3, 5, Branch),
 
     // 0xB0
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BCS", Relative, 2, 2, Branch),
+    official("LDA", IndirectIndexed, 2, 5, PageCrossed),
+    official("LDA", ZeroPageIndirect, 2, 5, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("LDY", ZeroPageX, 2, 4, Never),
+    official("LDA", ZeroPageX, 2, 4, Never),
+    official("LDX", ZeroPageY, 2, 4, Never),
+    official("SMB3", ZeroPage, 2, 5, Never),
+
+    // 0xB8
+    official("CLV", Implicit, 1, 2, Never),
+    official("LDA", AbsoluteY, 3, 4, PageCrossed),
+    official("TSX", Implicit, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("LDY", AbsoluteX, 3, 4, PageCrossed),
+    official("LDA", AbsoluteX, 3, 4, PageCrossed),
+    official("LDX", AbsoluteY, 3, 4, PageCrossed),
+    official("BBS3", ZeroPageRelative, 3, 5, Branch),
+
+    // 0xC0
+    official("CPY", Immediate, 2, 2, Never),
+    official("CMP", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("CPY", ZeroPage, 2, 3, Never),
+    official("CMP", ZeroPage, 2, 3, Never),
+    official("DEC", ZeroPage, 2, 5, Never),
+    official("SMB4", ZeroPage, 2, 5, Never),
+
+    // 0xC8
+    official("INY", Implicit, 1, 2, Never),
+    official("CMP", Immediate, 2, 2, Never),
+    official("DEX", Implicit, 1, 2, Never),
+    official("WAI", Implicit, 1, 3, Never),
+
+    official("CPY", Absolute, 3, 4, Never),
+    official("CMP", Absolute, 3, 4, Never),
+    official("DEC", Absolute, 3, 6, Never),
+    official("BBS4", ZeroPageRelative, 3, 5, Branch),
 
     // 0xD0
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("BNE", Relative, 2, 2, Branch),
+    official("CMP", IndirectIndexed, 2, 5, PageCrossed),
+    official("CMP", ZeroPageIndirect, 2, 5, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("CMP", ZeroPageX, 2, 4, Never),
+    official("DEC", ZeroPageX, 2, 6, Never),
+    official("SMB5", ZeroPage, 2, 5, Never),
+
+    // 0xD8
+    official("CLD", Implicit, 1, 2, Never),
+    official("CMP", AbsoluteY, 3, 4, PageCrossed),
+    official("PHX", Implicit, 1, 3, Never),
+    official("STP", Implicit, 1, 3, Never),
+
+    unofficial("NOP", Absolute, 3, 4, Never),
+    official("CMP", AbsoluteX, 3, 4, PageCrossed),
+    official("DEC", AbsoluteX, 3, 7, Never),
+    official("BBS5", ZeroPageRelative, 3, 5, Branch),
 
     // 0xE0
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-
-    //0xF0
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
-    OpInfo{mode: Immediate, bytes: 0xFF, cycles: 0xFF},
+    official("CPX", Immediate, 2, 2, Never),
+    official("SBC", IndexedIndirect, 2, 6, Never),
+    unofficial("NOP", Immediate, 2, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("CPX", ZeroPage, 2, 3, Never),
+    official("SBC", ZeroPage, 2, 3, Never),
+    official("INC", ZeroPage, 2, 5, Never),
+    official("SMB6", ZeroPage, 2, 5, Never),
+
+    // 0xE8
+    official("INX", Implicit, 1, 2, Never),
+    official("SBC", Immediate, 2, 2, Never),
+    official("NOP", Implicit, 1, 2, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    official("CPX", Absolute, 3, 4, Never),
+    official("SBC", Absolute, 3, 4, Never),
+    official("INC", Absolute, 3, 6, Never),
+    official("BBS6", ZeroPageRelative, 3, 5, Branch),
+
+    // 0xF0
+    official("BEQ", Relative, 2, 2, Branch),
+    official("SBC", IndirectIndexed, 2, 5, PageCrossed),
+    official("SBC", ZeroPageIndirect, 2, 5, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    unofficial("NOP", ZeroPageX, 2, 4, Never),
+    official("SBC", ZeroPageX, 2, 4, Never),
+    official("INC", ZeroPageX, 2, 6, Never),
+    official("SMB7", ZeroPage, 2, 5, Never),
+
+    // 0xF8
+    official("SED", Implicit, 1, 2, Never),
+    official("SBC", AbsoluteY, 3, 4, PageCrossed),
+    official("PLX", Implicit, 1, 4, Never),
+    unofficial("NOP", Implicit, 1, 1, Never),
+
+    unofficial("NOP", Absolute, 3, 4, Never),
+    official("SBC", AbsoluteX, 3, 4, PageCrossed),
+    official("INC", AbsoluteX, 3, 7, Never),
+    official("BBS7", ZeroPageRelative, 3, 5, Branch),
 ];
+
+/// Opcode metadata of the given processor variant. The NMOS 6502 and the
+/// 2A03 share their opcodes.
+pub fn op_info(variant: Variant) -> &'static [OpInfo; 256] {
+    match variant {
+        Variant::Nmos | Variant::Ricoh2A03 => &OP_INFO,
+        Variant::Cmos65C02 => &OP_INFO_65C02,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn official_opcode_count() {
+        let n = OP_INFO.iter().filter(|o| o.official).count();
+        assert_eq!(n, 151);
+    }
+
+    #[test]
+    fn bytes_match_addressing_mode() {
+        for (op, o) in OP_INFO.iter().enumerate() {
+            assert_eq!(o.bytes, mode_bytes(o.mode), "opcode {:#04X}", op);
+        }
+        for (op, o) in OP_INFO_65C02.iter().enumerate() {
+            assert_eq!(o.bytes, mode_bytes(o.mode), "65C02 opcode {:#04X}", op);
+        }
+    }
+
+    fn mode_bytes(mode: AddressingMode) -> usize {
+        match mode {
+            Implicit | Accumulator => 1,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 3,
+            AbsoluteIndexedIndirect | ZeroPageRelative => 3,
+            _ => 2,
+        }
+    }
+
+    #[test]
+    fn only_relative_mode_is_branch() {
+        for o in OP_INFO.iter().chain(OP_INFO_65C02.iter()) {
+            let relative = o.mode == Relative || o.mode == ZeroPageRelative;
+            assert_eq!(relative, o.penalty == Branch, "{}", o.mnemonic);
+        }
+    }
+
+    #[test]
+    fn lookup_known_opcodes() {
+        assert_eq!(OP_INFO[0x60].mnemonic, "RTS");
+        assert_eq!(OP_INFO[0xE8].mnemonic, "INX");
+        assert_eq!(OP_INFO[0xC8].mnemonic, "INY");
+        assert_eq!(OP_INFO[0xB1].mode, IndirectIndexed);
+        assert_eq!(OP_INFO[0xB1].penalty, PageCrossed);
+        assert_eq!(OP_INFO[0x91].penalty, Never);
+        assert_eq!(OP_INFO[0x6C].cycles, 5);
+    }
+
+    #[test]
+    fn lookup_65c02_opcodes() {
+        let n = OP_INFO_65C02.iter().filter(|o| o.official).count();
+        assert_eq!(n, 212);
+
+        assert_eq!(OP_INFO_65C02[0x80].mnemonic, "BRA");
+        assert_eq!(OP_INFO_65C02[0x92].mode, ZeroPageIndirect);
+        assert_eq!(OP_INFO_65C02[0x7C].mode, AbsoluteIndexedIndirect);
+        assert_eq!(OP_INFO_65C02[0x6C].cycles, 6);
+        assert_eq!(OP_INFO_65C02[0xFF].mnemonic, "BBS7");
+        assert_eq!(op_info(Variant::Ricoh2A03)[0x02].mnemonic, "JAM");
+        assert_eq!(op_info(Variant::Cmos65C02)[0x02].mnemonic, "NOP");
+    }
+}
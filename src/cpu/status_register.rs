@@ -67,6 +67,7 @@ impl StatusRegister {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -137,6 +138,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(non_snake_case)]
     fn set_all_0xFF() {
         let mut sr = StatusRegister::new();
         sr.set_all(0xFF);
@@ -1,5 +1,5 @@
 /// most significatn bit
-const msb: u8 = 0x01 << 7;
+const MSB: u8 = 0x01 << 7;
 
 /// Checks if the overflow bit has to be set. `A` and `B` denote the values of
 /// the operands of an arithmetic operation, `C` the result of the operation.
@@ -34,7 +34,7 @@ const msb: u8 = 0x01 << 7;
 ///
 ///
 pub fn calculate_overflow_bit(a: u8, b: u8, c: u8) -> bool {
-    return ((!a & !b & c) | (a & b & !c)) & msb == msb;
+    ((!a & !b & c) | (a & b & !c)) & MSB == MSB
 }
 
 #[cfg(test)]
@@ -43,7 +43,7 @@ mod test {
 
     #[test]
     fn msb_value() {
-        assert_eq!(msb, 128);
+        assert_eq!(MSB, 128);
     }
 
     #[test]
@@ -68,6 +68,7 @@ mod test {
     }
 
     #[test]
+    #[allow(overflowing_literals)]
     fn non_overlfow_cases() {
         assert!(!calculate_overflow_bit(1, 1, 2));
         assert!(!calculate_overflow_bit(128, 2, 130));
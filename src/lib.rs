@@ -1,4 +1,9 @@
+#[cfg(test)]
+extern crate serde_json;
+
+pub mod cdl;
 pub mod cpu;
-mod memory;
+pub mod memory;
 pub mod nes;
+pub mod profiler;
 pub mod rom;
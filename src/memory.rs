@@ -1,28 +1,184 @@
-const MEM_SIZE: usize = 0xFFFF;
+//! The address space of the NES as seen by the CPU:
+//!
+//! ```text
+//! $0000-$07FF  2 kB internal RAM, mirrored through $1FFF
+//! $2000-$2007  PPU registers, mirrored through $3FFF
+//! $4000-$401F  APU and I/O registers
+//! $4020-$FFFF  cartridge space, routed to the mapper
+//! ```
+//!
+//! The PPU, the APU and I/O and the cartridge are devices implementing
+//! `Bus`, so their registers can have side effects on read and write. A
+//! device gets the address as the CPU put it on the bus, except for the PPU,
+//! which gets the mirrored registers at $2000-$2007.
+//!
+//! Every read and write leaves its value on the data bus. Reads of addresses
+//! without a device and the bits a device does not drive, see
+//! `Bus::driven_bits`, return this open bus value. Writes to addresses
+//! without a device are ignored.
+
+use cpu::bus::Bus;
+
+pub(crate) const RAM_SIZE: usize = 0x0800;
+pub(crate) const RAM_END: u16 = 0x2000;
+
+pub(crate) const PPU_REGISTERS: u16 = 0x2000;
+pub(crate) const PPU_REGISTERS_END: u16 = 0x4000;
+/// The eight PPU registers repeat every 8 bytes.
+pub(crate) const PPU_REGISTER_MASK: u16 = 0x0007;
+
+const IO_END: u16 = 0x4020;
+
+/// Where most cartridges put their RAM and ROM in the cartridge space, the
+/// expansion area below is rarely used.
+pub(crate) const CARTRIDGE_RAM: u16 = 0x6000;
+pub(crate) const CARTRIDGE_ROM: u16 = 0x8000;
+
+/// The region an address decodes to.
+enum Region {
+    Ram(usize),
+    Ppu(u16),
+    Io(u16),
+    Cartridge(u16),
+}
+
+fn decode(addr: u16) -> Region {
+    if addr < RAM_END {
+        Region::Ram(addr as usize % RAM_SIZE)
+    } else if addr < PPU_REGISTERS_END {
+        Region::Ppu(PPU_REGISTERS | (addr & PPU_REGISTER_MASK))
+    } else if addr < IO_END {
+        Region::Io(addr)
+    } else {
+        Region::Cartridge(addr)
+    }
+}
 
 pub struct Memory {
-    mem: [u8; MEM_SIZE],
+    ram: [u8; RAM_SIZE],
+    ppu: Option<Box<dyn Bus>>,
+    io: Option<Box<dyn Bus>>,
+    cartridge: Option<Box<dyn Bus>>,
+    /// the last value on the data bus
+    open_bus: u8,
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
 }
 
 impl Memory {
+    /// Internal RAM without any devices.
     pub fn new() -> Memory {
-        Memory { mem: [0; MEM_SIZE] }
+        Memory {
+            ram: [0; RAM_SIZE],
+            ppu: None,
+            io: None,
+            cartridge: None,
+            open_bus: 0,
+        }
+    }
+
+    /// Connect the PPU, it receives the accesses to $2000-$2007.
+    pub fn set_ppu(&mut self, ppu: Option<Box<dyn Bus>>) {
+        self.ppu = ppu;
+    }
+
+    /// Connect the APU and the I/O registers at $4000-$401F.
+    pub fn set_io(&mut self, io: Option<Box<dyn Bus>>) {
+        self.io = io;
     }
 
-    pub fn read(&self, addr: u16) -> u8 {
-        self.mem[addr as usize]
+    /// Insert a cartridge, its mapper receives the accesses to $4020-$FFFF.
+    pub fn set_cartridge(&mut self, cartridge: Option<Box<dyn Bus>>) {
+        self.cartridge = cartridge;
     }
 
-    pub fn write(&mut self, addr: u16, val: u8) {
-        // TODO: mirroring? Is it necessary to emulate?
-        self.mem[addr as usize] = val;
+    /// The last value read or written, which unmapped addresses read as.
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus
     }
 
-    /// Write a range in memory with a common value. The range is inclusice,
+    /// Write a range in memory with a common value. The range is inclusive,
     /// meaning both first and last are written.
     pub fn write_range(&mut self, first: usize, last: usize, val: u8) {
-        for x in (first..last) {
-            self.mem[x] = val;
+        for x in first..=last {
+            self.write(x as u16, val);
+        }
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, addr: u16) -> u8 {
+        let (device, addr) = match decode(addr) {
+            Region::Ram(i) => {
+                self.open_bus = self.ram[i];
+                return self.open_bus;
+            }
+            Region::Ppu(a) => (&mut self.ppu, a),
+            Region::Io(a) => (&mut self.io, a),
+            Region::Cartridge(a) => (&mut self.cartridge, a),
+        };
+        if let Some(ref mut device) = *device {
+            let val = device.read(addr);
+            let driven = device.driven_bits(addr);
+            self.open_bus = val & driven | self.open_bus & !driven;
+        }
+        self.open_bus
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.open_bus = val;
+        let (device, addr) = match decode(addr) {
+            Region::Ram(i) => return self.ram[i] = val,
+            Region::Ppu(a) => (&mut self.ppu, a),
+            Region::Io(a) => (&mut self.io, a),
+            Region::Cartridge(a) => (&mut self.cartridge, a),
+        };
+        if let Some(ref mut device) = *device {
+            device.write(addr, val);
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        let (device, addr) = match decode(addr) {
+            Region::Ram(i) => return Some(self.ram[i]),
+            Region::Ppu(a) => (&self.ppu, a),
+            Region::Io(a) => (&self.io, a),
+            Region::Cartridge(a) => (&self.cartridge, a),
+        };
+        match *device {
+            Some(ref device) => device.peek(addr).map(|val| {
+                let driven = device.driven_bits(addr);
+                val & driven | self.open_bus & !driven
+            }),
+            None => Some(self.open_bus),
+        }
+    }
+
+    fn poke(&mut self, addr: u16, val: u8) -> bool {
+        let (device, addr) = match decode(addr) {
+            Region::Ram(i) => {
+                self.ram[i] = val;
+                return true;
+            }
+            Region::Ppu(a) => (&mut self.ppu, a),
+            Region::Io(a) => (&mut self.io, a),
+            Region::Cartridge(a) => (&mut self.cartridge, a),
+        };
+        match *device {
+            Some(ref mut device) => device.poke(addr, val),
+            None => false,
+        }
+    }
+
+    fn tick(&mut self) {
+        for device in [&mut self.ppu, &mut self.io, &mut self.cartridge].iter_mut() {
+            if let Some(ref mut device) = **device {
+                device.tick();
+            }
         }
     }
 }
@@ -32,20 +188,154 @@ pub fn page(addr: u16) -> u8{
 }
 
 pub fn page_crossed(addr_a: u16, addr_b: u16) -> bool {
-    (addr_a >> 8) != (addr_b >> 8)
+    page(addr_a) != page(addr_b)
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
     use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     #[test]
     fn mem_write_range() {
         let mut mem = Memory::new();
         mem.write_range(0x00FF, 0x0200, 0x04);
-        for x in (0x00FF..0x0200) {
+        for x in 0x00FF..=0x0200 {
             assert_eq!(mem.read(x), 0x04);
         }
+        assert_eq!(mem.read(0x0201), 0x00);
+    }
+
+    type Log = Rc<RefCell<Vec<(&'static str, u16, u8)>>>;
+
+    /// Registers which log their accesses, a read of the first one clears
+    /// it like the status register of the PPU.
+    struct Registers {
+        log: Log,
+        status: u8,
+    }
+
+    impl Bus for Registers {
+        fn read(&mut self, addr: u16) -> u8 {
+            let val = if addr & 0x07 == 2 { self.status } else { addr as u8 };
+            if addr & 0x07 == 2 {
+                self.status = 0;
+            }
+            self.log.borrow_mut().push(("read", addr, val));
+            val
+        }
+
+        fn write(&mut self, addr: u16, val: u8) {
+            self.log.borrow_mut().push(("write", addr, val));
+        }
+
+        fn peek(&self, addr: u16) -> Option<u8> {
+            Some(if addr & 0x07 == 2 { self.status } else { addr as u8 })
+        }
+    }
+
+    fn registers() -> (Box<dyn Bus>, Log) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let registers = Registers {
+            log: log.clone(),
+            status: 0x80,
+        };
+        (Box::new(registers), log)
+    }
+
+    #[test]
+    fn ram_mirrors() {
+        let mut mem = Memory::new();
+        mem.write(0x0012, 0x34);
+        assert_eq!(mem.read(0x0812), 0x34);
+        assert_eq!(mem.read(0x1812), 0x34);
+        mem.write(0x1FFF, 0x56);
+        assert_eq!(mem.peek(0x07FF), Some(0x56));
+    }
+
+    #[test]
+    fn devices() {
+        let mut mem = Memory::new();
+        assert!(!mem.poke(0x8000, 0));
+
+        let (ppu, ppu_log) = registers();
+        let (io, io_log) = registers();
+        let (cartridge, cartridge_log) = registers();
+        mem.set_ppu(Some(ppu));
+        mem.set_io(Some(io));
+        mem.set_cartridge(Some(cartridge));
+
+        // the status is cleared by a read, but not by a peek
+        assert_eq!(mem.peek(0x3FFA), Some(0x80));
+        assert_eq!(mem.read(0x3FFA), 0x80);
+        assert_eq!(mem.read(0x2002), 0x00);
+        mem.write(0x2008, 0x1E);
+        assert_eq!(
+            *ppu_log.borrow(),
+            vec![
+                ("read", 0x2002, 0x80),
+                ("read", 0x2002, 0x00),
+                ("write", 0x2000, 0x1E)
+            ]
+        );
+
+        mem.write(0x4014, 0x02);
+        assert_eq!(mem.read(0x401F), 0x1F);
+        assert_eq!(*io_log.borrow(), vec![("write", 0x4014, 0x02), ("read", 0x401F, 0x1F)]);
+
+        mem.write(0x4020, 0x01);
+        assert_eq!(mem.read(0xFFFF), 0xFF);
+        assert_eq!(
+            *cartridge_log.borrow(),
+            vec![("write", 0x4020, 0x01), ("read", 0xFFFF, 0xFF)]
+        );
+    }
+
+    /// A controller port driving only bit 0, with the serial data of the
+    /// buttons.
+    struct Controller {
+        buttons: u8,
+    }
+
+    impl Bus for Controller {
+        fn read(&mut self, _addr: u16) -> u8 {
+            let bit = self.buttons & 0x01;
+            self.buttons >>= 1;
+            bit
+        }
+
+        fn write(&mut self, _addr: u16, _val: u8) {}
+
+        fn driven_bits(&self, _addr: u16) -> u8 {
+            0x01
+        }
+    }
+
+    #[test]
+    fn open_bus() {
+        let mut mem = Memory::new();
+        mem.write(0x0010, 0x40);
+        assert_eq!(mem.read(0x0010), 0x40);
+        assert_eq!(mem.read(0x5000), 0x40);
+        assert_eq!(mem.peek(0xFFFF), Some(0x40));
+        mem.write(0x2000, 0x9C);
+        assert_eq!(mem.read(0x8000), 0x9C);
+        assert_eq!(mem.open_bus(), 0x9C);
+
+        // LDA $4016 leaves the high byte of the address on the bus
+        mem.set_io(Some(Box::new(Controller { buttons: 0x05 })));
+        mem.write(0x0000, 0x40);
+        let reads: Vec<u8> = (0..3)
+            .map(|_| {
+                mem.read(0x0000);
+                mem.read(0x4016)
+            })
+            .collect();
+        assert_eq!(reads, vec![0x41, 0x40, 0x41]);
+        // the controller cannot be peeked
+        assert_eq!(mem.peek(0x4016), None);
     }
 
     #[test]
@@ -10,24 +10,25 @@
 //!
 //! * once the rom is loaded, where is it put in memory?
 
-use cpu::cpu::CPU;
+use cpu::cpu::{CpuState, CPU};
+use cpu::debug::{Backtrace, Breakpoint, BreakpointId, Stop};
+use cpu::hook::{Hook, HookId};
+use cpu::lint::LintReport;
 use memory::Memory;
 
-/// dummy for completeness
-pub struct Cartridge {}
-pub struct PPU{}
-pub struct APU{}
-pub struct Clock{}
+/// CPU cycles of an NTSC frame, 341 * 262 PPU dots at three dots per cycle
+const CPU_CYCLES_PER_FRAME: usize = 29781;
 
 pub struct Console {
     cpu: CPU,
     mem: Memory,
-    // TODO: missing:
-    //  car
-    car: Cartridge,
-    ppu: PPU,
-    apu: APU,
-    clk: Clock,
+    // TODO: missing: the cartridge, PPU, APU and clock, connected to `mem`
+}
+
+impl Default for Console {
+    fn default() -> Console {
+        Console::new()
+    }
 }
 
 impl Console {
@@ -35,12 +36,6 @@ impl Console {
         Console {
             cpu: CPU::new(),
             mem: Memory::new(),
-
-            // dummies
-            car: Cartridge{},
-            ppu: PPU{},
-            apu: APU{},
-            clk: Clock{},
         }
     }
 
@@ -48,24 +43,74 @@ impl Console {
         self.cpu.reset(&mut self.mem);
     }
 
-    pub fn load_cartridge(&mut self, filepath: &str) {
+    pub fn load_cartridge(&mut self, _filepath: &str) {
         self.cpu.reset(&mut self.mem);
     }
 
-    pub fn run(&mut self) {
-        // game loop
-        // TODO: timing
-        loop {
-            self.cpu.step(&mut self.mem);
+    /// Run until the CPU halts, hits a breakpoint or fails. Returns the
+    /// registers of the CPU if it halted without an error, e.g. on a JAM
+    /// opcode, and why it stopped otherwise. Running again continues after
+    /// a breakpoint.
+    pub fn run(&mut self) -> Result<CpuState, Stop> {
+        // game loop, the CPU runs a frame at a time and carries the cycles it
+        // overshot into the next frame
+        // TODO: run the PPU and APU for the same frame
+        let mut overshoot = 0;
+        while !self.cpu.is_halted() {
+            let budget = CPU_CYCLES_PER_FRAME.saturating_sub(overshoot);
+            let spent = self.cpu.run_for(&mut self.mem, budget)?;
+            overshoot = spent.saturating_sub(budget);
         }
+        Ok(self.cpu.state())
+    }
+
+    /// Register a hook with the CPU, see `CPU::add_hook`.
+    pub fn add_hook(&mut self, hook: Box<dyn Hook>) -> HookId {
+        self.cpu.add_hook(hook)
+    }
+
+    pub fn remove_hook(&mut self, id: HookId) -> Option<Box<dyn Hook>> {
+        self.cpu.remove_hook(id)
+    }
+
+    /// Register a breakpoint with the CPU, see `CPU::add_breakpoint`.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        self.cpu.add_breakpoint(breakpoint)
+    }
+
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Option<Breakpoint> {
+        self.cpu.remove_breakpoint(id)
+    }
+
+    /// Track the calls and interrupts of the CPU, see `CPU::set_call_stack`.
+    pub fn set_call_stack(&mut self, enabled: bool) {
+        self.cpu.set_call_stack(enabled);
+    }
+
+    pub fn backtrace(&self) -> Backtrace {
+        self.cpu.backtrace()
+    }
+
+    /// Warn about suspicious behaviour of the program, see `CPU::set_lint`.
+    pub fn set_lint(&mut self, enabled: bool) {
+        self.cpu.set_lint(enabled);
+    }
+
+    pub fn lint_report(&self) -> LintReport {
+        self.cpu.lint_report()
+    }
+
+    /// The address space of the CPU, to connect the devices.
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.mem
     }
 
     pub fn pause(&mut self) {}
 
     /// Store the state of the system
-    pub fn save(&mut self, path: &str) {}
+    pub fn save(&mut self, _path: &str) {}
 
-    pub fn load(&mut self, path: &str) {}
+    pub fn load(&mut self, _path: &str) {}
 
     pub fn poweroff(&mut self) {}
 }
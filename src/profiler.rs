@@ -0,0 +1,406 @@
+//! Cycle profiler charging the cycles of the CPU to the active subroutine.
+//!
+//! The `Profiler` is a `Hook` following the shadow call stack of the CPU, so
+//! the call stack has to be turned on with `CPU::set_call_stack`. Routines
+//! are identified by their entry address, the target of the JSR or the
+//! interrupt vector. Code outside of any routine is charged to `[top]`.
+//!
+//! The cycles of a JSR and of the interrupt sequence are charged to the
+//! routine they enter, those of the RTS or RTI to the routine they return
+//! to. Cycles spent idle, e.g. waiting for an interrupt, are charged with
+//! the next instruction.
+//!
+//! `write_folded` writes the collapsed stack format of `flamegraph.pl` and
+//! inferno:
+//!
+//! ```text
+//! [top];$C000 1200
+//! [top];$C000;$C123 420
+//! ```
+
+use cpu::debug::{Frame, FrameKind};
+use cpu::hook::{Hook, InstructionEvent};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+/// CPU cycles of the 20 vblank scanlines of an NTSC frame, the time the NMI
+/// handler has to update the PPU.
+pub const VBLANK_CYCLES: usize = 20 * 341 / 3;
+
+/// Name of the code outside of any routine.
+const TOP: &str = "[top]";
+
+/// The cycles charged to a routine.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoutineStats {
+    pub entry: u16,
+    pub calls: usize,
+    /// cycles spent in the routine and the routines it called
+    pub inclusive: usize,
+    /// cycles spent in the routine itself
+    pub exclusive: usize,
+}
+
+/// A distinct call path, a node of the tree of paths.
+struct Node {
+    parent: usize,
+    entry: u16,
+    cycles: usize,
+}
+
+/// A routine on the call stack.
+struct Activation {
+    entry: u16,
+    kind: FrameKind,
+    /// the charged cycles when the routine was entered
+    start: usize,
+}
+
+pub struct Profiler {
+    routines: HashMap<u16, RoutineStats>,
+    /// call paths, the first is `[top]`
+    nodes: Vec<Node>,
+    children: HashMap<(usize, u16), usize>,
+    /// the current path
+    node: usize,
+    stack: Vec<Activation>,
+    /// all cycles charged so far
+    total: usize,
+    /// cycle counter of the CPU after the last instruction
+    last_cycles: Option<usize>,
+    names: HashMap<u16, String>,
+    nmi_cycles: Vec<usize>,
+    vblank_budget: usize,
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            routines: HashMap::new(),
+            nodes: vec![Node {
+                parent: 0,
+                entry: 0,
+                cycles: 0,
+            }],
+            children: HashMap::new(),
+            node: 0,
+            stack: Vec::new(),
+            total: 0,
+            last_cycles: None,
+            names: HashMap::new(),
+            nmi_cycles: Vec::new(),
+            vblank_budget: VBLANK_CYCLES,
+        }
+    }
+
+    /// Name the routine at `entry` in the reports, e.g. with the symbols of
+    /// `asm::Program`. Unnamed routines are shown as `$C123`.
+    pub fn set_name(&mut self, entry: u16, name: &str) {
+        self.names.insert(entry, name.replace(';', "_"));
+    }
+
+    /// The cycles an NMI handler may take, `VBLANK_CYCLES` by default.
+    pub fn set_vblank_budget(&mut self, cycles: usize) {
+        self.vblank_budget = cycles;
+    }
+
+    pub fn total_cycles(&self) -> usize {
+        self.total
+    }
+
+    pub fn routine(&self, entry: u16) -> Option<RoutineStats> {
+        self.routines.get(&entry).cloned()
+    }
+
+    /// All routines called so far, the most expensive first.
+    pub fn routines(&self) -> Vec<RoutineStats> {
+        let mut routines: Vec<RoutineStats> = self.routines.values().cloned().collect();
+        routines.sort_by(|a, b| {
+            b.inclusive
+                .cmp(&a.inclusive)
+                .then(b.exclusive.cmp(&a.exclusive))
+                .then(a.entry.cmp(&b.entry))
+        });
+        routines
+    }
+
+    /// Cycles of every completed run of an NMI handler, one per frame.
+    pub fn nmi_frames(&self) -> &[usize] {
+        &self.nmi_cycles
+    }
+
+    /// Number of NMI handler runs which exceeded the vblank budget.
+    pub fn nmi_over_budget(&self) -> usize {
+        self.nmi_cycles
+            .iter()
+            .filter(|&&c| c > self.vblank_budget)
+            .count()
+    }
+
+    /// Write the exclusive cycles of every call path in the collapsed stack
+    /// format, sorted by path.
+    pub fn write_folded<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut lines: Vec<(String, usize)> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|n| n.1.cycles > 0)
+            .map(|(index, node)| (self.path(index), node.cycles))
+            .collect();
+        lines.sort();
+
+        for (path, cycles) in lines {
+            writeln!(out, "{} {}", path, cycles)?;
+        }
+        Ok(())
+    }
+
+    fn name(&self, entry: u16) -> String {
+        match self.names.get(&entry) {
+            Some(name) => name.clone(),
+            None => format!("${:04X}", entry),
+        }
+    }
+
+    /// The names of the call path ending in `node`, separated by `;`.
+    fn path(&self, mut node: usize) -> String {
+        let mut names = Vec::new();
+        while node != 0 {
+            names.push(self.name(self.nodes[node].entry));
+            node = self.nodes[node].parent;
+        }
+        names.push(TOP.to_string());
+        names.reverse();
+        names.join(";")
+    }
+
+    fn charge(&mut self, cycles: usize) {
+        self.total += cycles;
+        self.nodes[self.node].cycles += cycles;
+        if let Some(top) = self.stack.last() {
+            if let Some(routine) = self.routines.get_mut(&top.entry) {
+                routine.exclusive += cycles;
+            }
+        }
+    }
+}
+
+impl Hook for Profiler {
+    fn after_instruction(&mut self, event: &InstructionEvent) {
+        let end = event.state.cycles();
+        let cycles = match self.last_cycles {
+            Some(last) => end.saturating_sub(last),
+            None => event.cycles,
+        };
+        self.last_cycles = Some(end);
+        self.charge(cycles);
+    }
+
+    fn enter_frame(&mut self, frame: &Frame) {
+        let entry = frame.entry;
+        self.routines
+            .entry(entry)
+            .or_insert(RoutineStats {
+                entry,
+                ..Default::default()
+            })
+            .calls += 1;
+
+        let next = self.nodes.len();
+        let parent = self.node;
+        self.node = *self.children.entry((parent, entry)).or_insert(next);
+        if self.node == next {
+            self.nodes.push(Node {
+                parent,
+                entry,
+                cycles: 0,
+            });
+        }
+
+        self.stack.push(Activation {
+            entry,
+            kind: frame.kind,
+            start: self.total,
+        });
+    }
+
+    fn leave_frame(&mut self, frame: &Frame) {
+        // frames entered before the profiler was added are not on its stack
+        match self.stack.last() {
+            Some(top) if top.entry == frame.entry && top.kind == frame.kind => {}
+            _ => return,
+        }
+        let activation = self.stack.pop().unwrap();
+        let cycles = self.total - activation.start;
+
+        // a recursive call is part of the outer one
+        if !self.stack.iter().any(|a| a.entry == activation.entry) {
+            if let Some(routine) = self.routines.get_mut(&activation.entry) {
+                routine.inclusive += cycles;
+            }
+        }
+        if activation.kind == FrameKind::Nmi {
+            self.nmi_cycles.push(cycles);
+        }
+        self.node = self.nodes[self.node].parent;
+    }
+}
+
+/// A table of the routines and a summary of the NMI handler.
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} cycles", self.total)?;
+        writeln!(
+            f,
+            "{:>8} {:>12} {:>12}  routine",
+            "calls", "inclusive", "exclusive"
+        )?;
+        for routine in self.routines() {
+            writeln!(
+                f,
+                "{:>8} {:>12} {:>12}  {}",
+                routine.calls,
+                routine.inclusive,
+                routine.exclusive,
+                self.name(routine.entry)
+            )?;
+        }
+
+        if !self.nmi_cycles.is_empty() {
+            let frames = self.nmi_cycles.len();
+            let max = self.nmi_cycles.iter().max().cloned().unwrap_or(0);
+            let average = self.nmi_cycles.iter().sum::<usize>() / frames;
+            writeln!(
+                f,
+                "NMI: {} frames, {} cycles average, {} max, {} over the vblank budget of {}",
+                frames,
+                average,
+                max,
+                self.nmi_over_budget(),
+                self.vblank_budget
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::asm;
+    use cpu::bus::Ram;
+    use cpu::cpu::{Variant, CPU};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn setup(source: &str) -> (CPU, Ram, asm::Program, Rc<RefCell<Profiler>>) {
+        let program = asm::assemble(Variant::Ricoh2A03, source).unwrap();
+        let mut mem = Ram::new();
+        program.load_into(&mut mem);
+
+        let mut cpu = CPU::new();
+        let mut state = cpu.state();
+        state.set_pc(program.symbol("start").unwrap());
+        state.set_sp(0xFD);
+        cpu.set_state(&state);
+        cpu.set_call_stack(true);
+
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        cpu.add_hook(Box::new(profiler.clone()));
+        (cpu, mem, program, profiler)
+    }
+
+    #[test]
+    fn inclusive_and_exclusive_cycles() {
+        let (mut cpu, mut mem, program, profiler) = setup(
+            "
+                    .org $8000
+            start:  jsr outer
+                    jsr inner
+                    nop
+            outer:  jsr inner
+                    rts
+            inner:  nop
+                    rts
+            ",
+        );
+        let outer = program.symbol("outer").unwrap();
+        let inner = program.symbol("inner").unwrap();
+        for _ in 0..9 {
+            cpu.step(&mut mem).unwrap();
+        }
+
+        let mut profiler = profiler.borrow_mut();
+        // three times each of JSR 6, NOP 2 and RTS 6
+        assert_eq!(profiler.total_cycles(), 3 * (6 + 2 + 6));
+        assert_eq!(
+            profiler.routine(inner),
+            Some(RoutineStats {
+                entry: inner,
+                calls: 2,
+                inclusive: 16,
+                exclusive: 16,
+            })
+        );
+        assert_eq!(
+            profiler.routine(outer),
+            Some(RoutineStats {
+                entry: outer,
+                calls: 1,
+                inclusive: 20,
+                exclusive: 12,
+            })
+        );
+        assert_eq!(profiler.routines()[0].entry, outer);
+
+        profiler.set_name(outer, "outer");
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(
+            String::from_utf8(folded).unwrap(),
+            format!(
+                "[top] 14\n[top];${:04X} 8\n[top];outer 12\n[top];outer;${:04X} 8\n",
+                inner, inner
+            )
+        );
+    }
+
+    #[test]
+    fn nmi_frames() {
+        let (mut cpu, mut mem, _, profiler) = setup(
+            "
+                    .org $8000
+            start:  jmp start
+            nmi:    ldx #200
+            @loop:  dex
+                    bne @loop
+                    rti
+                    .org $FFFA
+                    .word nmi
+            ",
+        );
+        profiler.borrow_mut().set_vblank_budget(1000);
+
+        for _ in 0..3 {
+            cpu.set_nmi(true);
+            cpu.run_for(&mut mem, 1500).unwrap();
+            cpu.set_nmi(false);
+        }
+
+        let profiler = profiler.borrow();
+        // interrupt 7, LDX 2, 199 taken and one untaken branch with DEX
+        let handler = 7 + 2 + 199 * 5 + 4;
+        assert_eq!(profiler.nmi_frames(), &[handler, handler, handler]);
+        assert_eq!(profiler.nmi_over_budget(), 3);
+        assert!(profiler.to_string().contains(
+            "NMI: 3 frames, 1008 cycles average, 1008 max, 3 over the vblank budget of 1000"
+        ));
+    }
+}
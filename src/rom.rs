@@ -1,38 +1,80 @@
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
-use std::str;
 
 /// Limit the amount of data to read. In case a user accidentally wants to read
 /// a very large file as a ROM.
 const MAX_ROM_SIZE: u64 = 5 * 1024 * 1024;
 
-pub enum MirroringType {
-    Nothing,
-}
+const INES_HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+
+/// Size of a PRG ROM bank in ines files.
+pub const PRG_BANK_SIZE: usize = 0x4000;
 
-/// Structure representing a rom/cartridge of the NES system.
+/// Size of a CHR ROM bank in ines files.
+pub const CHR_BANK_SIZE: usize = 0x2000;
+
+/// The ROMs of a cartridge.
 pub struct Rom {
     prg: Vec<u8>,
     chr: Vec<u8>,
-    mirroring_type: MirroringType,
+}
+
+impl Rom {
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    /// The CHR ROM, empty if the cartridge has CHR RAM.
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
 }
 
 /// Information contained in the header of ines files.
 pub struct InesHeader {
     prg_banks: usize, // program rom banks, each 16 kB
     chr_banks: usize, // character rom banks, each 8 kB
-    ram_banks: usize, // number of 8 kB ROM banks
     control_1: u8,
-    control_2: u8,
+}
+
+impl InesHeader {
+    pub fn prg_banks(&self) -> usize {
+        self.prg_banks
+    }
+
+    pub fn chr_banks(&self) -> usize {
+        self.chr_banks
+    }
+
+    /// A 512 byte trainer is stored between the header and the PRG ROM.
+    pub fn has_trainer(&self) -> bool {
+        self.control_1 & 0x04 != 0
+    }
+
+    /// Offset of the PRG ROM in the file.
+    pub fn prg_offset(&self) -> usize {
+        INES_HEADER_SIZE + if self.has_trainer() { TRAINER_SIZE } else { 0 }
+    }
 }
 
 /// Create a Rom from bytes in the Ines format
-fn from_ines(b: Vec<u8>) -> Result<Rom, ParseError> {
+pub fn from_ines(b: &[u8]) -> Result<Rom, ParseError> {
+    let header = parse_ines_header(b)?;
+    let prg_start = header.prg_offset();
+    let chr_start = prg_start + header.prg_banks * PRG_BANK_SIZE;
+    let chr_end = chr_start + header.chr_banks * CHR_BANK_SIZE;
+    if b.len() < chr_end {
+        return Err(ParseError::new(
+            format!("expected {} bytes but the file has {}", chr_end, b.len()),
+            ParseErrorKind::SizeError,
+        ));
+    }
+
     Ok(Rom {
-        prg: Vec::new(),
-        chr: Vec::new(),
-        mirroring_type: MirroringType::Nothing,
+        prg: b[prg_start..chr_start].to_vec(),
+        chr: b[chr_start..chr_end].to_vec(),
     })
 }
 
@@ -43,7 +85,7 @@ where
     let f = File::open(fp).unwrap();
     let mut bytes: Vec<u8> = Vec::new();
 
-    let file_size = f.take(MAX_ROM_SIZE).read_to_end(&mut bytes).unwrap();
+    f.take(MAX_ROM_SIZE).read_to_end(&mut bytes).unwrap();
 
     bytes
 }
@@ -63,16 +105,18 @@ impl ParseError {
 #[derive(Debug)]
 pub enum ParseErrorKind {
     HeaderError,
+    /// the file is shorter than the header says
+    SizeError,
 }
 
-pub fn parse_ines(b: &Vec<u8>) -> Result<(), ParseError> {
+pub fn parse_ines(b: &[u8]) -> Result<(), ParseError> {
     parse_ines_header(b)?;
 
     Ok(())
 }
 
-pub fn parse_ines_header(b: &Vec<u8>) -> Result<InesHeader, ParseError> {
-    if str::from_utf8(&b[0..3]).unwrap() != "NES" {
+pub fn parse_ines_header(b: &[u8]) -> Result<InesHeader, ParseError> {
+    if b.len() < INES_HEADER_SIZE || &b[0..3] != b"NES" {
         return Err(ParseError::new(
             String::from("could not find NES"),
             ParseErrorKind::HeaderError,
@@ -81,7 +125,7 @@ pub fn parse_ines_header(b: &Vec<u8>) -> Result<InesHeader, ParseError> {
 
     if b[3] != 0x1A {
         return Err(ParseError::new(
-            String::from(format!("4th byte is not 0x1A but {}", b[3])),
+            format!("4th byte is not 0x1A but {}", b[3]),
             ParseErrorKind::HeaderError,
         ));
     }
@@ -90,7 +134,36 @@ pub fn parse_ines_header(b: &Vec<u8>) -> Result<InesHeader, ParseError> {
         prg_banks: b[4] as usize,
         chr_banks: b[5] as usize,
         control_1: b[6],
-        control_2: b[7],
-        ram_banks: b[8] as usize,
     })
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ines(control_1: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+        let mut b = vec![b'N', b'E', b'S', 0x1A, prg_banks, chr_banks, control_1];
+        b.resize(INES_HEADER_SIZE, 0);
+        if control_1 & 0x04 != 0 {
+            b.resize(INES_HEADER_SIZE + TRAINER_SIZE, 0xEE);
+        }
+        b.extend(vec![0x11; prg_banks as usize * PRG_BANK_SIZE]);
+        b.extend(vec![0x22; chr_banks as usize * CHR_BANK_SIZE]);
+        b
+    }
+
+    #[test]
+    fn rom_from_ines() {
+        let rom = from_ines(&ines(0x04, 2, 1)).unwrap();
+        assert_eq!(rom.prg().len(), 2 * PRG_BANK_SIZE);
+        assert!(rom.prg().iter().all(|&b| b == 0x11));
+        assert_eq!(rom.chr().len(), CHR_BANK_SIZE);
+        assert!(rom.chr().iter().all(|&b| b == 0x22));
+
+        let mut truncated = ines(0x00, 1, 1);
+        truncated.pop();
+        assert!(from_ines(&truncated).is_err());
+        assert!(from_ines(b"NES").is_err());
+    }
+}